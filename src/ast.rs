@@ -1,49 +1,153 @@
+use crate::span::Span;
 use crate::symbol_table::Type;
 
+/// An AST node together with the region of source it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: ASTNode,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(kind: ASTNode, span: Span) -> Self {
+        Node { kind, span }
+    }
+}
+
+/// A typed function parameter, e.g. `a int`.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub param_type: Type,
+    pub span: Span,
+}
+
+/// A method an interface requires, e.g. `func update(dt float)`.
+#[derive(Debug, Clone)]
+pub struct MethodSignature {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub return_type: Type,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub enum ASTNode {
-    Program(Vec<ASTNode>),
-    ClassDeclaration { name: String, members: Vec<ASTNode> },
-    FunctionDeclaration { name: String, params: Vec<String>, body: Vec<ASTNode> },
+    Program(Vec<Node>),
+    /// A class's members are its fields (`var`s) and methods (`func`s). A method
+    /// called `init` is run by the constructor. A class that `extends` a base
+    /// class inherits its fields and methods, and can override the methods.
+    /// It must have every method of the interfaces it `implements`.
+    ClassDeclaration { name: String, base: Option<String>, interfaces: Vec<String>, members: Vec<Node> },
+    /// The methods a class has to have to implement the interface. Interfaces
+    /// can be used as types, holding instances of any implementing class.
+    InterfaceDeclaration { name: String, methods: Vec<MethodSignature> },
+    /// A function without a declared return type returns `void`.
+    FunctionDeclaration { name: String, params: Vec<Parameter>, return_type: Type, body: Vec<Node> },
     VariableDeclaration { name: String, var_type: Type },
-    Assignment { variable: String, expression: Box<ASTNode> },
+    Assignment { variable: String, expression: Box<Node> },
     Number(i64),
     Float(f64),
     StringLiteral(String),
+    /// A string with embedded expressions, e.g. `"HP: {hp}"`: the literal
+    /// text as `StringLiteral`s in between the expressions.
+    Interpolation(Vec<Node>),
     Boolean(bool),
+    /// `[a, b, c]`
+    ArrayLiteral(Vec<Node>),
     Identifier(String),
     FunctionCall {
         name: String,
-        args: Vec<ASTNode>,
+        args: Vec<Node>,
+    },
+    /// `Class.new(args)`, creating an instance and passing `args` to its `init`
+    /// method. `new` can't be the name of a method, so this is never a `MethodCall`.
+    New {
+        class: String,
+        args: Vec<Node>,
+    },
+    /// `self` inside a method.
+    SelfRef,
+    /// `object.field`
+    MemberAccess {
+        object: Box<Node>,
+        member: String,
+    },
+    /// `object.field = expression`
+    FieldAssignment {
+        object: Box<Node>,
+        field: String,
+        expression: Box<Node>,
+    },
+    /// `object.method(args)`
+    MethodCall {
+        object: Box<Node>,
+        method: String,
+        args: Vec<Node>,
+    },
+    /// `array[index]`
+    Index {
+        object: Box<Node>,
+        index: Box<Node>,
+    },
+    /// `array[index] = expression`
+    IndexAssignment {
+        object: Box<Node>,
+        index: Box<Node>,
+        expression: Box<Node>,
+    },
+    /// `super.method(args)`, calling the base class's version of a method on `self`.
+    SuperCall {
+        method: String,
+        args: Vec<Node>,
     },
     Arithmetic {
-        left: Box<ASTNode>,
+        left: Box<Node>,
         operator: String,
-        right: Box<ASTNode>,
+        right: Box<Node>,
     },
     Comparison {
-        left: Box<ASTNode>,
+        left: Box<Node>,
         operator: String,
-        right: Box<ASTNode>,
+        right: Box<Node>,
+    },
+    /// `and` / `or`, evaluated with short-circuiting.
+    Logical {
+        left: Box<Node>,
+        operator: String,
+        right: Box<Node>,
+    },
+    Unary {
+        operator: String,
+        operand: Box<Node>,
     },
     IfElse {
-        condition: Box<ASTNode>,
-        then_block: Vec<ASTNode>,
-        else_block: Option<Vec<ASTNode>>,
+        condition: Box<Node>,
+        then_block: Vec<Node>,
+        else_block: Option<Vec<Node>>,
     },
+    /// `ret` without a value is only valid in `void` functions.
     Ret {
-        expression: Box<ASTNode>,
+        expression: Option<Box<Node>>,
     },
-    Loop { body: Vec<ASTNode> },
+    Loop { body: Vec<Node> },
     ForLoop {
         variable: String,
-        start: Box<ASTNode>,
-        end: Box<ASTNode>,
-        body: Vec<ASTNode>,
+        start: Box<Node>,
+        end: Box<Node>,
+        body: Vec<Node>,
+    },
+    /// `for item in array`, running the body for each element in turn.
+    ForEach {
+        variable: String,
+        array: Box<Node>,
+        body: Vec<Node>,
     },
     WhileLoop {
-        condition: Box<ASTNode>,
-        body: Vec<ASTNode>,
+        condition: Box<Node>,
+        body: Vec<Node>,
     },
+    /// Placeholder for a statement that failed to parse.
+    Error,
 }
 
@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::native::Natives;
+use crate::symbol_table::{FunctionSignature, Type};
+use crate::value::{Array, Value};
+
+type Sink = dyn FnMut(&str);
+
+/// Where `print` and `println` write. Cloning shares the same sink, so a host
+/// can redirect script output to stdout, a buffer or an in-game console.
+#[derive(Clone)]
+pub struct Output(Rc<RefCell<Sink>>);
+
+impl Output {
+    pub fn new(sink: impl FnMut(&str) + 'static) -> Self {
+        Output(Rc::new(RefCell::new(sink)))
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(|text| {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(text.as_bytes());
+            let _ = stdout.flush();
+        })
+    }
+
+    /// An output that collects everything written to it in the returned buffer.
+    pub fn buffer() -> (Self, Rc<RefCell<String>>) {
+        let buffer = Rc::new(RefCell::new(String::new()));
+        let sink = Rc::clone(&buffer);
+        (Self::new(move |text| sink.borrow_mut().push_str(text)), buffer)
+    }
+
+    pub fn write(&self, text: &str) {
+        (self.0.borrow_mut())(text);
+    }
+}
+
+/// The builtins that can be called as methods of a string, `s.split(",")`
+/// meaning `split(s, ",")`.
+pub const STRING_METHODS: [&str; 7] = ["len", "substring", "contains", "split", "upper", "lower", "trim"];
+
+/// The functions every program can call, with `print` and `println` writing to `output`:
+///
+/// - `print(value)` and `println(value)` write a value, the latter followed by a newline
+/// - `to_string(value) string` formats a value the way `print` does
+/// - `len(s string) int` counts the characters in a string, and `len(a array<T>) int`
+///   the elements of an array
+/// - `substring(s string, start int, end int) string` takes the characters
+///   from `start` up to but not including `end`
+/// - `contains(s string, part string) bool`
+/// - `upper(s string) string`, `lower(s string) string` and `trim(s string) string`
+///   change case and strip surrounding whitespace
+/// - `format(template string, args...) string` replaces each `{}` in the
+///   template with the next argument; `{{` and `}}` stand for literal braces,
+///   so a template with them has to be a raw string, in which the lexer
+///   leaves them alone
+/// - `split(s string, separator string) array<string>` cuts a string at each separator
+/// - `push(a array<T>, value T)` appends to an array and `pop(a array<T>) T`
+///   removes and returns its last element
+/// - `insert(a array<T>, index int, value T)` inserts before `index`, which
+///   may be the length to append, and `remove(a array<T>, index int) T`
+///   removes and returns the element at `index`
+///
+/// The array builtins are declared with `array<any>` and `any` parameters;
+/// the type checker works out the real types from the array passed in. The
+/// string builtins in [`STRING_METHODS`] can also be called as methods.
+pub fn natives(output: Output) -> Natives {
+    let mut natives = Natives::new();
+    let any = |return_type: Type| FunctionSignature { params: vec![Type::Any], return_type, variadic: false };
+
+    let out = output.clone();
+    natives.register_raw("print", any(Type::Void), move |args| {
+        out.write(&args[0].to_string());
+        Ok(Value::Void)
+    });
+    natives.register_raw("println", any(Type::Void), move |args| {
+        output.write(&format!("{}\n", args[0]));
+        Ok(Value::Void)
+    });
+    natives.register_raw("to_string", any(Type::String), |args| Ok(Value::String(args[0].to_string())));
+    natives.register_raw("len", any(Type::Int), |args| match &args[0] {
+        Value::String(s) => Ok(Value::Int(s.chars().count() as i64)),
+        Value::Array(array) => Ok(Value::Int(array.len() as i64)),
+        value => Err(format!("expected a string or an array, got {:?}", value)),
+    });
+    natives.register_fn("substring", substring);
+    natives.register_fn("contains", |s: String, part: String| s.contains(&part));
+    natives.register_fn("upper", |s: String| s.to_uppercase());
+    natives.register_fn("lower", |s: String| s.to_lowercase());
+    natives.register_fn("trim", |s: String| s.trim().to_string());
+    natives.register_raw(
+        "format",
+        FunctionSignature { params: vec![Type::String, Type::Any], return_type: Type::String, variadic: true },
+        |args| match &args[0] {
+            Value::String(template) => format(template, &args[1..]).map(Value::String),
+            _ => unreachable!("the template was checked to be a string"),
+        },
+    );
+    natives.register_raw(
+        "split",
+        FunctionSignature {
+            params: vec![Type::String, Type::String],
+            return_type: Type::Array(Box::new(Type::String)),
+            variadic: false,
+        },
+        |args| match (&args[0], &args[1]) {
+            (Value::String(s), Value::String(separator)) => split(s, separator),
+            _ => unreachable!("the arguments were checked to be strings"),
+        },
+    );
+
+    let array = || Type::Array(Box::new(Type::Any));
+    let signature = |params: Vec<Type>, return_type: Type| FunctionSignature { params, return_type, variadic: false };
+    natives.register_raw("push", signature(vec![array(), Type::Any], Type::Void), |args| {
+        array_arg(args).push(args[1].clone());
+        Ok(Value::Void)
+    });
+    natives.register_raw("pop", signature(vec![array()], Type::Any), |args| {
+        array_arg(args).pop().ok_or_else(|| "cannot pop from an empty array".to_string())
+    });
+    natives.register_raw("insert", signature(vec![array(), Type::Int, Type::Any], Type::Void), |args| {
+        let array = array_arg(args);
+        let index = int_arg(args, 1);
+        // Inserting at the length appends.
+        match usize::try_from(index) {
+            Ok(position) if array.insert(position, args[2].clone()) => Ok(Value::Void),
+            _ => Err(out_of_bounds(index, array.len())),
+        }
+    });
+    natives.register_raw("remove", signature(vec![array(), Type::Int], Type::Any), |args| {
+        let array = array_arg(args);
+        let index = int_arg(args, 1);
+        usize::try_from(index)
+            .ok()
+            .and_then(|position| array.remove(position))
+            .ok_or_else(|| out_of_bounds(index, array.len()))
+    });
+    natives
+}
+
+fn array_arg(args: &[Value]) -> &Array {
+    match &args[0] {
+        Value::Array(array) => array,
+        _ => unreachable!("the first argument was checked to be an array"),
+    }
+}
+
+fn int_arg(args: &[Value], index: usize) -> i64 {
+    match args[index] {
+        Value::Int(n) => n,
+        _ => unreachable!("the argument was checked to be an int"),
+    }
+}
+
+fn out_of_bounds(index: i64, len: usize) -> String {
+    format!("index {} is out of bounds for an array of length {}", index, len)
+}
+
+fn split(s: &str, separator: &str) -> Result<Value, String> {
+    if separator.is_empty() {
+        return Err("the separator can't be empty".to_string());
+    }
+    let parts = s.split(separator).map(|part| Value::String(part.to_string())).collect();
+    Ok(Value::Array(Array::new(parts)))
+}
+
+/// Indexes by character rather than byte, so it can't split a character in two.
+fn substring(s: String, start: i64, end: i64) -> Result<String, String> {
+    let len = s.chars().count() as i64;
+    if start < 0 || end < start || end > len {
+        return Err(format!(
+            "range {}..{} is out of bounds for a string of length {}",
+            start, end, len
+        ));
+    }
+    Ok(s.chars().skip(start as usize).take((end - start) as usize).collect())
+}
+
+/// Counts the `{}` placeholders in a `format` template, or describes why it is invalid.
+pub fn count_placeholders(template: &str) -> Result<usize, String> {
+    let mut count = 0;
+    expand(template, |piece| {
+        if piece.is_none() {
+            count += 1;
+        }
+    })?;
+    Ok(count)
+}
+
+/// Fills in a `format` template.
+pub fn format(template: &str, args: &[Value]) -> Result<String, String> {
+    let expected = count_placeholders(template)?;
+    if expected != args.len() {
+        return Err(placeholder_mismatch(expected, args.len()));
+    }
+    let mut result = String::new();
+    let mut args = args.iter();
+    expand(template, |piece| match piece {
+        Some(text) => result.push_str(text),
+        None => result.push_str(&args.next().expect("placeholders were counted").to_string()),
+    })?;
+    Ok(result)
+}
+
+pub(crate) fn placeholder_mismatch(expected: usize, given: usize) -> String {
+    format!(
+        "format string has {} placeholder{} but {} argument{} given",
+        expected,
+        if expected == 1 { "" } else { "s" },
+        given,
+        if given == 1 { " was" } else { "s were" }
+    )
+}
+
+/// Splits a template into literal text (`Some`) and placeholders (`None`).
+fn expand<'a>(template: &'a str, mut piece: impl FnMut(Option<&'a str>)) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        piece(Some(&rest[..at]));
+        match (&rest[at..at + 1], rest[at + 1..].chars().next()) {
+            ("{", Some('}')) => piece(None),
+            ("{", Some('{')) => piece(Some("{")),
+            ("}", Some('}')) => piece(Some("}")),
+            ("{", _) => return Err("unmatched '{' in format string".to_string()),
+            _ => return Err("unmatched '}' in format string".to_string()),
+        }
+        rest = &rest[at + 2..];
+    }
+    piece(Some(rest));
+    Ok(())
+}
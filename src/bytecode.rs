@@ -0,0 +1,130 @@
+use crate::span::Span;
+use crate::value::{ArithmeticOp, ComparisonOp, Value};
+
+/// The condition a conditional jump is testing, so a non-bool value can be
+/// reported the same way the interpreter reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    If,
+    While,
+    And,
+    Or,
+}
+
+impl Condition {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Condition::If => "'if' condition",
+            Condition::While => "'while' condition",
+            Condition::And => "operand of 'and'",
+            Condition::Or => "operand of 'or'",
+        }
+    }
+}
+
+/// A single VM instruction. Operands index into the program's constant pool,
+/// global, function, native or class table, the current frame's local slots,
+/// an object's fields, or (for jumps) the current function's code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Pushes a constant.
+    Constant(u16),
+    /// Discards the top of the stack.
+    Pop,
+    /// Pushes a copy of the top of the stack.
+    Dup,
+    GetLocal(u16),
+    /// Pops a value into a local slot.
+    SetLocal(u16),
+    /// Pushes a global, failing if it has not been declared yet.
+    GetGlobal(u16),
+    /// Pops a value into an already declared global.
+    SetGlobal(u16),
+    /// Pops a value into a global, declaring it if needed.
+    DefineGlobal(u16),
+    Arithmetic(ArithmeticOp),
+    Compare(ComparisonOp),
+    Negate,
+    Not,
+    /// Pops this many values and pushes them joined into a string, formatted like `to_string`.
+    BuildString(u16),
+    /// Pops this many values and pushes a new array holding them, in order.
+    BuildArray(u16),
+    /// Pops an index and then an array, and pushes the element at that index.
+    GetIndex,
+    /// Pops an index, an array and then a value, and stores the value at that index.
+    SetIndex,
+    /// Pops an array and pushes its length, failing as a `for` loop would if it isn't an array.
+    Length,
+    /// Fails unless the top of the stack is a bool, leaving it in place.
+    CheckBool(Condition),
+    /// Fails unless the top two values are ints, leaving them in place.
+    CheckRange,
+    Jump(u32),
+    /// Pops a bool and jumps if it is false.
+    JumpIfFalse(u32, Condition),
+    /// Calls a function with the given number of arguments from the top of the stack.
+    Call(u16, u8),
+    /// Calls a native function by its index in [`Program::natives`], like `Call`.
+    CallNative(u16, u8),
+    /// Pushes a new instance of a class in [`Program::classes`], with its array
+    /// fields set to empty arrays and every other field void.
+    New(u16),
+    /// Pops an object and pushes the value of its field.
+    GetField(u16),
+    /// Pops an object and then a value, and stores the value in the object's field.
+    SetField(u16),
+    /// Like `GetField`, but the field is found by the name in the given
+    /// string constant, for objects whose class is only known at runtime.
+    GetMember(u16),
+    /// Like `SetField`, with the field found by name like `GetMember`.
+    SetMember(u16),
+    /// Calls the method named by a string constant on the object below the
+    /// given number of arguments, passing the object as the first argument.
+    Invoke(u16, u8),
+    /// Pops the return value, discards the current frame and pushes the value for the caller.
+    Return,
+}
+
+/// A compiled function. `spans[i]` is the source of `code[i]`, used for errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    /// Number of local slots, including the parameters.
+    pub locals: u16,
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,
+}
+
+/// A class's layout and methods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: String,
+    /// The field names, indexed by `GetField`/`SetField`.
+    pub fields: Vec<String>,
+    /// The fields holding arrays, which start out as empty arrays rather than void.
+    pub arrays: Vec<u16>,
+    /// Each method's name and the function it is compiled to, which takes
+    /// the instance as an extra first argument.
+    pub methods: Vec<(String, u16)>,
+}
+
+/// A whole compiled program. Function 0 is the top-level code, which runs
+/// before the entry point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub constants: Vec<Value>,
+    /// Names of the globals, indexed by `GetGlobal`/`SetGlobal`/`DefineGlobal`.
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+    /// Names of the native functions the program calls, bound to the host's
+    /// registered natives when the VM is created.
+    pub natives: Vec<String>,
+    pub classes: Vec<Class>,
+    /// The `run` function, if the program has one.
+    pub entry: Option<u16>,
+}
+
+/// Index of the top-level code in [`Program::functions`].
+pub const SCRIPT: u16 = 0;
@@ -0,0 +1,623 @@
+//! The `.nbc` file format for compiled programs.
+//!
+//! All integers are little-endian. A file is laid out as:
+//!
+//! - header: the magic bytes `NTRN` and a `u16` format version
+//! - constant pool: `u32` count, then each constant as a tag byte and payload
+//! - globals: `u32` count, then each name
+//! - natives: `u32` count, then the name of each native function called
+//! - classes: `u32` count, then each class's name, its field names (`u32`
+//!   count, then each name), the fields holding arrays (`u32` count, then
+//!   each `u16` field index) and its methods (`u32` count, then each name and
+//!   `u16` function index)
+//! - function table: `u32` count, then each function's name, arity (`u8`),
+//!   local count (`u16`) and code (`u32` count, then opcode bytes and operands)
+//! - entry point: a `u8` flag followed by a `u16` function index if set
+//! - debug line table: for each function, a `u32` count of runs, each run
+//!   being the first instruction it covers (`u32`) and a span (four `u32`s)
+//!
+//! Strings are a `u32` byte length followed by UTF-8.
+
+use std::fmt;
+
+use crate::bytecode::{Class, Condition, Function, Instruction, Program};
+use crate::span::Span;
+use crate::value::{ArithmeticOp, ComparisonOp, Value};
+
+pub const MAGIC: &[u8; 4] = b"NTRN";
+/// Bumped whenever the layout or instruction encoding changes, including
+/// reordering the operator and condition enums, whose discriminants are written as-is.
+pub const VERSION: u16 = 7;
+
+/// Why a bytecode file could not be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    Truncated,
+    Malformed(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NotBytecode => write!(f, "not a Neutron bytecode file"),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "bytecode format version {} is not supported (expected {})",
+                version, VERSION
+            ),
+            LoadError::Truncated => write!(f, "bytecode file is truncated"),
+            LoadError::Malformed(reason) => write!(f, "malformed bytecode file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Returns whether `bytes` start like a bytecode file.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn write(program: &Program) -> Vec<u8> {
+    let mut out = Writer { bytes: Vec::new() };
+    out.bytes.extend_from_slice(MAGIC);
+    out.u16(VERSION);
+
+    out.len(program.constants.len());
+    for constant in &program.constants {
+        match constant {
+            Value::Int(n) => {
+                out.u8(0);
+                out.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Float(f) => {
+                out.u8(1);
+                out.bytes.extend_from_slice(&f.to_bits().to_le_bytes());
+            }
+            Value::Bool(b) => {
+                out.u8(2);
+                out.u8(*b as u8);
+            }
+            Value::String(s) => {
+                out.u8(3);
+                out.string(s);
+            }
+            Value::Void => out.u8(4),
+            Value::Object(_) | Value::Array(_) | Value::Return(_) => {
+                unreachable!("the compiler never emits objects, arrays or return values as constants")
+            }
+        }
+    }
+
+    out.len(program.globals.len());
+    for global in &program.globals {
+        out.string(global);
+    }
+
+    out.len(program.natives.len());
+    for native in &program.natives {
+        out.string(native);
+    }
+
+    out.len(program.classes.len());
+    for class in &program.classes {
+        out.string(&class.name);
+        out.len(class.fields.len());
+        for field in &class.fields {
+            out.string(field);
+        }
+        out.len(class.arrays.len());
+        for field in &class.arrays {
+            out.u16(*field);
+        }
+        out.len(class.methods.len());
+        for (method, function) in &class.methods {
+            out.string(method);
+            out.u16(*function);
+        }
+    }
+
+    out.len(program.functions.len());
+    for function in &program.functions {
+        out.string(&function.name);
+        out.u8(function.arity);
+        out.u16(function.locals);
+        out.len(function.code.len());
+        for instruction in &function.code {
+            out.instruction(*instruction);
+        }
+    }
+
+    match program.entry {
+        Some(entry) => {
+            out.u8(1);
+            out.u16(entry);
+        }
+        None => out.u8(0),
+    }
+
+    for function in &program.functions {
+        let runs: Vec<(usize, &Span)> = function
+            .spans
+            .iter()
+            .enumerate()
+            .filter(|(i, span)| *i == 0 || function.spans[i - 1] != **span)
+            .collect();
+        out.len(runs.len());
+        for (first, span) in runs {
+            out.len(first);
+            for part in [span.start, span.end, span.line, span.column] {
+                out.len(part);
+            }
+        }
+    }
+    out.bytes
+}
+
+pub fn read(bytes: &[u8]) -> Result<Program, LoadError> {
+    if !is_bytecode(bytes) {
+        return Err(LoadError::NotBytecode);
+    }
+    let mut input = Reader { bytes, position: MAGIC.len() };
+    let version = input.u16()?;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let mut constants = Vec::new();
+    for _ in 0..input.u32()? {
+        constants.push(match input.u8()? {
+            0 => Value::Int(i64::from_le_bytes(input.array()?)),
+            1 => Value::Float(f64::from_bits(u64::from_le_bytes(input.array()?))),
+            2 => Value::Bool(input.u8()? != 0),
+            3 => Value::String(input.string()?),
+            4 => Value::Void,
+            tag => return Err(malformed(format!("unknown constant tag {}", tag))),
+        });
+    }
+
+    let mut globals = Vec::new();
+    for _ in 0..input.u32()? {
+        globals.push(input.string()?);
+    }
+
+    let mut natives = Vec::new();
+    for _ in 0..input.u32()? {
+        natives.push(input.string()?);
+    }
+
+    let mut classes = Vec::new();
+    for _ in 0..input.u32()? {
+        let name = input.string()?;
+        let mut fields = Vec::new();
+        for _ in 0..input.u32()? {
+            fields.push(input.string()?);
+        }
+        let mut arrays = Vec::new();
+        for _ in 0..input.u32()? {
+            arrays.push(input.u16()?);
+        }
+        let mut methods = Vec::new();
+        for _ in 0..input.u32()? {
+            methods.push((input.string()?, input.u16()?));
+        }
+        classes.push(Class { name, fields, arrays, methods });
+    }
+
+    let mut functions = Vec::new();
+    for _ in 0..input.u32()? {
+        let name = input.string()?;
+        let arity = input.u8()?;
+        let locals = input.u16()?;
+        let mut code = Vec::new();
+        for _ in 0..input.u32()? {
+            code.push(input.instruction()?);
+        }
+        functions.push(Function { name, arity, locals, code, spans: Vec::new() });
+    }
+
+    let entry = match input.u8()? {
+        0 => None,
+        _ => Some(input.u16()?),
+    };
+
+    for function in &mut functions {
+        let mut runs = Vec::new();
+        for _ in 0..input.u32()? {
+            let first = input.u32()? as usize;
+            let [start, end, line, column] = [input.u32()?, input.u32()?, input.u32()?, input.u32()?];
+            runs.push((first, Span::new(start as usize, end as usize, line as usize, column as usize)));
+        }
+        for (index, (first, span)) in runs.iter().enumerate() {
+            let next = runs.get(index + 1).map_or(function.code.len(), |(next, _)| *next);
+            if *first != function.spans.len() || next < *first || next > function.code.len() {
+                return Err(malformed(format!("bad debug table for '{}'", function.name)));
+            }
+            function.spans.resize(next, *span);
+        }
+        if function.spans.len() != function.code.len() {
+            return Err(malformed(format!("bad debug table for '{}'", function.name)));
+        }
+    }
+
+    if input.position != bytes.len() {
+        return Err(malformed("trailing bytes after the debug table".to_string()));
+    }
+
+    let program = Program { constants, globals, functions, natives, classes, entry };
+    validate(&program)?;
+    Ok(program)
+}
+
+fn malformed(reason: String) -> LoadError {
+    LoadError::Malformed(reason)
+}
+
+/// Checks every operand refers to something that exists and that no code
+/// can pop more than it pushed, so the VM can index its tables and pop its
+/// stack without checks. What the values on the stack are is still checked
+/// as the program runs.
+fn validate(program: &Program) -> Result<(), LoadError> {
+    if program.functions.is_empty() {
+        return Err(malformed("missing top-level code".to_string()));
+    }
+    if program.entry.is_some_and(|entry| entry as usize >= program.functions.len()) {
+        return Err(malformed("entry point out of range".to_string()));
+    }
+    for class in &program.classes {
+        if class.methods.iter().any(|(_, function)| *function as usize >= program.functions.len()) {
+            return Err(malformed(format!("a method of '{}' is out of range", class.name)));
+        }
+        if class.arrays.iter().any(|field| *field as usize >= class.fields.len()) {
+            return Err(malformed(format!("an array field of '{}' is out of range", class.name)));
+        }
+    }
+    let is_name = |index: u16| matches!(program.constants.get(index as usize), Some(Value::String(_)));
+    for function in &program.functions {
+        if function.arity as usize > function.locals as usize {
+            return Err(malformed(format!("'{}' has fewer locals than parameters", function.name)));
+        }
+        if function.code.last() != Some(&Instruction::Return) {
+            return Err(malformed(format!("'{}' does not end with a return", function.name)));
+        }
+        for instruction in &function.code {
+            let in_range = match *instruction {
+                Instruction::Constant(index) => (index as usize) < program.constants.len(),
+                Instruction::GetLocal(slot) | Instruction::SetLocal(slot) => slot < function.locals,
+                Instruction::GetGlobal(index)
+                | Instruction::SetGlobal(index)
+                | Instruction::DefineGlobal(index) => (index as usize) < program.globals.len(),
+                Instruction::Jump(target) | Instruction::JumpIfFalse(target, _) => {
+                    (target as usize) < function.code.len()
+                }
+                Instruction::Call(index, _) => (index as usize) < program.functions.len(),
+                Instruction::CallNative(index, _) => (index as usize) < program.natives.len(),
+                Instruction::New(index) => (index as usize) < program.classes.len(),
+                Instruction::GetMember(name) | Instruction::SetMember(name) => is_name(name),
+                // The receiver is passed along with the arguments, so there is one more of them.
+                Instruction::Invoke(name, argc) => is_name(name) && argc < u8::MAX,
+                // Which class an object belongs to is only known at runtime, so the VM checks field indices.
+                _ => true,
+            };
+            if !in_range {
+                return Err(malformed(format!(
+                    "operand of {:?} in '{}' is out of range",
+                    instruction, function.name
+                )));
+            }
+        }
+        check_stack(function)?;
+    }
+    Ok(())
+}
+
+/// Follows every path through `function`, checking the stack never holds
+/// fewer values than an instruction pops and holds the same number whichever
+/// way an instruction is reached. Jump targets were already checked.
+fn check_stack(function: &Function) -> Result<(), LoadError> {
+    let mut depths: Vec<Option<usize>> = vec![None; function.code.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((at, depth)) = pending.pop() {
+        match depths[at] {
+            Some(known) if known == depth => continue,
+            Some(known) => {
+                return Err(malformed(format!(
+                    "stack depth at {} in '{}' is both {} and {}",
+                    at, function.name, known, depth
+                )))
+            }
+            None => depths[at] = Some(depth),
+        }
+        let instruction = function.code[at];
+        let (pops, pushes) = stack_effect(instruction);
+        let Some(depth) = depth.checked_sub(pops) else {
+            return Err(malformed(format!(
+                "{:?} at {} in '{}' pops more values than the stack holds",
+                instruction, at, function.name
+            )));
+        };
+        let depth = depth + pushes;
+        match instruction {
+            Instruction::Return => {}
+            Instruction::Jump(target) => pending.push((target as usize, depth)),
+            Instruction::JumpIfFalse(target, _) => {
+                pending.push((target as usize, depth));
+                pending.push((at + 1, depth));
+            }
+            // Functions end with a return, so this is never past the end.
+            _ => pending.push((at + 1, depth)),
+        }
+    }
+    Ok(())
+}
+
+/// How many values an instruction pops, and then how many it pushes.
+fn stack_effect(instruction: Instruction) -> (usize, usize) {
+    match instruction {
+        Instruction::Constant(_)
+        | Instruction::GetLocal(_)
+        | Instruction::GetGlobal(_)
+        | Instruction::New(_) => (0, 1),
+        Instruction::Pop
+        | Instruction::SetLocal(_)
+        | Instruction::SetGlobal(_)
+        | Instruction::DefineGlobal(_)
+        | Instruction::JumpIfFalse(..)
+        | Instruction::Return => (1, 0),
+        Instruction::Dup => (1, 2),
+        Instruction::Arithmetic(_) | Instruction::Compare(_) | Instruction::GetIndex => (2, 1),
+        Instruction::Negate
+        | Instruction::Not
+        | Instruction::CheckBool(_)
+        | Instruction::Length
+        | Instruction::GetField(_)
+        | Instruction::GetMember(_) => (1, 1),
+        Instruction::BuildString(count) | Instruction::BuildArray(count) => (count as usize, 1),
+        Instruction::CheckRange => (2, 2),
+        Instruction::Jump(_) => (0, 0),
+        Instruction::Call(_, argc) | Instruction::CallNative(_, argc) => (argc as usize, 1),
+        Instruction::Invoke(_, argc) => (argc as usize + 1, 1),
+        Instruction::SetField(_) | Instruction::SetMember(_) => (2, 0),
+        Instruction::SetIndex => (3, 0),
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a count or offset, which the compiler keeps well within `u32`.
+    fn len(&mut self, value: usize) {
+        self.u32(u32::try_from(value).expect("program too large for the bytecode format"));
+    }
+
+    fn string(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn instruction(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Constant(index) => {
+                self.u8(0);
+                self.u16(index);
+            }
+            Instruction::Pop => self.u8(1),
+            Instruction::GetLocal(slot) => {
+                self.u8(2);
+                self.u16(slot);
+            }
+            Instruction::SetLocal(slot) => {
+                self.u8(3);
+                self.u16(slot);
+            }
+            Instruction::GetGlobal(index) => {
+                self.u8(4);
+                self.u16(index);
+            }
+            Instruction::SetGlobal(index) => {
+                self.u8(5);
+                self.u16(index);
+            }
+            Instruction::DefineGlobal(index) => {
+                self.u8(6);
+                self.u16(index);
+            }
+            Instruction::Arithmetic(op) => {
+                self.u8(7);
+                self.u8(op as u8);
+            }
+            Instruction::Compare(op) => {
+                self.u8(8);
+                self.u8(op as u8);
+            }
+            Instruction::Negate => self.u8(9),
+            Instruction::Not => self.u8(10),
+            Instruction::CheckBool(condition) => {
+                self.u8(11);
+                self.u8(condition as u8);
+            }
+            Instruction::CheckRange => self.u8(12),
+            Instruction::Jump(target) => {
+                self.u8(13);
+                self.u32(target);
+            }
+            Instruction::JumpIfFalse(target, condition) => {
+                self.u8(14);
+                self.u32(target);
+                self.u8(condition as u8);
+            }
+            Instruction::Call(function, argc) => {
+                self.u8(15);
+                self.u16(function);
+                self.u8(argc);
+            }
+            Instruction::Return => self.u8(16),
+            Instruction::CallNative(native, argc) => {
+                self.u8(17);
+                self.u16(native);
+                self.u8(argc);
+            }
+            Instruction::BuildString(count) => {
+                self.u8(18);
+                self.u16(count);
+            }
+            Instruction::Dup => self.u8(19),
+            Instruction::New(class) => {
+                self.u8(20);
+                self.u16(class);
+            }
+            Instruction::GetField(field) => {
+                self.u8(21);
+                self.u16(field);
+            }
+            Instruction::SetField(field) => {
+                self.u8(22);
+                self.u16(field);
+            }
+            Instruction::GetMember(name) => {
+                self.u8(23);
+                self.u16(name);
+            }
+            Instruction::SetMember(name) => {
+                self.u8(24);
+                self.u16(name);
+            }
+            Instruction::Invoke(name, argc) => {
+                self.u8(25);
+                self.u16(name);
+                self.u8(argc);
+            }
+            Instruction::BuildArray(count) => {
+                self.u8(26);
+                self.u16(count);
+            }
+            Instruction::GetIndex => self.u8(27),
+            Instruction::SetIndex => self.u8(28),
+            Instruction::Length => self.u8(29),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        let end = self.position.checked_add(N).ok_or(LoadError::Truncated)?;
+        let bytes = self.bytes.get(self.position..end).ok_or(LoadError::Truncated)?;
+        self.position = end;
+        Ok(bytes.try_into().expect("slice has the requested length"))
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.u32()? as usize;
+        let end = self.position.checked_add(len).ok_or(LoadError::Truncated)?;
+        let bytes = self.bytes.get(self.position..end).ok_or(LoadError::Truncated)?;
+        self.position = end;
+        String::from_utf8(bytes.to_vec()).map_err(|_| malformed("string is not UTF-8".to_string()))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, LoadError> {
+        Ok(match self.u8()? {
+            0 => Instruction::Constant(self.u16()?),
+            1 => Instruction::Pop,
+            2 => Instruction::GetLocal(self.u16()?),
+            3 => Instruction::SetLocal(self.u16()?),
+            4 => Instruction::GetGlobal(self.u16()?),
+            5 => Instruction::SetGlobal(self.u16()?),
+            6 => Instruction::DefineGlobal(self.u16()?),
+            7 => Instruction::Arithmetic(self.arithmetic_op()?),
+            8 => Instruction::Compare(self.comparison_op()?),
+            9 => Instruction::Negate,
+            10 => Instruction::Not,
+            11 => Instruction::CheckBool(self.condition()?),
+            12 => Instruction::CheckRange,
+            13 => Instruction::Jump(self.u32()?),
+            14 => Instruction::JumpIfFalse(self.u32()?, self.condition()?),
+            15 => Instruction::Call(self.u16()?, self.u8()?),
+            16 => Instruction::Return,
+            17 => Instruction::CallNative(self.u16()?, self.u8()?),
+            18 => Instruction::BuildString(self.u16()?),
+            19 => Instruction::Dup,
+            20 => Instruction::New(self.u16()?),
+            21 => Instruction::GetField(self.u16()?),
+            22 => Instruction::SetField(self.u16()?),
+            23 => Instruction::GetMember(self.u16()?),
+            24 => Instruction::SetMember(self.u16()?),
+            25 => Instruction::Invoke(self.u16()?, self.u8()?),
+            26 => Instruction::BuildArray(self.u16()?),
+            27 => Instruction::GetIndex,
+            28 => Instruction::SetIndex,
+            29 => Instruction::Length,
+            opcode => return Err(malformed(format!("unknown opcode {}", opcode))),
+        })
+    }
+
+    fn arithmetic_op(&mut self) -> Result<ArithmeticOp, LoadError> {
+        const OPS: [ArithmeticOp; 5] = [
+            ArithmeticOp::Add,
+            ArithmeticOp::Subtract,
+            ArithmeticOp::Multiply,
+            ArithmeticOp::Divide,
+            ArithmeticOp::Remainder,
+        ];
+        let byte = self.u8()?;
+        OPS.into_iter()
+            .find(|op| *op as u8 == byte)
+            .ok_or_else(|| malformed(format!("unknown arithmetic operator {}", byte)))
+    }
+
+    fn comparison_op(&mut self) -> Result<ComparisonOp, LoadError> {
+        const OPS: [ComparisonOp; 6] = [
+            ComparisonOp::Less,
+            ComparisonOp::Greater,
+            ComparisonOp::LessEqual,
+            ComparisonOp::GreaterEqual,
+            ComparisonOp::Equal,
+            ComparisonOp::NotEqual,
+        ];
+        let byte = self.u8()?;
+        OPS.into_iter()
+            .find(|op| *op as u8 == byte)
+            .ok_or_else(|| malformed(format!("unknown comparison operator {}", byte)))
+    }
+
+    fn condition(&mut self) -> Result<Condition, LoadError> {
+        const CONDITIONS: [Condition; 4] = [Condition::If, Condition::While, Condition::And, Condition::Or];
+        let byte = self.u8()?;
+        CONDITIONS
+            .into_iter()
+            .find(|condition| *condition as u8 == byte)
+            .ok_or_else(|| malformed(format!("unknown condition kind {}", byte)))
+    }
+}
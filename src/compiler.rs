@@ -0,0 +1,752 @@
+use std::collections::HashMap;
+
+use crate::ast::{ASTNode, Node};
+use crate::builtins::{self, Output};
+use crate::bytecode::{Class, Condition, Function, Instruction, Program};
+use crate::diagnostic::Diagnostic;
+use crate::native::Natives;
+use crate::runtime_error::{arity_mismatch, function_not_found};
+use crate::span::Span;
+use crate::symbol_table::Type;
+use crate::value::{ArithmeticOp, ComparisonOp, Value};
+
+/// Compiles a checked program to bytecode for the [`Vm`](crate::vm::Vm).
+///
+/// Names are resolved here rather than at runtime: variables declared inside
+/// a function or block get a slot in the function's frame, and everything else
+/// refers to a global by index.
+pub fn compile(ast: &Node) -> Result<Program, Diagnostic> {
+    compile_with_natives(ast, &builtins::natives(Output::stdout()))
+}
+
+/// Like [`compile`], but calls to functions the program does not declare
+/// refer to the host's `natives` instead of the builtins.
+pub fn compile_with_natives(ast: &Node, natives: &Natives) -> Result<Program, Diagnostic> {
+    let mut declarations = Vec::new();
+    let mut class_declarations = Vec::new();
+    collect_declarations(ast, None, &mut declarations, &mut class_declarations);
+
+    let mut compiler = Compiler {
+        constants: Vec::new(),
+        globals: Vec::new(),
+        function_indices: HashMap::new(),
+        available_natives: natives,
+        natives: Vec::new(),
+        classes: Vec::new(),
+        bases: Vec::new(),
+        function: FunctionBuilder::new(true),
+    };
+    for declaration in &class_declarations {
+        let ASTNode::ClassDeclaration { name, members, .. } = &declaration.kind else {
+            unreachable!("collect_declarations only returns class declarations as classes");
+        };
+        let fields: Vec<(&String, &Type)> = members
+            .iter()
+            .filter_map(|member| match &member.kind {
+                ASTNode::VariableDeclaration { name, var_type } => Some((name, var_type)),
+                _ => None,
+            })
+            .collect();
+        let mut arrays = Vec::new();
+        for (index, (_, field_type)) in fields.iter().enumerate() {
+            if matches!(field_type, Type::Array(_)) {
+                arrays.push(compiler.index(index, "fields", declaration.span)?);
+            }
+        }
+        let fields = fields.into_iter().map(|(name, _)| name.clone()).collect();
+        compiler.classes.push(Class { name: name.clone(), fields, arrays, methods: Vec::new() });
+    }
+    for declaration in &class_declarations {
+        let ASTNode::ClassDeclaration { base, .. } = &declaration.kind else { unreachable!() };
+        let base = match base {
+            Some(base) => match compiler.classes.iter().position(|c| c.name == *base) {
+                Some(index) => Some(index),
+                None => return Err(Diagnostic::error("E0401", format!("Unknown class '{}'", base), declaration.span)),
+            },
+            None => None,
+        };
+        compiler.bases.push(base);
+    }
+    for (index, (declaration, class)) in declarations.iter().enumerate() {
+        if let ASTNode::FunctionDeclaration { name, .. } = &declaration.kind {
+            let index = compiler.index(index + 1, "functions", declaration.span)?;
+            match class {
+                Some(class) => {
+                    let class = compiler.classes.iter_mut().find(|c| c.name == *class);
+                    class.expect("classes were collected").methods.push((name.clone(), index));
+                }
+                None => {
+                    compiler.function_indices.insert(name.clone(), index);
+                }
+            }
+        }
+    }
+    inherit(&mut compiler.classes, &compiler.bases, ast.span)?;
+
+    compiler.statement(ast)?;
+    let void = compiler.constant(Value::Void, ast.span)?;
+    compiler.emit(Instruction::Constant(void), ast.span);
+    compiler.emit(Instruction::Return, ast.span);
+    // The top-level code is function 0, see `bytecode::SCRIPT`.
+    let mut functions = vec![compiler.finish("<script>", 0, ast.span)?];
+
+    for (declaration, class) in &declarations {
+        functions.push(compiler.function(declaration, *class)?);
+    }
+
+    let entry = compiler.function_indices.get("run").copied();
+    Ok(Program {
+        constants: compiler.constants,
+        globals: compiler.globals,
+        functions,
+        natives: compiler.natives,
+        classes: compiler.classes,
+        entry,
+    })
+}
+
+/// Finds every function and class declaration in the program, in source
+/// order. Methods are paired with the name of their class.
+fn collect_declarations<'a>(
+    node: &'a Node,
+    class: Option<&'a str>,
+    functions: &mut Vec<(&'a Node, Option<&'a str>)>,
+    classes: &mut Vec<&'a Node>,
+) {
+    let mut members_of = None;
+    let children: &[Node] = match &node.kind {
+        ASTNode::Program(nodes) => nodes,
+        ASTNode::ClassDeclaration { name, members, .. } => {
+            classes.push(node);
+            members_of = Some(name.as_str());
+            members
+        }
+        ASTNode::FunctionDeclaration { body, .. } => {
+            functions.push((node, class));
+            body
+        }
+        ASTNode::IfElse { then_block, else_block, .. } => {
+            for child in then_block {
+                collect_declarations(child, None, functions, classes);
+            }
+            else_block.as_deref().unwrap_or_default()
+        }
+        ASTNode::WhileLoop { body, .. }
+        | ASTNode::ForLoop { body, .. }
+        | ASTNode::ForEach { body, .. }
+        | ASTNode::Loop { body } => body,
+        _ => &[],
+    };
+    for child in children {
+        collect_declarations(child, members_of, functions, classes);
+    }
+}
+
+/// Gives every class the fields and methods of its base classes, so the VM
+/// never has to look further than an object's own class. Inherited fields come
+/// first, keeping the indices the base class's methods were compiled with, and
+/// a class's own methods override inherited ones of the same name.
+fn inherit(classes: &mut [Class], bases: &[Option<usize>], span: Span) -> Result<(), Diagnostic> {
+    let mut done = vec![false; classes.len()];
+    for class in 0..classes.len() {
+        if done[class] {
+            continue;
+        }
+        // Bases have to be complete before the classes extending them.
+        let mut pending = vec![class];
+        let mut current = class;
+        while let Some(base) = bases[current].filter(|&base| !done[base]) {
+            if pending.contains(&base) {
+                let message = format!("Class '{}' inherits from itself", classes[base].name);
+                return Err(Diagnostic::error("E0223", message, span));
+            }
+            pending.push(base);
+            current = base;
+        }
+        for &index in pending.iter().rev() {
+            if let Some(base) = bases[index] {
+                let base = classes[base].clone();
+                let class = &mut classes[index];
+                // The class's own fields move up to make room for the inherited ones.
+                let mut arrays = base.arrays;
+                for field in &class.arrays {
+                    let field = u16::try_from(base.fields.len() + *field as usize)
+                        .map_err(|_| Diagnostic::error("E0400", "Too many fields to compile", span))?;
+                    arrays.push(field);
+                }
+                class.arrays = arrays;
+                class.fields.splice(0..0, base.fields);
+                for (method, function) in base.methods {
+                    if !class.methods.iter().any(|(name, _)| *name == method) {
+                        class.methods.push((method, function));
+                    }
+                }
+            }
+            done[index] = true;
+        }
+    }
+    Ok(())
+}
+
+/// The function currently being compiled.
+struct FunctionBuilder {
+    code: Vec<Instruction>,
+    spans: Vec<Span>,
+    /// Names of the live local slots; `None` for slots the compiler uses internally.
+    locals: Vec<Option<String>>,
+    /// Number of live locals when each enclosing block started.
+    scopes: Vec<usize>,
+    max_locals: usize,
+    /// Variables declared at the top level of the script are globals.
+    is_script: bool,
+    /// The class of the method being compiled, whose instance is in slot 0.
+    class: Option<String>,
+}
+
+impl FunctionBuilder {
+    fn new(is_script: bool) -> Self {
+        FunctionBuilder {
+            code: Vec::new(),
+            spans: Vec::new(),
+            locals: Vec::new(),
+            scopes: Vec::new(),
+            max_locals: 0,
+            is_script,
+            class: None,
+        }
+    }
+}
+
+enum Variable {
+    Local(u16),
+    /// A field of `self`.
+    Field(u16),
+    Global(u16),
+}
+
+struct Compiler<'a> {
+    constants: Vec<Value>,
+    globals: Vec<String>,
+    function_indices: HashMap<String, u16>,
+    available_natives: &'a Natives,
+    /// The natives called so far, see `Program::natives`.
+    natives: Vec<String>,
+    classes: Vec<Class>,
+    /// The index in `classes` of each class's base class.
+    bases: Vec<Option<usize>>,
+    function: FunctionBuilder,
+}
+
+impl Compiler<'_> {
+    /// Converts an index into an instruction operand, failing if the program is too large.
+    fn index(&self, index: usize, what: &str, span: Span) -> Result<u16, Diagnostic> {
+        u16::try_from(index).map_err(|_| {
+            Diagnostic::error("E0400", format!("Too many {} to compile", what), span)
+        })
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.function.code.push(instruction);
+        self.function.spans.push(span);
+        self.function.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.function.code.len() as u32
+    }
+
+    /// Points the jump at `at` to the next instruction to be emitted.
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.function.code[at] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to, _) => *to = target,
+            other => unreachable!("patching a non-jump instruction {:?}", other),
+        }
+    }
+
+    fn constant(&mut self, value: Value, span: Span) -> Result<u16, Diagnostic> {
+        let same = |constant: &Value| match (constant, &value) {
+            // Compare floats by bits so `0.0` and `-0.0` (or NaNs) stay distinct.
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (constant, value) => constant == value,
+        };
+        if let Some(index) = self.constants.iter().position(same) {
+            return self.index(index, "constants", span);
+        }
+        self.constants.push(value);
+        self.index(self.constants.len() - 1, "constants", span)
+    }
+
+    fn global(&mut self, name: &str, span: Span) -> Result<u16, Diagnostic> {
+        let index = match self.globals.iter().position(|g| g == name) {
+            Some(index) => index,
+            None => {
+                self.globals.push(name.to_string());
+                self.globals.len() - 1
+            }
+        };
+        self.index(index, "globals", span)
+    }
+
+    fn native(&mut self, name: &str, span: Span) -> Result<u16, Diagnostic> {
+        let index = match self.natives.iter().position(|native| native == name) {
+            Some(index) => index,
+            None => {
+                self.natives.push(name.to_string());
+                self.natives.len() - 1
+            }
+        };
+        self.index(index, "native functions", span)
+    }
+
+    fn add_local(&mut self, name: Option<&str>, span: Span) -> Result<u16, Diagnostic> {
+        self.function.locals.push(name.map(str::to_string));
+        let count = self.function.locals.len();
+        self.function.max_locals = self.function.max_locals.max(count);
+        self.index(count - 1, "local variables", span)
+    }
+
+    fn resolve(&mut self, name: &str, span: Span) -> Result<Variable, Diagnostic> {
+        let local = self.function.locals.iter().rposition(|local| local.as_deref() == Some(name));
+        if let Some(slot) = local {
+            return Ok(Variable::Local(self.index(slot, "local variables", span)?));
+        }
+        let field = self
+            .function
+            .class
+            .as_ref()
+            .and_then(|class| self.classes.iter().find(|c| &c.name == class))
+            .and_then(|class| class.fields.iter().position(|field| field == name));
+        match field {
+            Some(field) => Ok(Variable::Field(self.index(field, "fields", span)?)),
+            None => Ok(Variable::Global(self.global(name, span)?)),
+        }
+    }
+
+    /// Whether the class whose method is being compiled has a method called `name`.
+    fn is_method(&self, name: &str) -> bool {
+        let class = self.function.class.as_ref().and_then(|class| self.classes.iter().find(|c| &c.name == class));
+        class.is_some_and(|class| class.methods.iter().any(|(method, _)| method == name))
+    }
+
+    fn begin_scope(&mut self) {
+        self.function.scopes.push(self.function.locals.len());
+    }
+
+    fn end_scope(&mut self) {
+        let start = self.function.scopes.pop().expect("unbalanced scopes");
+        self.function.locals.truncate(start);
+    }
+
+    fn finish(&mut self, name: &str, arity: usize, span: Span) -> Result<Function, Diagnostic> {
+        let builder = std::mem::replace(&mut self.function, FunctionBuilder::new(false));
+        Ok(Function {
+            name: name.to_string(),
+            arity: u8::try_from(arity).map_err(|_| {
+                Diagnostic::error("E0400", format!("Too many parameters in '{}' to compile", name), span)
+            })?,
+            locals: self.index(builder.max_locals, "local variables", span)?,
+            code: builder.code,
+            spans: builder.spans,
+        })
+    }
+
+    /// Compiles a function, or a method of `class` taking the instance as an extra first argument.
+    fn function(&mut self, declaration: &Node, class: Option<&str>) -> Result<Function, Diagnostic> {
+        let ASTNode::FunctionDeclaration { name, params, body, .. } = &declaration.kind else {
+            unreachable!("collect_declarations only returns function declarations as functions");
+        };
+        if let Some(class) = class {
+            self.function.class = Some(class.to_string());
+            self.add_local(Some("self"), declaration.span)?;
+        }
+        for param in params {
+            self.add_local(Some(&param.name), param.span)?;
+        }
+        self.block(body)?;
+        // Falling off the end of a function returns void.
+        let void = self.constant(Value::Void, declaration.span)?;
+        self.emit(Instruction::Constant(void), declaration.span);
+        self.emit(Instruction::Return, declaration.span);
+        match class {
+            Some(class) => self.finish(&format!("{}.{}", class, name), params.len() + 1, declaration.span),
+            None => self.finish(name, params.len(), declaration.span),
+        }
+    }
+
+    fn block(&mut self, nodes: &[Node]) -> Result<(), Diagnostic> {
+        for node in nodes {
+            self.statement(node)?;
+        }
+        Ok(())
+    }
+
+    fn scoped_block(&mut self, nodes: &[Node]) -> Result<(), Diagnostic> {
+        self.begin_scope();
+        self.block(nodes)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn statement(&mut self, node: &Node) -> Result<(), Diagnostic> {
+        let span = node.span;
+        match &node.kind {
+            ASTNode::Program(nodes) => self.block(nodes)?,
+            // Functions and methods are compiled separately and called by index,
+            // and interfaces only matter to the type checker.
+            ASTNode::FunctionDeclaration { .. }
+            | ASTNode::ClassDeclaration { .. }
+            | ASTNode::InterfaceDeclaration { .. } => {}
+            ASTNode::VariableDeclaration { name, var_type } => {
+                // Arrays start out empty, so they can be pushed to without assigning one first.
+                if matches!(var_type, Type::Array(_)) {
+                    self.emit(Instruction::BuildArray(0), span);
+                } else {
+                    let void = self.constant(Value::Void, span)?;
+                    self.emit(Instruction::Constant(void), span);
+                }
+                if self.function.is_script && self.function.scopes.is_empty() {
+                    let global = self.global(name, span)?;
+                    self.emit(Instruction::DefineGlobal(global), span);
+                } else {
+                    let slot = self.add_local(Some(name), span)?;
+                    self.emit(Instruction::SetLocal(slot), span);
+                }
+            }
+            ASTNode::Assignment { variable, expression } => {
+                self.expression(expression)?;
+                match self.resolve(variable, span)? {
+                    Variable::Local(slot) => self.emit(Instruction::SetLocal(slot), span),
+                    Variable::Field(field) => {
+                        self.emit(Instruction::GetLocal(0), span);
+                        self.emit(Instruction::SetField(field), span)
+                    }
+                    Variable::Global(global) => self.emit(Instruction::SetGlobal(global), span),
+                };
+            }
+            ASTNode::FieldAssignment { object, field, expression } => {
+                self.expression(expression)?;
+                self.expression(object)?;
+                let name = self.constant(Value::String(field.clone()), span)?;
+                self.emit(Instruction::SetMember(name), span);
+            }
+            ASTNode::IndexAssignment { object, index, expression } => {
+                self.expression(expression)?;
+                self.expression(object)?;
+                self.expression(index)?;
+                self.emit(Instruction::SetIndex, span);
+            }
+            ASTNode::IfElse { condition, then_block, else_block } => {
+                self.expression(condition)?;
+                let to_else = self.emit(Instruction::JumpIfFalse(0, Condition::If), condition.span);
+                self.scoped_block(then_block)?;
+                match else_block {
+                    Some(else_block) => {
+                        let to_end = self.emit(Instruction::Jump(0), span);
+                        self.patch(to_else);
+                        self.scoped_block(else_block)?;
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            ASTNode::WhileLoop { condition, body } => {
+                let top = self.here();
+                self.expression(condition)?;
+                let to_exit = self.emit(Instruction::JumpIfFalse(0, Condition::While), condition.span);
+                self.scoped_block(body)?;
+                self.emit(Instruction::Jump(top), span);
+                self.patch(to_exit);
+            }
+            ASTNode::ForLoop { variable, start, end, body } => self.for_loop(variable, start, end, body, span)?,
+            ASTNode::ForEach { variable, array, body } => self.for_each(variable, array, body, span)?,
+            ASTNode::Ret { expression } => {
+                match expression {
+                    Some(expression) => self.expression(expression)?,
+                    None => {
+                        let void = self.constant(Value::Void, span)?;
+                        self.emit(Instruction::Constant(void), span);
+                    }
+                }
+                self.emit(Instruction::Return, span);
+            }
+            ASTNode::Error | ASTNode::Loop { .. } => {
+                return Err(Diagnostic::error(
+                    "E0402",
+                    format!("Compilation not yet implemented for: {:?}", node.kind),
+                    span,
+                ))
+            }
+            _ => {
+                self.expression(node)?;
+                self.emit(Instruction::Pop, span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles `for variable = start end`. The counter and bound live in
+    /// hidden slots, so assigning to the loop variable doesn't affect iteration.
+    fn for_loop(
+        &mut self,
+        variable: &str,
+        start: &Node,
+        end: &Node,
+        body: &[Node],
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        self.begin_scope();
+        self.expression(start)?;
+        self.expression(end)?;
+        self.emit(Instruction::CheckRange, start.span.to(end.span));
+        let bound = self.add_local(None, span)?;
+        let counter = self.add_local(None, span)?;
+        let slot = self.add_local(Some(variable), span)?;
+        self.emit(Instruction::SetLocal(bound), span);
+        self.emit(Instruction::SetLocal(counter), span);
+
+        let top = self.here();
+        self.emit(Instruction::GetLocal(counter), span);
+        self.emit(Instruction::GetLocal(bound), span);
+        self.emit(Instruction::Compare(ComparisonOp::Less), span);
+        // The comparison always yields a bool, so the condition kind is never reported.
+        let to_exit = self.emit(Instruction::JumpIfFalse(0, Condition::While), span);
+        self.emit(Instruction::GetLocal(counter), span);
+        self.emit(Instruction::SetLocal(slot), span);
+        self.scoped_block(body)?;
+
+        // `counter < bound`, so this can't overflow.
+        let one = self.constant(Value::Int(1), span)?;
+        self.emit(Instruction::GetLocal(counter), span);
+        self.emit(Instruction::Constant(one), span);
+        self.emit(Instruction::Arithmetic(ArithmeticOp::Add), span);
+        self.emit(Instruction::SetLocal(counter), span);
+        self.emit(Instruction::Jump(top), span);
+        self.patch(to_exit);
+        self.end_scope();
+        Ok(())
+    }
+
+    /// Compiles `for variable in array`. Like `for_loop`, the array and the
+    /// position in it live in hidden slots, and the length is read on every
+    /// iteration so the body may grow or shrink the array.
+    fn for_each(&mut self, variable: &str, array: &Node, body: &[Node], span: Span) -> Result<(), Diagnostic> {
+        self.begin_scope();
+        self.expression(array)?;
+        let elements = self.add_local(None, span)?;
+        let counter = self.add_local(None, span)?;
+        let slot = self.add_local(Some(variable), span)?;
+        self.emit(Instruction::SetLocal(elements), span);
+        let zero = self.constant(Value::Int(0), span)?;
+        self.emit(Instruction::Constant(zero), span);
+        self.emit(Instruction::SetLocal(counter), span);
+
+        let top = self.here();
+        self.emit(Instruction::GetLocal(counter), span);
+        self.emit(Instruction::GetLocal(elements), span);
+        // Fails if the value being iterated isn't an array.
+        self.emit(Instruction::Length, array.span);
+        self.emit(Instruction::Compare(ComparisonOp::Less), span);
+        let to_exit = self.emit(Instruction::JumpIfFalse(0, Condition::While), span);
+        self.emit(Instruction::GetLocal(elements), span);
+        self.emit(Instruction::GetLocal(counter), span);
+        self.emit(Instruction::GetIndex, span);
+        self.emit(Instruction::SetLocal(slot), span);
+        self.scoped_block(body)?;
+
+        let one = self.constant(Value::Int(1), span)?;
+        self.emit(Instruction::GetLocal(counter), span);
+        self.emit(Instruction::Constant(one), span);
+        self.emit(Instruction::Arithmetic(ArithmeticOp::Add), span);
+        self.emit(Instruction::SetLocal(counter), span);
+        self.emit(Instruction::Jump(top), span);
+        self.patch(to_exit);
+        self.end_scope();
+        Ok(())
+    }
+
+    fn expression(&mut self, node: &Node) -> Result<(), Diagnostic> {
+        let span = node.span;
+        match &node.kind {
+            ASTNode::Number(n) => self.push_constant(Value::Int(*n), span)?,
+            ASTNode::Float(f) => self.push_constant(Value::Float(*f), span)?,
+            ASTNode::Boolean(b) => self.push_constant(Value::Bool(*b), span)?,
+            ASTNode::StringLiteral(s) => self.push_constant(Value::String(s.clone()), span)?,
+            ASTNode::Interpolation(parts) => {
+                for part in parts {
+                    self.expression(part)?;
+                }
+                let count = self.index(parts.len(), "interpolated values", span)?;
+                self.emit(Instruction::BuildString(count), span);
+            }
+            ASTNode::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                let count = self.index(elements.len(), "array elements", span)?;
+                self.emit(Instruction::BuildArray(count), span);
+            }
+            ASTNode::Index { object, index } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.emit(Instruction::GetIndex, span);
+            }
+            ASTNode::Identifier(name) => {
+                match self.resolve(name, span)? {
+                    Variable::Local(slot) => self.emit(Instruction::GetLocal(slot), span),
+                    Variable::Field(field) => {
+                        self.emit(Instruction::GetLocal(0), span);
+                        self.emit(Instruction::GetField(field), span)
+                    }
+                    Variable::Global(global) => self.emit(Instruction::GetGlobal(global), span),
+                };
+            }
+            ASTNode::SelfRef => {
+                if self.function.class.is_none() {
+                    return Err(Diagnostic::error("E0218", "'self' can only be used inside a method", span));
+                }
+                self.emit(Instruction::GetLocal(0), span);
+            }
+            ASTNode::Arithmetic { left, operator, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                let op = ArithmeticOp::from_symbol(operator).expect("parser only builds known operators");
+                self.emit(Instruction::Arithmetic(op), span);
+            }
+            ASTNode::Comparison { left, operator, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                let op = ComparisonOp::from_symbol(operator).expect("parser only builds known operators");
+                self.emit(Instruction::Compare(op), span);
+            }
+            ASTNode::Logical { left, operator, right } => {
+                // `false and _` and `true or _` never evaluate their right-hand side.
+                let is_and = operator == "and";
+                let condition = if is_and { Condition::And } else { Condition::Or };
+                self.expression(left)?;
+                let to_short_circuit = self.emit(Instruction::JumpIfFalse(0, condition), left.span);
+                if is_and {
+                    self.expression(right)?;
+                    self.emit(Instruction::CheckBool(condition), right.span);
+                    let to_end = self.emit(Instruction::Jump(0), span);
+                    self.patch(to_short_circuit);
+                    self.push_constant(Value::Bool(false), span)?;
+                    self.patch(to_end);
+                } else {
+                    self.push_constant(Value::Bool(true), span)?;
+                    let to_end = self.emit(Instruction::Jump(0), span);
+                    self.patch(to_short_circuit);
+                    self.expression(right)?;
+                    self.emit(Instruction::CheckBool(condition), right.span);
+                    self.patch(to_end);
+                }
+            }
+            ASTNode::Unary { operator, operand } => {
+                self.expression(operand)?;
+                let instruction = if operator == "not" { Instruction::Not } else { Instruction::Negate };
+                self.emit(instruction, span);
+            }
+            ASTNode::FunctionCall { name, args } if self.is_method(name) => {
+                // Calling another method of the class calls it on `self`.
+                self.emit(Instruction::GetLocal(0), span);
+                self.invoke(name, args, span)?;
+            }
+            ASTNode::FunctionCall { name, args } => {
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                let argc = self.argument_count(args.len(), span)?;
+                if let Some(&function) = self.function_indices.get(name) {
+                    self.emit(Instruction::Call(function, argc), span);
+                } else if self.available_natives.get(name).is_some() {
+                    let native = self.native(name, span)?;
+                    self.emit(Instruction::CallNative(native, argc), span);
+                } else {
+                    return Err(function_not_found(name, span));
+                }
+            }
+            ASTNode::MethodCall { object, method, args } => {
+                self.expression(object)?;
+                self.invoke(method, args, span)?;
+            }
+            ASTNode::MemberAccess { object, member } => {
+                self.expression(object)?;
+                let name = self.constant(Value::String(member.clone()), span)?;
+                self.emit(Instruction::GetMember(name), span);
+            }
+            ASTNode::SuperCall { method, args } => {
+                let Some(class) = self.function.class.as_ref() else {
+                    return Err(Diagnostic::error("E0218", "'super' can only be used inside a method", span));
+                };
+                // The base class's version is known here, so it is called directly rather than invoked.
+                let class = self.classes.iter().position(|c| c.name == *class).expect("classes were collected");
+                let function = self.bases[class]
+                    .and_then(|base| self.classes[base].methods.iter().find(|(name, _)| name == method))
+                    .map(|&(_, function)| function);
+                let Some(function) = function else {
+                    return Err(Diagnostic::error(
+                        "E0220",
+                        format!("Class '{}' has no base class method '{}'", self.classes[class].name, method),
+                        span,
+                    ));
+                };
+                self.emit(Instruction::GetLocal(0), span);
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                let argc = self.argument_count(args.len() + 1, span)?;
+                self.emit(Instruction::Call(function, argc), span);
+            }
+            ASTNode::New { class, args } => {
+                let Some(index) = self.classes.iter().position(|c| &c.name == class) else {
+                    return Err(Diagnostic::error("E0401", format!("Unknown class '{}'", class), span));
+                };
+                let has_init = self.classes[index].methods.iter().any(|(method, _)| method == "init");
+                let index = self.index(index, "classes", span)?;
+                self.emit(Instruction::New(index), span);
+                if has_init {
+                    // `init` gets its own reference to the instance, the one below is the result.
+                    self.emit(Instruction::Dup, span);
+                    self.invoke("init", args, span)?;
+                    self.emit(Instruction::Pop, span);
+                } else if !args.is_empty() {
+                    return Err(arity_mismatch(&format!("{}.new", class), 0, args.len(), span));
+                }
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    "E0402",
+                    format!("Compilation not yet implemented for: {:?}", node.kind),
+                    span,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `method` on the object on top of the stack.
+    fn invoke(&mut self, method: &str, args: &[Node], span: Span) -> Result<(), Diagnostic> {
+        for arg in args {
+            self.expression(arg)?;
+        }
+        // The object is passed as an extra argument, which must fit too.
+        self.argument_count(args.len() + 1, span)?;
+        // A string calls the builtin of the same name, which the VM finds among the program's natives.
+        if builtins::STRING_METHODS.contains(&method) && self.available_natives.get(method).is_some() {
+            self.native(method, span)?;
+        }
+        let name = self.constant(Value::String(method.to_string()), span)?;
+        self.emit(Instruction::Invoke(name, args.len() as u8), span);
+        Ok(())
+    }
+
+    fn argument_count(&self, count: usize, span: Span) -> Result<u8, Diagnostic> {
+        u8::try_from(count).map_err(|_| Diagnostic::error("E0400", "Too many arguments to compile", span))
+    }
+
+    fn push_constant(&mut self, value: Value, span: Span) -> Result<(), Diagnostic> {
+        let index = self.constant(value, span)?;
+        self.emit(Instruction::Constant(index), span);
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A secondary location attached to a diagnostic, e.g. where a variable was declared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem found while lexing, parsing, analyzing or running a program.
+///
+/// Codes are grouped by the pass that reports them: `E00xx` lexer,
+/// `E01xx` parser, `E02xx` semantic analysis, `E03xx` runtime and `E04xx`
+/// bytecode compilation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message, span)
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic rustc-style: the header, the offending source
+    /// lines with the primary span underlined by `^` and labels by `-`, then notes.
+    pub fn render(&self, filename: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", style, text)
+            } else {
+                text.to_string()
+            }
+        };
+        let severity_style = match self.severity {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+            Severity::Note => "1;36",
+        };
+
+        let mut marks: Vec<(Span, char, &str)> = vec![(self.span, '^', "")];
+        marks.extend(self.labels.iter().map(|l| (l.span, '-', l.message.as_str())));
+        marks.sort_by_key(|(span, ..)| span.start);
+
+        let lines: Vec<(usize, &str)> = line_starts(source);
+        let line_of = |offset: usize| lines.partition_point(|(start, _)| *start <= offset).max(1) - 1;
+        let gutter = marks
+            .iter()
+            .map(|(span, ..)| (line_of(span.start) + 1).to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+        let bar = paint("1;34", "|");
+
+        let mut out = format!(
+            "{}{}\n{}{} {}:{}\n",
+            paint(severity_style, &format!("{}[{}]", self.severity, self.code)),
+            paint("1", &format!(": {}", self.message)),
+            pad,
+            paint("1;34", "-->"),
+            filename,
+            self.span
+        );
+        out.push_str(&format!("{} {}\n", pad, bar));
+
+        let mut last_line = None;
+        for (span, marker, message) in &marks {
+            let index = line_of(span.start);
+            let (line_start, text) = lines.get(index).copied().unwrap_or((0, ""));
+            if last_line != Some(index) {
+                if matches!(last_line, Some(last) if index > last + 1) {
+                    out.push_str(&format!("{}\n", paint("1;34", "...")));
+                }
+                let number = format!("{:>width$}", index + 1, width = gutter);
+                out.push_str(&format!("{} {} {}\n", paint("1;34", &number), bar, text));
+                last_line = Some(index);
+            }
+
+            let column = span.start.saturating_sub(line_start).min(text.len());
+            let indent: String = text[..column]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let end = span.end.saturating_sub(line_start).clamp(column, text.len());
+            let width = text[column..end].chars().count().max(1);
+            let style = if *marker == '^' { severity_style } else { "1;34" };
+            let underline = marker.to_string().repeat(width);
+            let mut line = format!("{} {} {}{}", pad, bar, indent, paint(style, &underline));
+            if !message.is_empty() {
+                line.push(' ');
+                line.push_str(&paint(style, message));
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{} {} note: {}\n", pad, paint("1;34", "="), note));
+        }
+        out
+    }
+}
+
+/// Returns each line of `source` along with the byte offset it starts at.
+fn line_starts(source: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in source.split('\n') {
+        lines.push((start, line.strip_suffix('\r').unwrap_or(line)));
+        start += line.len() + 1;
+    }
+    lines
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}[{}]: {}", self.span, self.severity, self.code, self.message)?;
+        for note in &self.notes {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
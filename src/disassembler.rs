@@ -0,0 +1,115 @@
+use std::fmt::Write;
+
+use crate::bytecode::{Function, Instruction, Program};
+
+/// Renders a compiled program as human-readable assembly, for debugging the compiler.
+///
+/// Each instruction is shown with its offset, the source line it came from
+/// (`|` when unchanged from the previous instruction) and, for operands that
+/// index a table, what they refer to.
+pub fn disassemble(program: &Program) -> String {
+    let mut out = String::new();
+    out.push_str("constants:\n");
+    for (index, constant) in program.constants.iter().enumerate() {
+        let _ = writeln!(out, "  {:>4}  {:?}", index, constant);
+    }
+    out.push_str("globals:\n");
+    for (index, global) in program.globals.iter().enumerate() {
+        let _ = writeln!(out, "  {:>4}  {}", index, global);
+    }
+    out.push_str("natives:\n");
+    for (index, native) in program.natives.iter().enumerate() {
+        let _ = writeln!(out, "  {:>4}  {}", index, native);
+    }
+    out.push_str("classes:\n");
+    for (index, class) in program.classes.iter().enumerate() {
+        let methods: Vec<String> =
+            class.methods.iter().map(|(name, function)| format!("{} -> {}", name, function)).collect();
+        let _ = writeln!(
+            out,
+            "  {:>4}  {} (fields: {}; methods: {})",
+            index,
+            class.name,
+            class.fields.join(", "),
+            methods.join(", ")
+        );
+    }
+    for (index, function) in program.functions.iter().enumerate() {
+        let entry = if program.entry == Some(index as u16) { ", entry" } else { "" };
+        let _ = writeln!(
+            out,
+            "\nfunction {} '{}' (arity {}, locals {}{}):",
+            index, function.name, function.arity, function.locals, entry
+        );
+        disassemble_function(program, function, &mut out);
+    }
+    out
+}
+
+fn disassemble_function(program: &Program, function: &Function, out: &mut String) {
+    for (offset, instruction) in function.code.iter().enumerate() {
+        let line = function.spans.get(offset).map(|span| span.line);
+        let previous = offset.checked_sub(1).and_then(|i| function.spans.get(i)).map(|span| span.line);
+        let line = match line {
+            Some(line) if Some(line) != previous => line.to_string(),
+            Some(_) => "|".to_string(),
+            None => "?".to_string(),
+        };
+        let _ = write!(out, "  {:04}  {:>4}  {}", offset, line, mnemonic(*instruction));
+        if let Some(comment) = comment(program, *instruction) {
+            let _ = write!(out, "  ; {}", comment);
+        }
+        out.push('\n');
+    }
+}
+
+fn mnemonic(instruction: Instruction) -> String {
+    match instruction {
+        Instruction::Constant(index) => format!("CONSTANT {}", index),
+        Instruction::Pop => "POP".to_string(),
+        Instruction::Dup => "DUP".to_string(),
+        Instruction::GetLocal(slot) => format!("GET_LOCAL {}", slot),
+        Instruction::SetLocal(slot) => format!("SET_LOCAL {}", slot),
+        Instruction::GetGlobal(index) => format!("GET_GLOBAL {}", index),
+        Instruction::SetGlobal(index) => format!("SET_GLOBAL {}", index),
+        Instruction::DefineGlobal(index) => format!("DEFINE_GLOBAL {}", index),
+        Instruction::Arithmetic(op) => format!("ARITHMETIC {}", op),
+        Instruction::Compare(op) => format!("COMPARE {}", op),
+        Instruction::Negate => "NEGATE".to_string(),
+        Instruction::Not => "NOT".to_string(),
+        Instruction::BuildString(count) => format!("BUILD_STRING {}", count),
+        Instruction::BuildArray(count) => format!("BUILD_ARRAY {}", count),
+        Instruction::GetIndex => "GET_INDEX".to_string(),
+        Instruction::SetIndex => "SET_INDEX".to_string(),
+        Instruction::Length => "LENGTH".to_string(),
+        Instruction::CheckBool(condition) => format!("CHECK_BOOL {:?}", condition),
+        Instruction::CheckRange => "CHECK_RANGE".to_string(),
+        Instruction::Jump(target) => format!("JUMP {:04}", target),
+        Instruction::JumpIfFalse(target, condition) => format!("JUMP_IF_FALSE {:04} {:?}", target, condition),
+        Instruction::Call(function, argc) => format!("CALL {} {}", function, argc),
+        Instruction::CallNative(native, argc) => format!("CALL_NATIVE {} {}", native, argc),
+        Instruction::New(class) => format!("NEW {}", class),
+        Instruction::GetField(field) => format!("GET_FIELD {}", field),
+        Instruction::SetField(field) => format!("SET_FIELD {}", field),
+        Instruction::GetMember(name) => format!("GET_MEMBER {}", name),
+        Instruction::SetMember(name) => format!("SET_MEMBER {}", name),
+        Instruction::Invoke(name, argc) => format!("INVOKE {} {}", name, argc),
+        Instruction::Return => "RETURN".to_string(),
+    }
+}
+
+fn comment(program: &Program, instruction: Instruction) -> Option<String> {
+    match instruction {
+        Instruction::Constant(index)
+        | Instruction::GetMember(index)
+        | Instruction::SetMember(index)
+        | Instruction::Invoke(index, _) => program.constants.get(index as usize).map(|c| format!("{:?}", c)),
+        Instruction::GetGlobal(index) | Instruction::SetGlobal(index) | Instruction::DefineGlobal(index) => {
+            program.globals.get(index as usize).cloned()
+        }
+        Instruction::Call(index, _) => program.functions.get(index as usize).map(|f| f.name.clone()),
+        Instruction::CallNative(index, _) => program.natives.get(index as usize).cloned(),
+        Instruction::New(index) => program.classes.get(index as usize).map(|c| c.name.clone()),
+        _ => None,
+    }
+}
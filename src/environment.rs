@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use crate::interpreter::Value;
+
+/// The variables of one function call: a stack of block scopes, innermost last.
+#[derive(Debug, Default)]
+struct Frame {
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+/// Runtime variable storage. Names resolve through the block scopes of the
+/// current call frame and then the globals, so a function never sees the
+/// locals of its caller.
+#[derive(Debug, Default)]
+pub struct Environment {
+    globals: HashMap<String, Value>,
+    frames: Vec<Frame>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a function call whose outermost scope holds `bindings`.
+    pub fn push_frame(&mut self, bindings: HashMap<String, Value>) {
+        self.frames.push(Frame { scopes: vec![bindings] });
+    }
+
+    pub fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Opens a block scope. Outside of any call the scope gets a frame of its
+    /// own, so top-level blocks don't leak into the globals.
+    pub fn push_scope(&mut self) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.scopes.push(HashMap::new()),
+            None => self.push_frame(HashMap::new()),
+        }
+    }
+
+    pub fn pop_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.pop();
+            if frame.scopes.is_empty() {
+                self.frames.pop();
+            }
+        }
+    }
+
+    fn innermost(&mut self) -> &mut HashMap<String, Value> {
+        match self.frames.last_mut().and_then(|frame| frame.scopes.last_mut()) {
+            Some(scope) => scope,
+            None => &mut self.globals,
+        }
+    }
+
+    /// Creates a binding in the innermost scope, shadowing any outer one.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.innermost().insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.get_local(name).or_else(|| self.globals.get(name))
+    }
+
+    /// Looks `name` up in the current call frame only, ignoring the globals.
+    pub fn get_local(&self, name: &str) -> Option<&Value> {
+        let locals = self.frames.last().map(|frame| frame.scopes.as_slice()).unwrap_or_default();
+        locals.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Updates the innermost existing binding of `name`. Returns `false` if
+    /// there is none.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        let locals = match self.frames.last_mut() {
+            Some(frame) => frame.scopes.as_mut_slice(),
+            None => &mut [],
+        };
+        let slot = locals
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .or_else(|| self.globals.get_mut(name));
+        match slot {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
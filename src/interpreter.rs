@@ -1,256 +1,645 @@
 use std::collections::HashMap;
-use crate::ast::ASTNode;
+use std::rc::Rc;
 
-#[derive(Debug, Clone)]
-pub enum Value {
-    Int(i64),
-    Float(f64),
-    Bool(bool),
-    String(String),
-    Void,
-    Return(Box<Value>),
-}
+use crate::ast::{ASTNode, Node, Parameter};
+use crate::diagnostic::Diagnostic;
+use crate::builtins::{self, Output};
+use crate::environment::Environment;
+use crate::native::{IntoNative, Natives};
+use crate::span::Span;
+use crate::symbol_table::Type;
+use crate::runtime_error::{
+    arity_mismatch, division_by_zero, function_not_found, invalid_comparison, invalid_for_range, invalid_index,
+    no_member, not_a_bool, not_iterable, overflow, stack_overflow, undefined_variable, unsupported_arithmetic,
+    unsupported_unary, MAX_INTERPRETER_CALL_DEPTH,
+};
+pub use crate::runtime_error::{RuntimeError, StackFrame};
+pub use crate::value::Value;
+use crate::value::{arithmetic, compare, element, negate, Array, ArithmeticError, ArithmeticOp, ComparisonOp, Object};
 
 #[derive(Debug, Clone)]
 pub struct FunctionInfo {
     pub params: Vec<String>,
-    pub body: Vec<ASTNode>,
+    pub body: Vec<Node>,
+    /// The class declaring a method, or `None` for a function.
+    pub class: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    pub base: Option<String>,
+    /// The names and types of the fields the class declares, in the order an
+    /// instance stores them after the fields of its base classes.
+    pub fields: Vec<(String, Type)>,
+    /// The methods the class declares, not counting inherited ones.
+    pub methods: HashMap<String, Rc<FunctionInfo>>,
 }
 
 pub struct Interpreter {
-    pub variables: HashMap<String, Value>,
-    pub functions: HashMap<String, FunctionInfo>,
+    pub environment: Environment,
+    /// Shared so calls don't have to copy the function body.
+    pub functions: HashMap<String, Rc<FunctionInfo>>,
+    pub classes: HashMap<String, Rc<ClassInfo>>,
+    /// The builtins and any functions registered by the host, called when the
+    /// program declares no function of the same name.
+    pub natives: Natives,
+    pub call_stack: Vec<StackFrame>,
+    /// The class declaring the running method, whose base class `super` calls look in.
+    class: Option<String>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
+    /// Creates an interpreter whose builtins print to stdout.
     pub fn new() -> Self {
+        Self::with_output(Output::stdout())
+    }
+
+    /// Creates an interpreter whose builtins print to `output`.
+    pub fn with_output(output: Output) -> Self {
         Self {
-            variables: HashMap::new(),
+            environment: Environment::new(),
             functions: HashMap::new(),
+            classes: HashMap::new(),
+            natives: builtins::natives(output),
+            call_stack: Vec::new(),
+            class: None,
         }
     }
 
-    pub fn interpret(&mut self, node: &ASTNode) -> Value {
-        match node {
-            ASTNode::Program(nodes) => {
-                for stmt in nodes {
-                    self.interpret(stmt);
-                }
+    /// Makes a Rust function callable from scripts. Pass [`Interpreter::natives`]
+    /// to [`SymbolTable::with_natives`](crate::symbol_table::SymbolTable::with_natives)
+    /// so calls to it are type checked.
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        self.natives.register_fn(name, function);
+    }
 
-                if self.functions.contains_key("run") {
-                    self.call_function("run", vec![])
-                } else {
-                    Value::Void
-                }
-            }
+    /// Attaches the current call stack to a runtime failure.
+    fn fail(&self, diagnostic: Diagnostic) -> RuntimeError {
+        RuntimeError {
+            diagnostic: Box::new(diagnostic),
+            stack_trace: self.call_stack.iter().rev().cloned().collect(),
+        }
+    }
 
-            ASTNode::ClassDeclaration { name: _, members } => {
-                for member in members {
-                    self.interpret(member);
-                }
-                Value::Void
+    /// Runs a statement, or evaluates an expression used as one.
+    pub fn interpret(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        let span = node.span;
+        match &node.kind {
+            ASTNode::Program(nodes) => self.program(nodes),
+            ASTNode::ClassDeclaration { name, base, members, .. } => self.declare_class(name, base, members),
+            // Interfaces only matter to the type checker: methods are found on the instance's class.
+            ASTNode::InterfaceDeclaration { .. } => Ok(Value::Void),
+            ASTNode::FunctionDeclaration { name, params, body, .. } => self.declare_function(name, params, body),
+            ASTNode::VariableDeclaration { name, var_type } => self.declare_variable(name, var_type),
+            ASTNode::Assignment { variable, expression } => self.assign(variable, expression, span),
+            ASTNode::Ret { expression } => self.ret(expression.as_deref()),
+            ASTNode::IfElse { condition, then_block, else_block } => {
+                self.if_else(condition, then_block, else_block.as_deref())
             }
-
-            ASTNode::FunctionDeclaration { name, params, body } => {
-                self.functions.insert(
-                    name.clone(),
-                    FunctionInfo {
-                        params: params.clone(),
-                        body: body.clone(),
-                    },
-                );
-                Value::Void
+            ASTNode::WhileLoop { condition, body } => self.while_loop(condition, body),
+            ASTNode::ForLoop { variable, start, end, body } => self.for_loop(variable, start, end, body),
+            ASTNode::ForEach { variable, array, body } => self.for_each(variable, array, body),
+            ASTNode::FieldAssignment { object, field, expression } => {
+                self.field_assignment(object, field, expression, span)
             }
-
-            ASTNode::VariableDeclaration { name, .. } => {
-                self.variables.insert(name.clone(), Value::Void);
-                Value::Void
+            ASTNode::IndexAssignment { object, index, expression } => {
+                self.index_assignment(object, index, expression, span)
             }
+            _ => self.evaluate(node),
+        }
+    }
 
-            ASTNode::Assignment { variable, expression } => {
-                let value = self.interpret(expression);
-                self.variables.insert(variable.clone(), value);
-                Value::Void
+    /// Evaluates an expression. Kept apart from `interpret` so each level of
+    /// a nested expression, and so each nested call, takes less native stack.
+    fn evaluate(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        let span = node.span;
+        match &node.kind {
+            ASTNode::Identifier(name) => self.variable(name, span),
+            ASTNode::SelfRef => self.self_value(span),
+            ASTNode::Number(_) | ASTNode::Float(_) | ASTNode::Boolean(_) | ASTNode::StringLiteral(_) => {
+                Ok(literal(&node.kind))
             }
+            ASTNode::Interpolation(parts) => self.interpolate(parts),
+            ASTNode::Arithmetic { left, operator, right } => self.eval_arithmetic(left, operator, right, span),
+            ASTNode::Comparison { left, operator, right } => self.eval_comparison(left, operator, right, span),
+            ASTNode::Logical { left, operator, right } => self.eval_logical(left, operator, right),
+            ASTNode::Unary { operator, operand } => self.eval_unary(operator, operand, span),
+            ASTNode::FunctionCall { name, args } => self.function_call(name, args, span),
+            ASTNode::MemberAccess { object, member } => self.member_access(object, member, span),
+            ASTNode::ArrayLiteral(elements) => self.array_literal(elements),
+            ASTNode::Index { object, index } => self.index(object, index, span),
+            ASTNode::MethodCall { object, method, args } => self.method_call(object, method, args, span),
+            ASTNode::SuperCall { method, args } => self.super_call(method, args, span),
+            ASTNode::New { class, args } => self.new_instance(class, args, span),
+            _ => Err(self.unsupported(node)),
+        }
+    }
 
-            ASTNode::Identifier(name) => {
-                self.variables.get(name).cloned().unwrap_or_else(|| {
-                    panic!("Runtime error: undefined variable '{}'", name)
-                })
-            }
+    fn declare_variable(&mut self, name: &str, var_type: &Type) -> Result<Value, RuntimeError> {
+        self.environment.define(name, initial_value(var_type));
+        Ok(Value::Void)
+    }
 
-            ASTNode::Number(n) => Value::Int(*n),
-            ASTNode::Float(f) => Value::Float(*f),
-            ASTNode::Boolean(b) => Value::Bool(*b),
-            ASTNode::StringLiteral(s) => Value::String(s.clone()),
-
-            ASTNode::Arithmetic { left, operator, right } => {
-                let l = self.interpret(left);
-                let r = self.interpret(right);
-                match (l, r, operator.as_str()) {
-                    (Value::Int(a), Value::Int(b), "+") => Value::Int(a + b),
-                    (Value::Int(a), Value::Int(b), "-") => Value::Int(a - b),
-                    (Value::Int(a), Value::Int(b), "*") => Value::Int(a * b),
-                    (Value::Int(a), Value::Int(b), "/") => Value::Int(a / b),
-                    (Value::Float(a), Value::Float(b), "+") => Value::Float(a + b),
-                    (Value::Float(a), Value::Float(b), "-") => Value::Float(a - b),
-                    (Value::Float(a), Value::Float(b), "*") => Value::Float(a * b),
-                    (Value::Float(a), Value::Float(b), "/") => Value::Float(a / b),
-                    _ => panic!("Unsupported arithmetic operation"),
+    fn unsupported(&self, node: &Node) -> RuntimeError {
+        self.fail(Diagnostic::error(
+            "E0304",
+            format!("Interpretation not yet implemented for: {:?}", node.kind),
+            node.span,
+        ))
+    }
+
+    fn program(&mut self, nodes: &[Node]) -> Result<Value, RuntimeError> {
+        for stmt in nodes {
+            self.interpret(stmt)?;
+        }
+
+        if self.functions.contains_key("run") {
+            self.call_function("run", vec![])
+        } else {
+            Ok(Value::Void)
+        }
+    }
+
+    fn declare_class(&mut self, class_name: &str, base: &Option<String>, members: &[Node]) -> Result<Value, RuntimeError> {
+        let mut class = ClassInfo { base: base.clone(), fields: Vec::new(), methods: HashMap::new() };
+        for member in members {
+            match &member.kind {
+                ASTNode::VariableDeclaration { name, var_type } => class.fields.push((name.clone(), var_type.clone())),
+                ASTNode::FunctionDeclaration { name, params, body, .. } => {
+                    let method = FunctionInfo {
+                        params: params.iter().map(|param| param.name.clone()).collect(),
+                        body: body.clone(),
+                        class: Some(class_name.to_string()),
+                    };
+                    class.methods.insert(name.clone(), Rc::new(method));
                 }
+                _ => {}
             }
+        }
+        self.classes.insert(class_name.to_string(), Rc::new(class));
+        Ok(Value::Void)
+    }
+
+    fn declare_function(&mut self, name: &str, params: &[Parameter], body: &[Node]) -> Result<Value, RuntimeError> {
+        let params = params.iter().map(|param| param.name.clone()).collect();
+        let function = FunctionInfo { params, body: body.to_vec(), class: None };
+        self.functions.insert(name.to_string(), Rc::new(function));
+        Ok(Value::Void)
+    }
+
+    fn assign(&mut self, variable: &str, expression: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(expression)?;
+        if let Some((object, index)) = self.self_field(variable) {
+            object.set(index, value);
+        } else if !self.environment.assign(variable, value) {
+            return Err(self.fail(undefined_variable(variable, span)));
+        }
+        Ok(Value::Void)
+    }
+
+    /// The value of a variable or a field of `self`.
+    fn variable(&self, name: &str, span: Span) -> Result<Value, RuntimeError> {
+        match self.self_field(name) {
+            Some((object, index)) => Ok(object.get(index).expect("instances have every field of their class")),
+            None => self.environment.get(name).cloned().ok_or_else(|| self.fail(undefined_variable(name, span))),
+        }
+    }
+
+    /// The instance the running method was called on, as a value.
+    fn self_value(&self, span: Span) -> Result<Value, RuntimeError> {
+        self.environment.get_local("self").cloned().ok_or_else(|| self.fail(undefined_variable("self", span)))
+    }
+
+    fn interpolate(&mut self, parts: &[Node]) -> Result<Value, RuntimeError> {
+        let mut string = String::new();
+        for part in parts {
+            string.push_str(&self.evaluate(part)?.to_string());
+        }
+        Ok(Value::String(string))
+    }
+
+    fn eval_arithmetic(&mut self, left: &Node, operator: &str, right: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let l = self.evaluate(left)?;
+        let r = self.evaluate(right)?;
+        let op = ArithmeticOp::from_symbol(operator).expect("parser only builds known operators");
+        arithmetic(op, &l, &r).map_err(|err| {
+            self.fail(match err {
+                ArithmeticError::DivisionByZero => division_by_zero(span, right.span),
+                ArithmeticError::Overflow => overflow(operator, span),
+                ArithmeticError::Unsupported => unsupported_arithmetic(op, &l, &r, span),
+            })
+        })
+    }
+
+    fn eval_comparison(&mut self, left: &Node, operator: &str, right: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let l = self.evaluate(left)?;
+        let r = self.evaluate(right)?;
+        let op = ComparisonOp::from_symbol(operator).expect("parser only builds known operators");
+        compare(op, &l, &r).ok_or_else(|| self.fail(invalid_comparison(op, &l, &r, span)))
+    }
+
+    fn eval_logical(&mut self, left: &Node, operator: &str, right: &Node) -> Result<Value, RuntimeError> {
+        let is_and = operator == "and";
+        let what = if is_and { "operand of 'and'" } else { "operand of 'or'" };
+        let left = self.eval_condition(left, what)?;
+        // `false and _` and `true or _` never evaluate their right-hand side.
+        if left != is_and {
+            Ok(Value::Bool(left))
+        } else {
+            Ok(Value::Bool(self.eval_condition(right, what)?))
+        }
+    }
+
+    fn eval_unary(&mut self, operator: &str, operand: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(operand)?;
+        match (operator, &value) {
+            ("not", Value::Bool(b)) => Ok(Value::Bool(!b)),
+            ("-", _) => match negate(&value) {
+                Ok(value) => Ok(value),
+                Err(ArithmeticError::Overflow) => Err(self.fail(overflow(operator, span))),
+                Err(_) => Err(self.fail(unsupported_unary(operator, &value, span))),
+            },
+            _ => Err(self.fail(unsupported_unary(operator, &value, span))),
+        }
+    }
 
-            ASTNode::Ret { expression } => {
-                let value = self.interpret(expression);
-                Value::Return(Box::new(value))
+    fn ret(&mut self, expression: Option<&Node>) -> Result<Value, RuntimeError> {
+        let value = match expression {
+            Some(expression) => self.evaluate(expression)?,
+            None => Value::Void,
+        };
+        Ok(Value::Return(Box::new(value)))
+    }
+
+    fn if_else(&mut self, condition: &Node, then_block: &[Node], else_block: Option<&[Node]>) -> Result<Value, RuntimeError> {
+        if self.eval_condition(condition, "'if' condition")? {
+            self.execute_block(then_block)
+        } else if let Some(else_block) = else_block {
+            self.execute_block(else_block)
+        } else {
+            Ok(Value::Void)
+        }
+    }
+
+    fn while_loop(&mut self, condition: &Node, body: &[Node]) -> Result<Value, RuntimeError> {
+        while self.eval_condition(condition, "'while' condition")? {
+            let result = self.execute_block(body)?;
+            if let Value::Return(_) = result {
+                return Ok(result);
             }
+        }
+        Ok(Value::Void)
+    }
 
-            ASTNode::IfElse { condition, then_block, else_block } => {
-                let cond = self.interpret(condition);
-            
-                let truthy = match cond {
-                    Value::Bool(b) => b,
-                    _ => panic!("Runtime error: 'if' condition must be a boolean"),
-                };
-
-                let empty_block: Vec<ASTNode> = vec![];
-            
-                let block = if truthy {
-                    then_block 
-                } else {
-                    else_block.as_ref().unwrap_or(&empty_block)
-                };
-            
-                for stmt in block {
-                    let result = self.interpret(stmt);
-                    if let Value::Return(_) = result {
-                        return result;
-                    }
+    fn for_loop(&mut self, variable: &str, start: &Node, end: &Node, body: &[Node]) -> Result<Value, RuntimeError> {
+        let start_val = self.evaluate(start)?;
+        let end_val = self.evaluate(end)?;
+
+        let (start_i, end_i) = match (start_val, end_val) {
+            (Value::Int(s), Value::Int(e)) => (s, e),
+            _ => return Err(self.fail(invalid_for_range(start.span.to(end.span)))),
+        };
+
+        // The loop variable lives in a scope of its own around the body.
+        self.environment.push_scope();
+        let mut outcome = Ok(Value::Void);
+        for i in start_i..end_i {
+            self.environment.define(variable, Value::Int(i));
+            match self.execute_block(body) {
+                Ok(Value::Return(value)) => {
+                    outcome = Ok(Value::Return(value));
+                    break;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    outcome = Err(err);
+                    break;
                 }
-            
-                Value::Void
             }
+        }
+        self.environment.pop_scope();
+        outcome
+    }
 
-            ASTNode::Comparison { left, operator, right } => {
-                let l = self.interpret(left);
-                let r = self.interpret(right);
-            
-                match (l, r, operator.as_str()) {
-                    (Value::Int(a), Value::Int(b), "<") => Value::Bool(a < b),
-                    (Value::Int(a), Value::Int(b), ">") => Value::Bool(a > b),
-                    (Value::Int(a), Value::Int(b), "==") => Value::Bool(a == b),
-            
-                    (Value::Float(a), Value::Float(b), "<") => Value::Bool(a < b),
-                    (Value::Float(a), Value::Float(b), ">") => Value::Bool(a > b),
-                    (Value::Float(a), Value::Float(b), "==") => Value::Bool(a == b),
-            
-                    (Value::Bool(a), Value::Bool(b), "==") => Value::Bool(a == b),
-                    (Value::String(a), Value::String(b), "==") => Value::Bool(a == b),
-            
-                    _ => panic!("Invalid comparison between incompatible types"),
+    fn for_each(&mut self, variable: &str, array: &Node, body: &[Node]) -> Result<Value, RuntimeError> {
+        let elements = match self.evaluate(array)? {
+            Value::Array(elements) => elements,
+            value => return Err(self.fail(not_iterable(&value, array.span))),
+        };
+
+        // The length is checked on every iteration, since the body may change it.
+        self.environment.push_scope();
+        let mut outcome = Ok(Value::Void);
+        let mut position = 0;
+        while let Some(element) = elements.get(position) {
+            self.environment.define(variable, element);
+            match self.execute_block(body) {
+                Ok(Value::Return(value)) => {
+                    outcome = Ok(Value::Return(value));
+                    break;
                 }
-            }
-            
-            ASTNode::WhileLoop { condition, body } => {
-                loop {
-                    let cond_val = self.interpret(condition);
-                    let is_true = match cond_val {
-                        Value::Bool(b) => b,
-                        _ => panic!("Runtime error: while condition must be a boolean"),
-                    };
-            
-                    if !is_true {
-                        break;
-                    }
-            
-                    for stmt in body {
-                        let result = self.interpret(stmt);
-                        if let Value::Return(_) = result {
-                            return result;
-                        }
-                    }
+                Ok(_) => {}
+                Err(err) => {
+                    outcome = Err(err);
+                    break;
                 }
-            
-                Value::Void
             }
+            position += 1;
+        }
+        self.environment.pop_scope();
+        outcome
+    }
 
-            ASTNode::ForLoop { variable, start, end, body } => {
-                let start_val = self.interpret(start);
-                let end_val = self.interpret(end);
-            
-                let (start_i, end_i) = match (start_val, end_val) {
-                    (Value::Int(s), Value::Int(e)) => (s, e),
-                    _ => panic!("Runtime error: 'for' loop range must be integers"),
-                };
-            
-                let old_var = self.variables.get(variable).cloned();
-            
-                for i in start_i..end_i {
-                    self.variables.insert(variable.clone(), Value::Int(i));
-            
-                    for stmt in body {
-                        let result = self.interpret(stmt);
-                        if let Value::Return(_) = result {
-                            if let Some(v) = old_var {
-                                self.variables.insert(variable.clone(), v);
-                            } else {
-                                self.variables.remove(variable);
-                            }
-                            return result;
-                        }
-                    }
-                }
-            
-                if let Some(v) = old_var {
-                    self.variables.insert(variable.clone(), v);
-                } else {
-                    self.variables.remove(variable);
+    /// Evaluates call arguments or array elements, in order.
+    fn arguments(&mut self, args: &[Node]) -> Result<Vec<Value>, RuntimeError> {
+        let mut evaluated = Vec::with_capacity(args.len());
+        for arg in args {
+            evaluated.push(self.evaluate(arg)?);
+        }
+        Ok(evaluated)
+    }
+
+    fn function_call(&mut self, name: &str, args: &[Node], span: Span) -> Result<Value, RuntimeError> {
+        let args = self.arguments(args)?;
+        // Calling another method of the class passes `self` along. Whether
+        // `name` is a method depends on the class declaring the running
+        // method, and which method runs on the instance's class.
+        let method = self
+            .receiver()
+            .filter(|_| self.class.as_deref().is_some_and(|class| self.method(class, name).is_some()))
+            .and_then(|object| Some((self.method(object.class(), name)?, object)));
+        match method {
+            Some(((name, method), object)) => self.call(&name, &method, Some(Value::Object(object)), args, Some(span)),
+            None => self.invoke(name, args, Some(span)),
+        }
+    }
+
+    fn member_access(&mut self, object: &Node, member: &str, span: Span) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let (target, index) = self.field(&object, member, span)?;
+        Ok(target.get(index).expect("instances have every field of their class"))
+    }
+
+    fn field_assignment(&mut self, object: &Node, field: &str, expression: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(expression)?;
+        let object = self.evaluate(object)?;
+        let (target, index) = self.field(&object, field, span)?;
+        target.set(index, value);
+        Ok(Value::Void)
+    }
+
+    fn array_literal(&mut self, elements: &[Node]) -> Result<Value, RuntimeError> {
+        Ok(Value::Array(Array::new(self.arguments(elements)?)))
+    }
+
+    fn index(&mut self, object: &Node, index: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let (array, position) = self.element(object, index, span)?;
+        Ok(array.get(position).expect("the index was checked"))
+    }
+
+    fn index_assignment(&mut self, object: &Node, index: &Node, expression: &Node, span: Span) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(expression)?;
+        let (array, position) = self.element(object, index, span)?;
+        array.set(position, value);
+        Ok(Value::Void)
+    }
+
+    fn method_call(&mut self, object: &Node, method: &str, args: &[Node], span: Span) -> Result<Value, RuntimeError> {
+        let receiver = self.evaluate(object)?;
+        let mut args = self.arguments(args)?;
+        let found = match &receiver {
+            Value::Object(object) => self.method(object.class(), method),
+            Value::String(_) if builtins::STRING_METHODS.contains(&method) => {
+                if let Some(native) = self.natives.get(method) {
+                    args.insert(0, receiver);
+                    return native.call(&args, span).map_err(|err| self.fail(err));
                 }
-            
-                Value::Void
+                None
+            }
+            _ => None,
+        };
+        let Some((name, function)) = found else {
+            return Err(self.fail(no_member(method, &receiver, span)));
+        };
+        self.call(&name, &function, Some(receiver), args, Some(span))
+    }
+
+    fn super_call(&mut self, method: &str, args: &[Node], span: Span) -> Result<Value, RuntimeError> {
+        let args = self.arguments(args)?;
+        let receiver = self.self_value(span)?;
+        let base = self.class.as_deref().and_then(|class| self.classes.get(class)?.base.clone());
+        let Some((name, function)) = base.and_then(|base| self.method(&base, method)) else {
+            return Err(self.fail(no_member(method, &receiver, span)));
+        };
+        self.call(&name, &function, Some(receiver), args, Some(span))
+    }
+
+    fn new_instance(&mut self, class: &str, args: &[Node], span: Span) -> Result<Value, RuntimeError> {
+        let args = self.arguments(args)?;
+        if !self.classes.contains_key(class) {
+            return Err(self.fail(Diagnostic::error("E0312", format!("Unknown class '{}'", class), span)));
+        }
+        let lineage: Vec<&ClassInfo> = self.lineage(class).map(|(_, info)| info).collect();
+        let fields = lineage.iter().rev().flat_map(|info| &info.fields).map(|(_, field_type)| initial_value(field_type));
+        let object = Object::new(class, fields.collect());
+        match self.method(class, "init") {
+            Some((name, init)) => {
+                let receiver = Some(Value::Object(object.clone()));
+                self.call(&name, &init, receiver, args, Some(span))?;
+            }
+            None if args.is_empty() => {}
+            None => {
+                let name = format!("{}.new", class);
+                return Err(self.fail(arity_mismatch(&name, 0, args.len(), span)));
             }
+        }
+        Ok(Value::Object(object))
+    }
 
-            ASTNode::FunctionCall { name, args } => {
-                let evaluated_args: Vec<Value> = args.iter()
-                    .map(|arg| self.interpret(arg))
-                    .collect();
-            
-                self.call_function(name, evaluated_args)
-            }            
+    /// Evaluates `object[index]` to the array and the position of the element.
+    fn element(&mut self, object: &Node, index: &Node, span: Span) -> Result<(Array, usize), RuntimeError> {
+        let array = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        element(&array, &index).map_err(|err| self.fail(invalid_index(err, &array, &index, span)))
+    }
+
+    /// Runs `nodes` in a new block scope, stopping early at a `ret`.
+    fn execute_block(&mut self, nodes: &[Node]) -> Result<Value, RuntimeError> {
+        self.environment.push_scope();
+        let result = self.execute_statements(nodes);
+        self.environment.pop_scope();
+        result
+    }
 
-            _ => panic!("Interpretation not yet implemented for: {:?}", node),
+    fn execute_statements(&mut self, nodes: &[Node]) -> Result<Value, RuntimeError> {
+        for stmt in nodes {
+            let result = self.interpret(stmt)?;
+            if let Value::Return(_) = result {
+                return Ok(result);
+            }
         }
+        Ok(Value::Void)
     }
 
-    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Value {
-        let func = self.functions.get(name).cloned().expect(&format!("Function '{}' not found", name));
+    fn eval_condition(&mut self, condition: &Node, what: &str) -> Result<bool, RuntimeError> {
+        match self.evaluate(condition)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(self.fail(not_a_bool(what, &other, condition.span))),
+        }
+    }
 
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.invoke(name, args, None)
+    }
+
+    fn invoke(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+        call_site: Option<Span>,
+    ) -> Result<Value, RuntimeError> {
+        let span = call_site.unwrap_or_default();
+        let Some(func) = self.functions.get(name).cloned() else {
+            return match self.natives.get(name) {
+                Some(native) => native.call(&args, span).map_err(|err| self.fail(err)),
+                None => Err(self.fail(function_not_found(name, span))),
+            };
+        };
+        self.call(name, &func, None, args, call_site)
+    }
+
+    /// Runs a script function, or a method if `receiver` is the instance it was called on.
+    fn call(
+        &mut self,
+        name: &str,
+        func: &FunctionInfo,
+        receiver: Option<Value>,
+        args: Vec<Value>,
+        call_site: Option<Span>,
+    ) -> Result<Value, RuntimeError> {
+        let class = self.enter(name, func, receiver, args, call_site)?;
+        let result = self.execute_statements(&func.body);
+        self.leave(class);
+
+        match result? {
+            Value::Return(value) => Ok(*value),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    /// Checks the arguments and pushes the frames for a call of `func`,
+    /// returning the class of the calling method for `leave` to restore.
+    /// Kept out of `call` so none of this takes up stack space for as long as
+    /// the call runs.
+    fn enter(
+        &mut self,
+        name: &str,
+        func: &FunctionInfo,
+        receiver: Option<Value>,
+        args: Vec<Value>,
+        call_site: Option<Span>,
+    ) -> Result<Option<String>, RuntimeError> {
         if args.len() != func.params.len() {
-            panic!(
-                "Function '{}' expects {} arguments, got {}",
-                name, func.params.len(), args.len()
-            );
+            let span = call_site.unwrap_or_default();
+            return Err(self.fail(arity_mismatch(name, func.params.len(), args.len(), span)));
+        }
+        if self.call_stack.len() >= MAX_INTERPRETER_CALL_DEPTH {
+            return Err(self.fail(stack_overflow(MAX_INTERPRETER_CALL_DEPTH, call_site.unwrap_or_default())));
         }
+        let mut bindings: HashMap<String, Value> = func.params.iter().cloned().zip(args).collect();
+        if let Some(receiver) = receiver {
+            bindings.insert("self".to_string(), receiver);
+        }
+        self.call_stack.push(StackFrame { function: name.to_string(), call_site });
+        self.environment.push_frame(bindings);
+        Ok(std::mem::replace(&mut self.class, func.class.clone()))
+    }
 
-        let old_vars = self.variables.clone();
+    fn leave(&mut self, class: Option<String>) {
+        self.class = class;
+        self.environment.pop_frame();
+        self.call_stack.pop();
+    }
 
-        for (param, arg) in func.params.iter().zip(args) {
-            self.variables.insert(param.clone(), arg);
+    /// The instance the running method was called on.
+    fn receiver(&self) -> Option<Object> {
+        match self.environment.get_local("self")? {
+            Value::Object(object) => Some(object.clone()),
+            _ => None,
         }
+    }
 
-        let mut return_value = Value::Void;
+    /// `class` followed by its base classes, nearest first.
+    fn lineage<'a>(&'a self, class: &str) -> impl Iterator<Item = (&'a str, &'a ClassInfo)> {
+        let first = self.classes.get_key_value(class);
+        std::iter::successors(first, |(_, info)| self.classes.get_key_value(info.base.as_deref()?))
+            .map(|(name, info)| (name.as_str(), info.as_ref()))
+            // Checked programs can't have inheritance cycles, this only keeps unchecked ones from hanging.
+            .take(self.classes.len())
+    }
 
-        let body = func.body.clone();
+    /// Finds the method `name` of `class`, declared by it or inherited, along
+    /// with the name its stack frames are given.
+    fn method(&self, class: &str, name: &str) -> Option<(String, Rc<FunctionInfo>)> {
+        self.lineage(class).find_map(|(class, info)| {
+            let method = info.methods.get(name)?;
+            Some((format!("{}.{}", class, name), Rc::clone(method)))
+        })
+    }
 
-        for stmt in &body {
-            let value = self.interpret(stmt);
-            if let Value::Return(inner) = value {
-                return_value = *inner;
-                break;
-            }
+    /// Where an instance of `class` stores its field `name`. Inherited fields come first.
+    fn field_index(&self, class: &str, name: &str) -> Option<usize> {
+        let lineage: Vec<&ClassInfo> = self.lineage(class).map(|(_, info)| info).collect();
+        lineage.iter().rev().flat_map(|info| &info.fields).position(|(field, _)| field == name)
+    }
+
+    /// Finds the field `name` of `value`, failing unless it is an object with such a field.
+    fn field(&self, value: &Value, name: &str, span: Span) -> Result<(Object, usize), RuntimeError> {
+        let index = match value {
+            Value::Object(object) => self.field_index(object.class(), name),
+            _ => None,
+        };
+        match (value, index) {
+            (Value::Object(object), Some(index)) => Ok((object.clone(), index)),
+            _ => Err(self.fail(no_member(name, value, span))),
         }
+    }
+
+    /// The field `name` of the running method's instance, unless a local variable shadows it.
+    /// Only fields of the class declaring the method count, not those of a
+    /// subclass the instance may belong to, which come after them.
+    fn self_field(&self, name: &str) -> Option<(Object, usize)> {
+        if self.environment.get_local(name).is_some() {
+            return None;
+        }
+        let object = self.receiver()?;
+        let index = self.field_index(self.class.as_deref()?, name)?;
+        Some((object, index))
+    }
+}
+
+/// The value of a literal node.
+fn literal(kind: &ASTNode) -> Value {
+    match kind {
+        ASTNode::Number(n) => Value::Int(*n),
+        ASTNode::Float(f) => Value::Float(*f),
+        ASTNode::Boolean(b) => Value::Bool(*b),
+        ASTNode::StringLiteral(s) => Value::String(s.clone()),
+        _ => unreachable!("only literals are passed in"),
+    }
+}
 
-        self.variables = old_vars;
-        return_value
+/// The value a variable or field declared as `declared` holds until one is
+/// assigned: arrays start out empty, so they can be pushed to right away.
+fn initial_value(declared: &Type) -> Value {
+    match declared {
+        Type::Array(_) => Value::Array(Array::new(Vec::new())),
+        _ => Value::Void,
     }
 }
@@ -1,3 +1,8 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+
 #[derive(Debug, PartialEq, Clone)]
 
 pub enum Token {
@@ -20,49 +25,320 @@ pub enum Token {
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
+    Dot,
     Then,
     End,
     Ret,
     Loop,
     For,
+    In,
     While,
     Boolean(bool),
     StringLiteral(String),
+    /// A string literal with `{expression}`s embedded in it.
+    InterpolatedString(Vec<StringPart>),
     LessThan,
     GreaterThan,
+    LessEqual,
+    GreaterEqual,
+    NotEqual,
+    Modulo,
+    And,
+    Or,
+    Not,
+    /// `self`, the instance a method was called on.
+    SelfRef,
+    Extends,
+    Super,
+    Interface,
+    Implements,
     EOF,
 }
 
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Identifier(name) => return write!(f, "identifier '{}'", name),
+            Token::Number(value) => return write!(f, "number {}", value),
+            Token::Float(value) => return write!(f, "float {}", value),
+            Token::Type(name) => return write!(f, "type '{}'", name),
+            Token::Boolean(value) => return write!(f, "'{}'", value),
+            Token::StringLiteral(value) => return write!(f, "string {:?}", value),
+            Token::InterpolatedString(_) => return write!(f, "interpolated string"),
+            Token::EOF => return write!(f, "end of file"),
+            Token::Class => "class",
+            Token::Func => "func",
+            Token::Var => "var",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Assign => "=",
+            Token::Equal => "==",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Then => "then",
+            Token::End => "end",
+            Token::Ret => "ret",
+            Token::Loop => "loop",
+            Token::For => "for",
+            Token::In => "in",
+            Token::While => "while",
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::LessEqual => "<=",
+            Token::GreaterEqual => ">=",
+            Token::NotEqual => "!=",
+            Token::Modulo => "%",
+            Token::And => "and",
+            Token::Or => "or",
+            Token::Not => "not",
+            Token::SelfRef => "self",
+            Token::Extends => "extends",
+            Token::Super => "super",
+            Token::Interface => "interface",
+            Token::Implements => "implements",
+        };
+        write!(f, "'{}'", text)
+    }
+}
+
+/// A piece of an interpolated string literal.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Text(String),
+    /// The source of an embedded expression and where it starts, for the
+    /// parser to lex and parse in place.
+    Code(String, Span),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
 pub struct Lexer<'a> {
     source: std::str::Chars<'a>,
     current_char: Option<char>,
+    offset: usize,
+    line: usize,
+    column: usize,
+    /// Errors in tokens that were still produced, such as invalid escapes.
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
-        let mut lexer = Lexer {
-            source: source.chars(),
-            current_char: None,
+        Self::starting_at(source, Span::new(0, 0, 1, 1))
+    }
+
+    /// Lexes `source` as if it appeared in a larger file at `start`, so spans
+    /// point into that file. Used for expressions embedded in strings.
+    pub fn starting_at(source: &'a str, start: Span) -> Self {
+        let mut chars = source.chars();
+        let current_char = chars.next();
+        Lexer {
+            source: chars,
+            current_char,
+            offset: start.start,
+            line: start.line,
+            column: start.column,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Takes the errors found in tokens that were lexed anyway, which
+    /// `get_next_token` doesn't return as they don't stop the token.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source.clone().next()
+    }
+
+    fn at_triple_quote(&self) -> bool {
+        self.current_char == Some('"') && self.source.as_str().starts_with("\"\"")
+    }
+
+    /// Reads a string literal starting at its opening quote, after any `r` prefix.
+    ///
+    /// `"..."` strings can't span lines and process escapes; `"""..."""`
+    /// strings can, dropping a newline right after the opening quotes. Raw
+    /// strings (`r"..."`, `r"""..."""`) keep backslashes as they are. An
+    /// invalid escape is recorded for `take_diagnostics` and read as U+FFFD,
+    /// so the literal is still a token and only the escape is reported.
+    ///
+    /// Strings other than raw strings interpolate `{expression}`s, and `{{`
+    /// and `}}` stand for a literal brace, like `\{` and `\}`. `{}` is kept
+    /// as it is so it can be used in `format` templates.
+    fn read_string(&mut self, start: Span, raw: bool) -> Result<Token, Diagnostic> {
+        let triple = self.at_triple_quote();
+        let quotes = if triple { 3 } else { 1 };
+        for _ in 0..quotes {
+            self.advance();
+        }
+        if triple && self.current_char == Some('\n') {
+            self.advance();
+        }
+        let opening = Span { end: start.start + quotes + raw as usize, ..start };
+        let unterminated = || Diagnostic::error("E0003", "Unterminated string literal", opening);
+
+        let mut parts = Vec::new();
+        let mut string = String::new();
+        loop {
+            match self.current_char {
+                None => return Err(unterminated()),
+                Some('\n') if !triple => {
+                    return Err(unterminated()
+                        .with_note("use \"\"\"triple quotes\"\"\" for a string that spans several lines"));
+                }
+                Some('"') if !triple || self.at_triple_quote() => {
+                    for _ in 0..quotes {
+                        self.advance();
+                    }
+                    break;
+                }
+                Some('\\') if !raw && self.peek().is_some() => match self.read_escape() {
+                    Ok(c) => string.push(c),
+                    Err(err) => {
+                        self.diagnostics.push(err);
+                        string.push(char::REPLACEMENT_CHARACTER);
+                    }
+                },
+                Some(brace @ ('{' | '}')) if !raw && self.peek() == Some(brace) => {
+                    string.push(brace);
+                    self.advance();
+                    self.advance();
+                }
+                Some('{') if !raw && self.peek() != Some('}') => {
+                    parts.push(StringPart::Text(std::mem::take(&mut string)));
+                    parts.push(self.read_interpolation(triple)?);
+                }
+                Some(c) => {
+                    string.push(c);
+                    self.advance();
+                }
+            }
+        }
+        if parts.is_empty() {
+            return Ok(Token::StringLiteral(string));
+        }
+        parts.push(StringPart::Text(string));
+        parts.retain(|part| part != &StringPart::Text(String::new()));
+        Ok(Token::InterpolatedString(parts))
+    }
+
+    /// Reads a `{expression}` in a string, starting at its `{`. The expression
+    /// may contain strings of its own, but not line breaks unless the string
+    /// it is in is triple-quoted.
+    fn read_interpolation(&mut self, triple: bool) -> Result<StringPart, Diagnostic> {
+        let brace = Span::new(self.offset, self.offset + 1, self.line, self.column);
+        self.advance();
+        let start = Span::new(self.offset, self.offset, self.line, self.column);
+        let mut code = String::new();
+        let mut depth = 0;
+        let mut in_string = false;
+        loop {
+            let c = match self.current_char {
+                Some('\n') if !triple => None,
+                c => c,
+            };
+            let Some(c) = c else {
+                return Err(Diagnostic::error("E0005", "Unclosed '{' in string interpolation", brace)
+                    .with_note("write {{ or \\{ for a literal brace"));
+            };
+            match c {
+                '}' if depth == 0 && !in_string => {
+                    self.advance();
+                    return Ok(StringPart::Code(code, start));
+                }
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => depth -= 1,
+                '"' => in_string = !in_string,
+                '\\' if in_string => {
+                    code.push(c);
+                    self.advance();
+                    if let Some(escaped) = self.current_char.filter(|c| *c != '\n') {
+                        code.push(escaped);
+                        self.advance();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            code.push(c);
+            self.advance();
+        }
+    }
+
+    /// Reads an escape sequence starting at its backslash.
+    fn read_escape(&mut self) -> Result<char, Diagnostic> {
+        let start = Span::new(self.offset, self.offset, self.line, self.column);
+        self.advance();
+        let escaped = match self.current_char {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('{') => '{',
+            Some('}') => '}',
+            Some('u') => return self.read_unicode_escape(start),
+            Some(c) => {
+                // A line break can't be part of the escape, or the string couldn't end on this line.
+                if c != '\n' {
+                    self.advance();
+                }
+                return Err(Diagnostic::error(
+                    "E0004",
+                    format!("Unknown escape sequence '\\{}'", c.escape_default()),
+                    self.span_from(start),
+                )
+                .with_note("valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\', \\{, \\} and \\u{...}"));
+            }
+            None => unreachable!("read_string only reads escapes that have a character after the backslash"),
         };
-        lexer.advance();
-        lexer
+        self.advance();
+        Ok(escaped)
     }
 
-    fn read_string(&mut self) -> String {
+    /// Reads the rest of a `\u{...}` escape of one to six hex digits.
+    fn read_unicode_escape(&mut self, start: Span) -> Result<char, Diagnostic> {
         self.advance();
-        let mut string = String::new();
-        while let Some(c) = self.current_char {
-            if c == '"' {
+        let mut digits = String::new();
+        let mut closed = false;
+        if self.current_char == Some('{') {
+            self.advance();
+            while let Some(c) = self.current_char.filter(|c| c.is_ascii_hexdigit()) {
+                digits.push(c);
                 self.advance();
-                break;
-            } else {
-                string.push(c);
+            }
+            if self.current_char == Some('}') {
                 self.advance();
+                closed = true;
             }
         }
-        string
-    }    
+        let code_point = u32::from_str_radix(&digits, 16).ok().filter(|_| closed && digits.len() <= 6);
+        code_point.and_then(char::from_u32).ok_or_else(|| {
+            Diagnostic::error("E0004", "Invalid unicode escape", self.span_from(start))
+                .with_note("write unicode escapes as \\u{...} with one to six hex digits naming a valid code point")
+        })
+    }
 
     fn skip_comment(&mut self) {
         while let Some(c) = self.current_char {
@@ -74,6 +350,15 @@ impl<'a> Lexer<'a> {
     }    
 
     fn advance(&mut self) {
+        if let Some(c) = self.current_char {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
         self.current_char = self.source.next();
     }
 
@@ -100,13 +385,57 @@ impl<'a> Lexer<'a> {
         identifier
     }
 
-    pub fn get_next_token(&mut self) -> Token {
+    pub fn get_next_token(&mut self) -> Result<SpannedToken, Diagnostic> {
         self.skip_whitespace();
         while let Some('#') = self.current_char {
             self.skip_comment();
             self.skip_whitespace();
         }
-        match self.current_char {
+        let start = Span::new(self.offset, self.offset, self.line, self.column);
+        let token = self.read_token(start)?;
+        Ok(SpannedToken {
+            token,
+            span: self.span_from(start),
+        })
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        Span { end: self.offset, ..start }
+    }
+
+    fn read_number(&mut self, start: Span) -> Result<Token, Diagnostic> {
+        let mut number = String::new();
+        while let Some(c) = self.current_char.filter(|c| c.is_ascii_digit()) {
+            number.push(c);
+            self.advance();
+        }
+        let is_float = self.current_char == Some('.');
+        if is_float {
+            number.push('.');
+            self.advance();
+            while let Some(c) = self.current_char.filter(|c| c.is_ascii_digit()) {
+                number.push(c);
+                self.advance();
+            }
+        }
+        let span = self.span_from(start);
+        if is_float {
+            number.parse::<f64>().map(Token::Float).map_err(|_| {
+                Diagnostic::error("E0002", format!("Invalid float literal '{}'", number), span)
+            })
+        } else {
+            number.parse::<i64>().map(Token::Number).map_err(|_| {
+                Diagnostic::error("E0002", format!("Integer literal '{}' is out of range", number), span)
+            })
+        }
+    }
+
+    fn read_token(&mut self, start: Span) -> Result<Token, Diagnostic> {
+        let token = match self.current_char {
+            Some('r') if self.peek() == Some('"') => {
+                self.advance();
+                self.read_string(start, true)?
+            }
             Some(c) if c.is_alphabetic() => {
                 let identifier = self.read_identifier();
                 match identifier.as_str() {
@@ -117,66 +446,31 @@ impl<'a> Lexer<'a> {
                     "end" => Token::End,
                     "ret" => Token::Ret,
                     "for" => Token::For,
+                    "in" => Token::In,
                     "while" => Token::While,
                     "loop" => Token::Loop,
                     "true" => Token::Boolean(true),
                     "false" => Token::Boolean(false),
                     "if" => Token::If,
                     "else" => Token::Else,
-                    "int" | "float" | "string" | "bool" => Token::Type(identifier),
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "self" => Token::SelfRef,
+                    "extends" => Token::Extends,
+                    "super" => Token::Super,
+                    "interface" => Token::Interface,
+                    "implements" => Token::Implements,
+                    "int" | "float" | "string" | "bool" | "array" => Token::Type(identifier),
                     _ => Token::Identifier(identifier),
                 }
             }
             Some('-') => {
                 self.advance();
-                match self.current_char {
-                    Some(c) if c.is_digit(10) => {
-                        let mut number = String::from("-");
-                        while let Some(d) = self.current_char.and_then(|c| c.to_digit(10)) {
-                            number.push(std::char::from_digit(d, 10).unwrap());
-                            self.advance();
-                        }
-                        if self.current_char == Some('.') {
-                            number.push('.');
-                            self.advance();
-                            while let Some(d) = self.current_char.and_then(|c| c.to_digit(10)) {
-                                number.push(std::char::from_digit(d, 10).unwrap());
-                                self.advance();
-                            }
-                            let value = number.parse::<f64>().unwrap();
-                            return Token::Float(value);
-                        } else {
-                            let value = number.parse::<i64>().unwrap();
-                            return Token::Number(value);
-                        }
-                    }
-                    _ => Token::Minus,
-                }
-            }
-            Some(c) if c.is_digit(10) => {
-                let mut number = String::new();            
-                while let Some(d) = self.current_char.and_then(|c| c.to_digit(10)) {
-                    number.push(std::char::from_digit(d, 10).unwrap());
-                    self.advance();
-                }            
-                if self.current_char == Some('.') {
-                    number.push('.');
-                    self.advance();            
-                    while let Some(d) = self.current_char.and_then(|c| c.to_digit(10)) {
-                        number.push(std::char::from_digit(d, 10).unwrap());
-                        self.advance();
-                    }
-                    let value = number.parse::<f64>().unwrap();
-                    Token::Float(value)
-                } else {
-                    let value = number.parse::<i64>().unwrap();
-                    Token::Number(value)
-                }
-            }
-            Some('"') => {
-                let string = self.read_string();
-                Token::StringLiteral(string)
+                Token::Minus
             }
+            Some(c) if c.is_ascii_digit() => self.read_number(start)?,
+            Some('"') => self.read_string(start, false)?,
             Some('=') => {
                 self.advance();
                 if self.current_char == Some('=') {
@@ -198,6 +492,20 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Token::Divide
             }
+            Some('%') => {
+                self.advance();
+                Token::Modulo
+            }
+            Some('!') => {
+                self.advance();
+                if self.current_char == Some('=') {
+                    self.advance();
+                    Token::NotEqual
+                } else {
+                    return Err(Diagnostic::error("E0001", "Unrecognized character: '!'", self.span_from(start))
+                        .with_note("use 'not' for logical negation"));
+                }
+            }
             Some('(') => {
                 self.advance();
                 Token::LParen
@@ -214,20 +522,50 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Token::RBrace
             }
+            Some('[') => {
+                self.advance();
+                Token::LBracket
+            }
+            Some(']') => {
+                self.advance();
+                Token::RBracket
+            }
             Some(',') => {
                 self.advance();
                 Token::Comma
             }
+            Some('.') => {
+                self.advance();
+                Token::Dot
+            }
             Some('<') => {
                 self.advance();
-                Token::LessThan
+                if self.current_char == Some('=') {
+                    self.advance();
+                    Token::LessEqual
+                } else {
+                    Token::LessThan
+                }
             }
             Some('>') => {
                 self.advance();
-                Token::GreaterThan
+                if self.current_char == Some('=') {
+                    self.advance();
+                    Token::GreaterEqual
+                } else {
+                    Token::GreaterThan
+                }
             }
             None => Token::EOF,
-            _ => panic!("Unrecognized character: {:?}", self.current_char),
-        }
+            Some(c) => {
+                self.advance();
+                return Err(Diagnostic::error(
+                    "E0001",
+                    format!("Unrecognized character: {:?}", c),
+                    self.span_from(start),
+                ));
+            }
+        };
+        Ok(token)
     }
 }
@@ -1,6 +1,21 @@
+// Diagnostics are only built on the error path, so their size doesn't matter for `Result`s.
+#![allow(clippy::result_large_err)]
+
 pub mod lexer;
 pub mod parser;
 pub mod ast;
 pub mod semantic;
 pub mod symbol_table;
-pub mod interpreter;
\ No newline at end of file
+pub mod interpreter;
+pub mod value;
+pub mod runtime_error;
+pub mod native;
+pub mod builtins;
+pub mod bytecode;
+pub mod compiler;
+pub mod vm;
+pub mod bytecode_file;
+pub mod disassembler;
+pub mod environment;
+pub mod span;
+pub mod diagnostic;
\ No newline at end of file
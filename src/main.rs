@@ -1,33 +1,139 @@
 use std::env;
 use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
 
+use neutron::ast::Node;
+use neutron::builtins::{self, Output};
+use neutron::bytecode::Program;
+use neutron::bytecode_file;
+use neutron::diagnostic::Diagnostic;
 use neutron::lexer::Lexer;
 use neutron::parser::Parser;
 use neutron::semantic::analyze;
 use neutron::symbol_table::SymbolTable;
-use neutron::interpreter::Interpreter;
+use neutron::compiler::compile_with_natives;
+use neutron::disassembler::disassemble;
+use neutron::native::Natives;
+use neutron::vm::Vm;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: neutron <source_file.neutron>");
+const USAGE: &str = "Usage: neutron [run|check|build|disassemble] <file.neutron|file.nbc>";
+
+fn use_color() -> bool {
+    std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+fn report(filename: &str, source: &str, diagnostics: &[Diagnostic]) {
+    let color = use_color();
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(filename, source, color));
+    }
+}
+
+fn fail(filename: &str, source: &str, diagnostics: &[Diagnostic]) -> ! {
+    report(filename, source, diagnostics);
+    std::process::exit(1);
+}
+
+/// Parses and type checks `source`, exiting with every diagnostic found on failure.
+fn check(filename: &str, source: &str, natives: &Natives) -> Node {
+    let mut parser = Parser::new(Lexer::new(source));
+    let ast = parser.parse_program().unwrap_or_else(|errors| fail(filename, source, &errors));
+
+    let mut symbol_table = SymbolTable::with_natives(natives);
+    if let Err(errors) = analyze(&ast, &mut symbol_table) {
+        let count = errors.len();
+        report(filename, source, &errors);
+        eprintln!("❌ {} error{} found", count, if count == 1 { "" } else { "s" });
         std::process::exit(1);
     }
+    ast
+}
+
+/// Checks and compiles `source`, exiting with diagnostics on failure.
+fn build(filename: &str, source: &str, natives: &Natives) -> Program {
+    let ast = check(filename, source, natives);
+    compile_with_natives(&ast, natives).unwrap_or_else(|err| fail(filename, source, &[err]))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (command, filename) = match args.as_slice() {
+        [_, file] => ("run", file),
+        [_, command, file] => (command.as_str(), file),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    let filename = &args[1];
-    let source = fs::read_to_string(filename)
-        .expect("Could not read source file.");
+    let bytes = fs::read(filename).unwrap_or_else(|err| {
+        eprintln!("Could not read file '{}': {}", filename, err);
+        std::process::exit(1);
+    });
 
-    let mut parser = Parser::new(Lexer::new(&source));
-    let ast = parser.parse_program();
+    let natives = builtins::natives(Output::stdout());
 
-    let mut symbol_table = SymbolTable::new();
-    analyze(&ast, &mut symbol_table);
+    // Bytecode files have no source to check or point diagnostics into.
+    if bytecode_file::is_bytecode(&bytes) {
+        let program = bytecode_file::read(&bytes).unwrap_or_else(|err| {
+            eprintln!("Could not load '{}': {}", filename, err);
+            std::process::exit(1);
+        });
+        match command {
+            "run" => {
+                println!("🧠 Running program:");
+                let result = Vm::with_natives(&program, &natives).run().unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                });
+                println!("✅ Result: {:?}", result);
+            }
+            "disassemble" => print!("{}", disassemble(&program)),
+            _ => {
+                eprintln!("'{}' needs a source file, but '{}' is compiled bytecode", command, filename);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    println!("✅ Program is valid!");
+    let source = String::from_utf8(bytes).unwrap_or_else(|_| {
+        eprintln!("Source file '{}' is not valid UTF-8", filename);
+        std::process::exit(1);
+    });
 
-    let mut interpreter = Interpreter::new();
-    println!("🧠 Running program:");
-    let result = interpreter.interpret(&ast);
-    println!("✅ Result: {:?}", result);
+    match command {
+        "check" => {
+            check(filename, &source, &natives);
+            println!("✅ Program is valid!");
+        }
+        "run" => {
+            let program = build(filename, &source, &natives);
+            println!("✅ Program is valid!");
+            println!("🧠 Running program:");
+            let result = Vm::with_natives(&program, &natives).run().unwrap_or_else(|err| {
+                eprintln!("{}", err.render(filename, &source, use_color()));
+                std::process::exit(1);
+            });
+            println!("✅ Result: {:?}", result);
+        }
+        "build" => {
+            let program = build(filename, &source, &natives);
+            let output = Path::new(filename).with_extension("nbc");
+            fs::write(&output, bytecode_file::write(&program)).unwrap_or_else(|err| {
+                eprintln!("Could not write '{}': {}", output.display(), err);
+                std::process::exit(1);
+            });
+            println!("📦 Wrote {}", output.display());
+        }
+        "disassemble" => {
+            let program = build(filename, &source, &natives);
+            print!("{}", disassemble(&program));
+        }
+        _ => {
+            eprintln!("Unknown command '{}'\n{}", command, USAGE);
+            std::process::exit(1);
+        }
+    }
 }
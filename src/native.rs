@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+use crate::runtime_error::{arity_mismatch, native_argument_mismatch, native_failed, too_few_arguments};
+use crate::span::Span;
+use crate::symbol_table::{FunctionSignature, Type};
+use crate::value::Value;
+
+/// A Rust type that can cross into and out of scripts.
+pub trait NativeType: Sized {
+    /// The script type this converts to and from.
+    const TYPE: Type;
+
+    fn from_value(value: &Value) -> Option<Self>;
+    fn into_value(self) -> Value;
+}
+
+impl NativeType for i64 {
+    const TYPE: Type = Type::Int;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl NativeType for f64 {
+    const TYPE: Type = Type::Float;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl NativeType for bool {
+    const TYPE: Type = Type::Bool;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl NativeType for String {
+    const TYPE: Type = Type::String;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl NativeType for () {
+    const TYPE: Type = Type::Void;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Void => Some(()),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Void
+    }
+}
+
+/// What a native function may return: a value, or a `Result` whose error is reported as a runtime error.
+pub trait NativeReturn {
+    const TYPE: Type;
+
+    fn into_result(self) -> Result<Value, String>;
+}
+
+impl<T: NativeType> NativeReturn for T {
+    const TYPE: Type = T::TYPE;
+
+    fn into_result(self) -> Result<Value, String> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: NativeType> NativeReturn for Result<T, String> {
+    const TYPE: Type = T::TYPE;
+
+    fn into_result(self) -> Result<Value, String> {
+        self.map(NativeType::into_value)
+    }
+}
+
+type NativeFn = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/// A Rust closure that can be registered as a native function. Implemented
+/// for closures of up to four [`NativeType`] arguments returning a
+/// [`NativeReturn`]; `Args` only tells the arities apart.
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: NativeReturn,
+            $($arg: NativeType,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let signature = FunctionSignature {
+                    params: vec![$($arg::TYPE),*],
+                    return_type: R::TYPE,
+                    variadic: false,
+                };
+                // `NativeFunction::call` checks the arguments against the signature first.
+                let function = move |args: &[Value]| {
+                    let mut args = args.iter();
+                    $(
+                        let $arg = args.next().and_then($arg::from_value).expect("arguments were checked");
+                    )*
+                    self($($arg),*).into_result()
+                };
+                NativeFunction { name: name.to_string(), signature, function: Rc::new(function) }
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+
+/// A function implemented in Rust by the host and callable from scripts.
+pub struct NativeFunction {
+    pub name: String,
+    pub signature: FunctionSignature,
+    function: NativeFn,
+}
+
+impl NativeFunction {
+    /// Checks the arguments against the signature and calls the function,
+    /// reporting wrong arguments or a failure at `span`.
+    pub(crate) fn call(&self, args: &[Value], span: Span) -> Result<Value, Diagnostic> {
+        let signature = &self.signature;
+        if !signature.accepts(args.len()) {
+            let expected = signature.params.len();
+            return Err(if signature.variadic {
+                too_few_arguments(&self.name, expected - 1, args.len(), span)
+            } else {
+                arity_mismatch(&self.name, expected, args.len(), span)
+            });
+        }
+        for (index, arg) in args.iter().enumerate() {
+            let expected = signature.param(index).expect("the argument count was checked");
+            if !has_type(arg, expected) {
+                return Err(native_argument_mismatch(&self.name, index, expected, arg, span));
+            }
+        }
+        (self.function)(args).map_err(|message| native_failed(&self.name, &message, span))
+    }
+}
+
+fn has_type(value: &Value, expected: &Type) -> bool {
+    match (value, expected) {
+        (Value::Void, _) => false,
+        (_, Type::Any) => true,
+        (Value::Int(_), Type::Int)
+        | (Value::Float(_), Type::Float)
+        | (Value::Bool(_), Type::Bool)
+        | (Value::String(_), Type::String) => true,
+        // The array builtins take `array<any>`: looking at every element on each call would make them linear.
+        (Value::Array(_), Type::Array(element)) if **element == Type::Any => true,
+        (Value::Array(array), Type::Array(element)) => array.elements().iter().all(|value| has_type(value, element)),
+        _ => false,
+    }
+}
+
+/// The native functions a host makes available to scripts.
+///
+/// The same registry is given to the [`SymbolTable`](crate::symbol_table::SymbolTable)
+/// so calls are type checked, and to the interpreter or VM that runs the
+/// program. Functions declared by the script shadow natives of the same name.
+#[derive(Clone, Default)]
+pub struct Natives {
+    functions: Vec<Rc<NativeFunction>>,
+    indices: HashMap<String, usize>,
+}
+
+impl Natives {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `function` under `name`, replacing any native already called that,
+    /// e.g. `natives.register_fn("spawn", |x: f64, y: f64| -> i64 { ... })`.
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        self.insert(function.into_native(name));
+    }
+
+    /// Registers a function that takes its arguments as [`Value`]s, for
+    /// signatures a closure can't express, such as `any` or variadic
+    /// parameters. The arguments are checked against `signature` before
+    /// `function` is called.
+    pub fn register_raw(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        self.insert(NativeFunction { name: name.to_string(), signature, function: Rc::new(function) });
+    }
+
+    fn insert(&mut self, native: NativeFunction) {
+        let name = native.name.clone();
+        let native = Rc::new(native);
+        match self.indices.get(&name) {
+            Some(&index) => self.functions[index] = native,
+            None => {
+                self.indices.insert(name, self.functions.len());
+                self.functions.push(native);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<NativeFunction>> {
+        self.indices.get(name).map(|&index| &self.functions[index])
+    }
+
+    /// The registered functions, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<NativeFunction>> {
+        self.functions.iter()
+    }
+}
@@ -1,355 +1,686 @@
-use crate::lexer::{Lexer, Token};
-use crate::ast::ASTNode;
-use crate::symbol_table::{SymbolTable, Type};
+use crate::lexer::{Lexer, SpannedToken, StringPart, Token};
+use crate::ast::{ASTNode, MethodSignature, Node, Parameter};
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+use crate::symbol_table::Type;
 
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Token,
-    symbol_table: SymbolTable,
+    current_span: Span,
+    previous_span: Span,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(mut lexer: Lexer<'a>) -> Self {
-        let current_token = lexer.get_next_token();
-        Parser { lexer, current_token, symbol_table: SymbolTable::new(), }
+    pub fn new(lexer: Lexer<'a>) -> Self {
+        Parser {
+            lexer,
+            current_token: Token::EOF,
+            current_span: Span::default(),
+            previous_span: Span::default(),
+            diagnostics: Vec::new(),
+        }
     }
 
+    /// Moves to the next token. Lexer errors are recorded and the offending
+    /// characters skipped, so the parser only ever sees valid tokens.
     fn advance(&mut self) {
-        self.current_token = self.lexer.get_next_token();
+        loop {
+            let next = self.lexer.get_next_token();
+            self.diagnostics.append(&mut self.lexer.take_diagnostics());
+            match next {
+                Ok(SpannedToken { token, span }) => {
+                    self.previous_span = self.current_span;
+                    self.current_token = token;
+                    self.current_span = span;
+                    return;
+                }
+                Err(err) => self.diagnostics.push(err),
+            }
+        }
     }
 
-    fn consume_token(&mut self, token: Token) {
+    fn consume_token(&mut self, token: Token) -> Result<(), Diagnostic> {
         if self.current_token == token {
             self.advance();
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        Diagnostic::error(
+            "E0100",
+            format!("Unexpected {}, expected {}", self.current_token, expected),
+            self.current_span,
+        )
+    }
+
+    fn expect_identifier(&mut self, what: &str) -> Result<String, Diagnostic> {
+        if let Token::Identifier(name) = self.current_token.clone() {
+            self.advance();
+            Ok(name)
         } else {
-            panic!("Unexpected token: {:?}, expected {:?}", self.current_token, token);
+            Err(Diagnostic::error(
+                "E0101",
+                format!("Expected {}, found {}", what, self.current_token),
+                self.current_span,
+            ))
         }
     }
 
-    pub fn parse_program(&mut self) -> ASTNode {
-        let mut nodes = Vec::new();
-        while self.current_token != Token::EOF {
-            nodes.push(self.parse_statement());
+    /// Builds a node spanning from `start` to the last consumed token.
+    fn node(&self, kind: ASTNode, start: Span) -> Node {
+        Node::new(kind, start.to(self.previous_span))
+    }
+
+    /// Parses a whole program, failing with every syntax error found.
+    pub fn parse_program(&mut self) -> Result<Node, Vec<Diagnostic>> {
+        let (ast, diagnostics) = self.parse_with_recovery();
+        if diagnostics.is_empty() {
+            Ok(ast)
+        } else {
+            Err(diagnostics)
         }
-        ASTNode::Program(nodes)
     }
 
-    fn parse_statement(&mut self) -> ASTNode {
-        println!("Parsing statement: {:?}", self.current_token);
+    /// Parses a whole program without stopping at syntax errors. Statements
+    /// that fail to parse are replaced by `ASTNode::Error` nodes, so tooling
+    /// always gets a best-effort tree alongside the diagnostics.
+    pub fn parse_with_recovery(&mut self) -> (Node, Vec<Diagnostic>) {
+        self.advance();
+        let start = self.current_span;
+        let nodes = self.parse_block(&[]);
+        let ast = self.node(ASTNode::Program(nodes), start);
+        (ast, std::mem::take(&mut self.diagnostics))
+    }
+
+    /// Parses statements until one of `terminators` (or the end of input) is reached,
+    /// recovering from errors in individual statements.
+    fn parse_block(&mut self, terminators: &[Token]) -> Vec<Node> {
+        let mut block = Vec::new();
+        while self.current_token != Token::EOF && !terminators.contains(&self.current_token) {
+            let start = self.current_span;
+            match self.parse_statement() {
+                Ok(node) => block.push(node),
+                Err(err) => {
+                    self.diagnostics.push(err);
+                    if self.current_span == start {
+                        self.advance();
+                    }
+                    self.synchronize(start);
+                    block.push(self.node(ASTNode::Error, start));
+                }
+            }
+        }
+        block
+    }
+
+    /// Skips tokens until one that can start or close a statement. Identifiers
+    /// only count when they begin a new line, since mid-line they are most
+    /// likely part of the broken expression.
+    fn synchronize(&mut self, start: Span) {
+        let mut line = start.line.max(self.previous_span.line);
+        loop {
+            match self.current_token {
+                Token::EOF
+                | Token::End
+                | Token::Else
+                | Token::Class
+                | Token::Interface
+                | Token::Func
+                | Token::Var
+                | Token::If
+                | Token::While
+                | Token::For
+                | Token::Ret => return,
+                Token::Identifier(_) | Token::SelfRef | Token::Super if self.current_span.line > line => return,
+                _ => {
+                    line = self.current_span.line;
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    /// Consumes the `end` closing a block. A missing `end` is reported but not
+    /// treated as fatal, so the enclosing declaration is still kept in the tree.
+    fn expect_end(&mut self, construct: &str, start: Span) {
+        if self.current_token == Token::End {
+            self.advance();
+        } else {
+            self.diagnostics.push(
+                self.unexpected("'end'")
+                    .with_note(format!("to close the '{}' starting at {}", construct, start)),
+            );
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Node, Diagnostic> {
         match self.current_token {
             Token::Class => self.parse_class_declaration(),
+            Token::Interface => self.parse_interface_declaration(),
             Token::Func => self.parse_function_declaration(),
             Token::Var => self.parse_variable_declaration(),
             Token::While => self.parse_while_loop(),
             Token::For => self.parse_for_loop(),
-            Token::Identifier(_) => self.parse_assignment(),
+            Token::Identifier(_) | Token::SelfRef | Token::Super => self.parse_assignment_or_call(),
             Token::If => self.parse_if_else(),
             Token::Ret => self.parse_ret(),
-            _ => panic!("Unexpected token: {:?}", self.current_token),
+            _ => Err(self.unexpected("a statement")),
         }
     }
 
-    fn parse_class_declaration(&mut self) -> ASTNode {
-        self.consume_token(Token::Class);
-        let name = if let Token::Identifier(name) = self.current_token.clone() {
-            name
+    fn parse_class_declaration(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::Class)?;
+        let name = self.expect_identifier("class name")?;
+        let base = if self.current_token == Token::Extends {
+            self.advance();
+            Some(self.expect_identifier("base class name")?)
         } else {
-            panic!("Expected class name");
+            None
         };
-        self.consume_token(Token::Identifier(name.clone()));
-        let mut members = Vec::new();
-        self.consume_token(Token::Then);
-        while self.current_token != Token::End {
-            members.push(self.parse_statement());
+        let mut interfaces = Vec::new();
+        if self.current_token == Token::Implements {
+            self.advance();
+            interfaces.push(self.expect_identifier("interface name")?);
+            while self.current_token == Token::Comma {
+                self.advance();
+                interfaces.push(self.expect_identifier("interface name")?);
+            }
         }
-        self.consume_token(Token::End);
-        ASTNode::ClassDeclaration { name, members }
+        self.consume_token(Token::Then)?;
+        let members = self.parse_block(&[Token::End]);
+        self.expect_end("class", start);
+        Ok(self.node(ASTNode::ClassDeclaration { name, base, interfaces, members }, start))
     }
 
-    fn parse_function_declaration(&mut self) -> ASTNode {
-        self.consume_token(Token::Func);
-        let name = if let Token::Identifier(name) = self.current_token.clone() {
-            name
-        } else {
-            panic!("Expected function name");
-        };
-        self.consume_token(Token::Identifier(name.clone()));
-        self.consume_token(Token::LParen);
+    /// Parses `interface Name then`, the signatures of its methods and `end`.
+    fn parse_interface_declaration(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::Interface)?;
+        let name = self.expect_identifier("interface name")?;
+        self.consume_token(Token::Then)?;
+        let mut methods = Vec::new();
+        while self.current_token == Token::Func {
+            methods.push(self.parse_signature()?);
+        }
+        if self.current_token != Token::End {
+            return Err(self.unexpected("'func' or 'end'"));
+        }
+        self.advance();
+        Ok(self.node(ASTNode::InterfaceDeclaration { name, methods }, start))
+    }
+
+    fn parse_function_declaration(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        let MethodSignature { name, params, return_type, .. } = self.parse_signature()?;
+        self.consume_token(Token::Then)?;
+        let body = self.parse_block(&[Token::End]);
+        self.expect_end("func", start);
+        Ok(self.node(ASTNode::FunctionDeclaration { name, params, return_type, body }, start))
+    }
+
+    /// Parses a function's name, parameters and return type, from `func` up to its body.
+    fn parse_signature(&mut self) -> Result<MethodSignature, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::Func)?;
+        let name = self.expect_identifier("function name")?;
+        self.consume_token(Token::LParen)?;
         let mut params = Vec::new();
         while self.current_token != Token::RParen {
-            if let Token::Identifier(param_name) = self.current_token.clone() {
-                params.push(param_name);
-                self.advance();
-            }
+            let param_start = self.current_span;
+            let param_name = self.expect_identifier("parameter name")?;
+            let param_type = self.parse_type("parameter")?;
+            params.push(Parameter {
+                name: param_name,
+                param_type,
+                span: param_start.to(self.previous_span),
+            });
             if self.current_token == Token::Comma {
                 self.advance();
+            } else if self.current_token != Token::RParen {
+                return Err(self.unexpected("',' or ')'"));
             }
         }
-        self.consume_token(Token::RParen);
-        self.consume_token(Token::Then);
-        let mut body = Vec::new();
-        while self.current_token != Token::End {
-            body.push(self.parse_statement());
-        }
-        self.consume_token(Token::End);
-        ASTNode::FunctionDeclaration { name, params, body }
+        self.consume_token(Token::RParen)?;
+        let return_type = match self.current_token {
+            Token::Type(_) | Token::Identifier(_) => self.parse_type("return")?,
+            _ => Type::Void,
+        };
+        Ok(MethodSignature { name, params, return_type, span: start.to(self.previous_span) })
     }
 
-    fn parse_variable_declaration(&mut self) -> ASTNode {
-        self.consume_token(Token::Var);
-        let name = if let Token::Identifier(name) = self.current_token.clone() {
-            name
-        } else {
-            panic!("Expected variable name");
+    /// Parses a type name such as `int`, or a class name; `what` names the
+    /// thing being typed for error messages.
+    fn parse_type(&mut self, what: &str) -> Result<Type, Diagnostic> {
+        if let Token::Identifier(class) = self.current_token.clone() {
+            self.advance();
+            return Ok(Type::Class(class));
+        }
+        let Token::Type(type_name) = self.current_token.clone() else {
+            return Err(Diagnostic::error(
+                "E0102",
+                format!("Expected {} type, found {}", what, self.current_token),
+                self.current_span,
+            ));
         };
-        self.consume_token(Token::Identifier(name.clone()));
-
-        let var_type = if let Token::Type(type_name) = self.current_token.clone() {
-            self.consume_token(Token::Type(type_name.clone()));
-            match type_name.as_str() {
-                "int" => Type::Int,
-                "float" => Type::Float,
-                "string" => Type::String,
-                "bool" => Type::Bool,
-                _ => panic!("Unknown type: {}", type_name),
+        let type_span = self.current_span;
+        self.advance();
+        match type_name.as_str() {
+            "array" => {
+                self.consume_token(Token::LessThan)?;
+                let element = self.parse_type("element")?;
+                self.consume_token(Token::GreaterThan)?;
+                Ok(Type::Array(Box::new(element)))
             }
-        } else {
-            panic!("Expected variable type after variable name");
+            "int" => Ok(Type::Int),
+            "float" => Ok(Type::Float),
+            "string" => Ok(Type::String),
+            "bool" => Ok(Type::Bool),
+            _ => Err(Diagnostic::error(
+                "E0102",
+                format!("Unknown type: {}", type_name),
+                type_span,
+            )),
+        }
+    }
+
+    fn parse_variable_declaration(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::Var)?;
+        let name = self.expect_identifier("variable name")?;
+        let var_type = self.parse_type("variable")?;
+        Ok(self.node(ASTNode::VariableDeclaration { name, var_type }, start))
+    }
+
+    /// Parses `name = expression` or `object.field = expression`, or a call used as a statement.
+    fn parse_assignment_or_call(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        let target = self.parse_expression()?;
+        match target.kind {
+            ASTNode::Identifier(name) if self.current_token == Token::Assign => {
+                self.advance();
+                let expression = self.parse_expression()?;
+                Ok(self.node(
+                    ASTNode::Assignment {
+                        variable: name,
+                        expression: Box::new(expression),
+                    },
+                    start,
+                ))
+            }
+            ASTNode::MemberAccess { object, member } if self.current_token == Token::Assign => {
+                self.advance();
+                let expression = self.parse_expression()?;
+                Ok(self.node(
+                    ASTNode::FieldAssignment { object, field: member, expression: Box::new(expression) },
+                    start,
+                ))
+            }
+            ASTNode::Index { object, index } if self.current_token == Token::Assign => {
+                self.advance();
+                let expression = self.parse_expression()?;
+                Ok(self.node(ASTNode::IndexAssignment { object, index, expression: Box::new(expression) }, start))
+            }
+            ASTNode::FunctionCall { .. }
+            | ASTNode::New { .. }
+            | ASTNode::MethodCall { .. }
+            | ASTNode::SuperCall { .. } => Ok(target),
+            _ if self.current_token == Token::Assign => Err(Diagnostic::error(
+                "E0101",
+                "Expected a variable, field or array element for assignment",
+                target.span,
+            )),
+            _ => Err(self.unexpected("'='")),
+        }
+    }
+
+    fn binary(&self, left: Node, op: &Token, right: Node) -> Node {
+        let span = left.span.to(right.span);
+        let (left, right) = (Box::new(left), Box::new(right));
+        let operator = match op {
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::LessEqual => "<=",
+            Token::GreaterEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Modulo => "%",
+            Token::And => "and",
+            Token::Or => "or",
+            _ => unreachable!(),
+        }
+        .to_string();
+        let kind = match op {
+            Token::LessThan
+            | Token::GreaterThan
+            | Token::LessEqual
+            | Token::GreaterEqual
+            | Token::Equal
+            | Token::NotEqual => ASTNode::Comparison { left, operator, right },
+            Token::And | Token::Or => ASTNode::Logical { left, operator, right },
+            _ => ASTNode::Arithmetic { left, operator, right },
         };
-        self.symbol_table.declare_variable(&name, var_type.clone());
-        ASTNode::VariableDeclaration { name, var_type }
+        Node::new(kind, span)
     }
 
-    fn parse_assignment(&mut self) -> ASTNode {
-        if let Token::Identifier(name) = self.current_token.clone() {
-            self.consume_token(Token::Identifier(name.clone()));
-            self.consume_token(Token::Assign);
-            let expression = self.parse_expression();
-            ASTNode::Assignment {
-                variable: name,
-                expression: Box::new(expression),
+    fn parse_expression(&mut self) -> Result<Node, Diagnostic> {
+        self.parse_precedence(Precedence::Lowest)
+    }
+
+    /// Parses an expression whose operators all bind tighter than `min`.
+    fn parse_precedence(&mut self, min: Precedence) -> Result<Node, Diagnostic> {
+        let mut left = self.parse_prefix()?;
+
+        while let Some((precedence, associativity)) = infix_operator(&self.current_token) {
+            if precedence <= min {
+                break;
             }
-        } else {
-            panic!("Expected variable for assignment");
+            left = self.parse_infix(left, precedence, associativity)?;
         }
+
+        Ok(left)
     }
 
-    fn parse_expression(&mut self) -> ASTNode {
-        let mut node = self.parse_term();
-    
-        while matches!(
-            self.current_token,
-            Token::LessThan | Token::GreaterThan | Token::Equal | Token::Plus | Token::Minus
-        ) {
-            let op = self.current_token.clone();
-            self.advance();
-            let right = self.parse_term();
-    
-            node = match op {
-                Token::LessThan => ASTNode::Comparison {
-                    left: Box::new(node),
-                    operator: "<".to_string(),
-                    right: Box::new(right),
-                },
-                Token::GreaterThan => ASTNode::Comparison {
-                    left: Box::new(node),
-                    operator: ">".to_string(),
-                    right: Box::new(right),
-                },
-                Token::Equal => ASTNode::Comparison { 
-                    left: Box::new(node),
-                    operator: "==".to_string(), 
-                    right: Box::new(right), 
-                },
-                Token::Plus => ASTNode::Arithmetic {
-                    left: Box::new(node),
-                    operator: "+".to_string(),
-                    right: Box::new(right),
-                },
-                Token::Minus => ASTNode::Arithmetic {
-                    left: Box::new(node),
-                    operator: "-".to_string(),
-                    right: Box::new(right),
-                },
-                _ => unreachable!(),
-            };
+    fn parse_prefix(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        let (operator, precedence) = match self.current_token {
+            Token::Not => ("not", Precedence::Not),
+            Token::Minus => ("-", Precedence::Unary),
+            _ => return self.parse_primary(),
+        };
+        self.advance();
+        // Prefix operators apply to everything that binds at least as tightly as they do.
+        let operand = self.parse_precedence(precedence.lower())?;
+        Ok(self.node(
+            ASTNode::Unary { operator: operator.to_string(), operand: Box::new(operand) },
+            start,
+        ))
+    }
+
+    fn parse_infix(
+        &mut self,
+        left: Node,
+        precedence: Precedence,
+        associativity: Associativity,
+    ) -> Result<Node, Diagnostic> {
+        let op = self.current_token.clone();
+        if op == Token::LParen {
+            return self.parse_call(left);
         }
-    
-        node
-    }    
-    
-    fn parse_term(&mut self) -> ASTNode {
-        let mut node = self.parse_factor();
-    
-        while matches!(self.current_token, Token::Plus | Token::Minus) {
-            let op = self.current_token.clone();
-            self.advance();
-            let right = self.parse_factor();
-    
-            node = match op {
-                Token::Plus => ASTNode::Arithmetic {
-                    left: Box::new(node),
-                    operator: "+".to_string(),
-                    right: Box::new(right),
-                },
-                Token::Minus => ASTNode::Arithmetic {
-                    left: Box::new(node),
-                    operator: "-".to_string(),
-                    right: Box::new(right),
-                },
-                _ => unreachable!(),
-            };
+        if op == Token::Dot {
+            return self.parse_member(left);
         }
-    
-        node
-    }
-    
-    fn parse_factor(&mut self) -> ASTNode {
-        let mut node = self.parse_primary();
-    
-        while matches!(self.current_token, Token::Multiply | Token::Divide) {
-            let op = self.current_token.clone();
+        if op == Token::LBracket {
             self.advance();
-            let right = self.parse_primary();
-    
-            node = match op {
-                Token::Multiply => ASTNode::Arithmetic {
-                    left: Box::new(node),
-                    operator: "*".to_string(),
-                    right: Box::new(right),
-                },
-                Token::Divide => ASTNode::Arithmetic {
-                    left: Box::new(node),
-                    operator: "/".to_string(),
-                    right: Box::new(right),
-                },
-                _ => unreachable!(),
-            };
+            let index = self.parse_expression()?;
+            self.consume_token(Token::RBracket)?;
+            let start = left.span;
+            return Ok(self.node(ASTNode::Index { object: Box::new(left), index: Box::new(index) }, start));
+        }
+        self.advance();
+        let right = match associativity {
+            Associativity::Left => self.parse_precedence(precedence)?,
+            Associativity::Right => self.parse_precedence(precedence.lower())?,
+        };
+        Ok(self.binary(left, &op, right))
+    }
+
+    fn parse_call(&mut self, callee: Node) -> Result<Node, Diagnostic> {
+        let name = match callee.kind {
+            ASTNode::Identifier(name) => name,
+            _ => {
+                return Err(Diagnostic::error(
+                    "E0103",
+                    "Only named functions can be called",
+                    callee.span,
+                ))
+            }
+        };
+        self.consume_token(Token::LParen)?;
+        let args = self.parse_arguments()?;
+        Ok(self.node(ASTNode::FunctionCall { name, args }, callee.span))
+    }
+
+    /// Parses what follows a `.`: a field, a method call or, on a class name, `new(args)`.
+    fn parse_member(&mut self, object: Node) -> Result<Node, Diagnostic> {
+        self.consume_token(Token::Dot)?;
+        let member = self.expect_identifier("field or method name after '.'")?;
+        let start = object.span;
+        if self.current_token != Token::LParen {
+            return Ok(self.node(ASTNode::MemberAccess { object: Box::new(object), member }, start));
         }
-    
-        node
-    }    
+        self.advance();
+        let args = self.parse_arguments()?;
+        let kind = match object.kind {
+            ASTNode::Identifier(class) if member == "new" => ASTNode::New { class, args },
+            kind => ASTNode::MethodCall { object: Box::new(Node::new(kind, start)), method: member, args },
+        };
+        Ok(self.node(kind, start))
+    }
+
+    /// Parses a comma separated argument list up to and including the closing `)`.
+    fn parse_arguments(&mut self) -> Result<Vec<Node>, Diagnostic> {
+        self.parse_list(Token::RParen)
+    }
 
-    fn parse_primary(&mut self) -> ASTNode {
+    /// Parses comma separated expressions up to and including `close`.
+    fn parse_list(&mut self, close: Token) -> Result<Vec<Node>, Diagnostic> {
+        let mut items = Vec::new();
+        while self.current_token != close {
+            items.push(self.parse_expression()?);
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != close {
+                return Err(self.unexpected(&format!("',' or {}", close)));
+            }
+        }
+        self.consume_token(close)?;
+        Ok(items)
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
         match self.current_token.clone() {
             Token::StringLiteral(s) => {
                 self.advance();
-                ASTNode::StringLiteral(s)
+                Ok(self.node(ASTNode::StringLiteral(s), start))
+            }
+            Token::InterpolatedString(parts) => {
+                self.advance();
+                let mut nodes = Vec::with_capacity(parts.len());
+                for part in parts {
+                    nodes.push(match part {
+                        // The text's own span isn't tracked, the whole literal stands in for it.
+                        StringPart::Text(text) => Node::new(ASTNode::StringLiteral(text), start),
+                        StringPart::Code(code, at) => self.parse_embedded(&code, at)?,
+                    });
+                }
+                Ok(self.node(ASTNode::Interpolation(nodes), start))
             }
             Token::Number(value) => {
                 self.advance();
-                ASTNode::Number(value)
+                Ok(self.node(ASTNode::Number(value), start))
             }
             Token::Boolean(value) => {
                 self.advance();
-                ASTNode::Boolean(value)
+                Ok(self.node(ASTNode::Boolean(value), start))
             }
             Token::Float(value) => {
                 self.advance();
-                ASTNode::Float(value)
+                Ok(self.node(ASTNode::Float(value), start))
             }
             Token::Identifier(name) => {
                 self.advance();
-                if self.current_token == Token::LParen {
-                    self.advance();
-                    let mut args = Vec::new();
-                    while self.current_token != Token::RParen {
-                        args.push(self.parse_expression());
-                        if self.current_token == Token::Comma {
-                            self.advance();
-                        }
-                    }
-                    self.consume_token(Token::RParen);
-                    ASTNode::FunctionCall { name, args }
-                } else {
-                    ASTNode::Identifier(name)
-                }
+                Ok(self.node(ASTNode::Identifier(name), start))
+            }
+            Token::SelfRef => {
+                self.advance();
+                Ok(self.node(ASTNode::SelfRef, start))
+            }
+            Token::Super => {
+                self.advance();
+                self.consume_token(Token::Dot)?;
+                let method = self.expect_identifier("method name after 'super.'")?;
+                self.consume_token(Token::LParen)?;
+                let args = self.parse_arguments()?;
+                Ok(self.node(ASTNode::SuperCall { method, args }, start))
+            }
+            Token::LBracket => {
+                self.advance();
+                let items = self.parse_list(Token::RBracket)?;
+                Ok(self.node(ASTNode::ArrayLiteral(items), start))
             }
             Token::LParen => {
                 self.advance();
-                let expr = self.parse_expression();
-                self.consume_token(Token::RParen);
-                expr
+                let mut expr = self.parse_expression()?;
+                self.consume_token(Token::RParen)?;
+                expr.span = start.to(self.previous_span);
+                Ok(expr)
             }
-            _ => panic!("Unexpected token in primary: {:?}", self.current_token),
+            _ => Err(self.unexpected("an expression")),
         }
     }
-    
 
-    fn parse_if_else(&mut self) -> ASTNode {
-        self.consume_token(Token::If);
-        let condition = self.parse_expression();
-        self.consume_token(Token::Then);
-        let mut then_block = Vec::new();
-        while self.current_token != Token::Else && self.current_token != Token::End {
-            then_block.push(self.parse_statement());
-        }
+    /// Parses an expression embedded in a string, which starts at `at` in the source.
+    fn parse_embedded(&mut self, code: &str, at: Span) -> Result<Node, Diagnostic> {
+        let mut parser = Parser::new(Lexer::starting_at(code, at));
+        parser.advance();
+        let result = parser.parse_expression().and_then(|expr| match parser.current_token {
+            Token::EOF => Ok(expr),
+            _ => Err(parser.unexpected("'}' to end the interpolation")),
+        });
+        self.diagnostics.append(&mut parser.diagnostics);
+        result
+    }
+
+    fn parse_if_else(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::If)?;
+        let condition = self.parse_expression()?;
+        self.consume_token(Token::Then)?;
+        let then_block = self.parse_block(&[Token::Else, Token::End]);
         let mut else_block = None;
         if self.current_token == Token::Else {
-            self.consume_token(Token::Else);
-            else_block = Some(self.parse_block());
-        }
-        self.consume_token(Token::End);
-        ASTNode::IfElse {
-            condition: Box::new(condition),
-            then_block,
-            else_block,
+            self.consume_token(Token::Else)?;
+            else_block = Some(self.parse_block(&[Token::End]));
         }
+        self.expect_end("if", start);
+        Ok(self.node(
+            ASTNode::IfElse {
+                condition: Box::new(condition),
+                then_block,
+                else_block,
+            },
+            start,
+        ))
     }
 
-    fn parse_block(&mut self) -> Vec<ASTNode> {
-        let mut block = Vec::new();
-        while self.current_token != Token::End && self.current_token != Token::Else {
-            block.push(self.parse_statement());
-        }
-        block
+    fn parse_ret(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::Ret)?;
+        // `ret` only stands alone right before the end of a block.
+        let expression = match self.current_token {
+            Token::End | Token::Else | Token::EOF => None,
+            _ => Some(Box::new(self.parse_expression()?)),
+        };
+        Ok(self.node(ASTNode::Ret { expression }, start))
     }
 
-    fn parse_ret(&mut self) -> ASTNode {
-        self.consume_token(Token::Ret);
-        let expression = self.parse_expression();
-        ASTNode::Ret { expression: Box::new(expression) }
-    }    
-
-    fn parse_while_loop(&mut self) -> ASTNode {
-        self.consume_token(Token::While);
-        let condition = self.parse_expression();
-        self.consume_token(Token::Then);
-        let mut body = Vec::new();
-        self.symbol_table.enter_scope();
-        while self.current_token != Token::End {
-            body.push(self.parse_statement());
-        }
-        self.consume_token(Token::End);
-        self.symbol_table.exit_scope();
-        ASTNode::WhileLoop { condition: (Box::new(condition)), body, }
-    }
-
-    fn parse_for_loop(&mut self) -> ASTNode {
-        self.consume_token(Token::For);
-        self.symbol_table.enter_scope();
-        let variable = if let Token::Identifier(name) = self.current_token.clone() {
-            self.consume_token(Token::Identifier(name.clone()));
-            if !self.symbol_table.variables.contains_key(&name) {
-                self.symbol_table.declare_variable(&name, Type::Int);
-            }
-            name
-        } else {
-            panic!("Expected variable name after 'for'");
-        };
-        if self.current_token != Token::Assign {
-            panic!("Expected '=', found {:?}", self.current_token);
+    fn parse_while_loop(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.current_span;
+        self.consume_token(Token::While)?;
+        let condition = self.parse_expression()?;
+        self.consume_token(Token::Then)?;
+        let body = self.parse_block(&[Token::End]);
+        self.expect_end("while", start);
+        Ok(self.node(ASTNode::WhileLoop { condition: (Box::new(condition)), body, }, start))
+    }
+
+    fn parse_for_loop(&mut self) -> Result<Node, Diagnostic> {
+        let start_span = self.current_span;
+        self.consume_token(Token::For)?;
+        let variable = self.expect_identifier("variable name after 'for'")?;
+        if self.current_token == Token::In {
+            self.advance();
+            let array = self.parse_expression()?;
+            let body = self.parse_block(&[Token::End]);
+            self.expect_end("for", start_span);
+            return Ok(self.node(ASTNode::ForEach { variable, array: Box::new(array), body }, start_span));
         }
-        
-        self.consume_token(Token::Assign);
-        let start = self.parse_expression();
-        let end = self.parse_expression();
-        let mut body = Vec::new();
-        while self.current_token != Token::End {
-            body.push(self.parse_statement());
+        self.consume_token(Token::Assign)?;
+        let start = self.parse_expression()?;
+        let end = self.parse_expression()?;
+        let body = self.parse_block(&[Token::End]);
+        self.expect_end("for", start_span);
+        Ok(self.node(
+            ASTNode::ForLoop { variable, start: (Box::new(start)), end: (Box::new(end)), body, },
+            start_span,
+        ))
+    }
+}
+
+/// Operator precedence levels, from loosest to tightest binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Lowest,
+    Or,
+    And,
+    Not,
+    Equality,
+    Comparison,
+    Additive,
+    Multiplicative,
+    Unary,
+    Postfix,
+}
+
+impl Precedence {
+    /// The next looser level, used to let an operand contain operators of the same level.
+    fn lower(self) -> Precedence {
+        match self {
+            Precedence::Lowest | Precedence::Or => Precedence::Lowest,
+            Precedence::And => Precedence::Or,
+            Precedence::Not => Precedence::And,
+            Precedence::Equality => Precedence::Not,
+            Precedence::Comparison => Precedence::Equality,
+            Precedence::Additive => Precedence::Comparison,
+            Precedence::Multiplicative => Precedence::Additive,
+            Precedence::Unary => Precedence::Multiplicative,
+            Precedence::Postfix => Precedence::Unary,
         }
-        self.consume_token(Token::End);
-        self.symbol_table.exit_scope();
-        ASTNode::ForLoop { variable, start: (Box::new(start)), end: (Box::new(end)), body, }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    // No operator is right-associative yet; kept so one can be added to the table directly.
+    #[allow(dead_code)]
+    Right,
+}
+
+/// The precedence table for operators that follow an operand.
+fn infix_operator(token: &Token) -> Option<(Precedence, Associativity)> {
+    let precedence = match token {
+        Token::Or => Precedence::Or,
+        Token::And => Precedence::And,
+        Token::Equal | Token::NotEqual => Precedence::Equality,
+        Token::LessThan | Token::GreaterThan | Token::LessEqual | Token::GreaterEqual => {
+            Precedence::Comparison
+        }
+        Token::Plus | Token::Minus => Precedence::Additive,
+        Token::Multiply | Token::Divide | Token::Modulo => Precedence::Multiplicative,
+        Token::LParen | Token::Dot | Token::LBracket => Precedence::Postfix,
+        _ => return None,
+    };
+    Some((precedence, Associativity::Left))
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+use crate::symbol_table::Type;
+use crate::value::{ArithmeticOp, ComparisonOp, IndexError, Value};
+
+/// How deeply calls can nest in the VM before the program fails with a stack overflow.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// How deeply calls can nest in the interpreter. Every call it makes goes
+/// several levels deeper into the Rust stack, so it stops well before the VM
+/// does to fail cleanly on the stack of an ordinary thread, even in debug builds.
+pub const MAX_INTERPRETER_CALL_DEPTH: usize = 200;
+
+/// A function call in progress.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    /// Where the function was called from, or `None` for the program's entry point.
+    pub call_site: Option<Span>,
+}
+
+/// A runtime failure along with the Neutron call stack at the point it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    /// Boxed to keep results small, since they are passed up through every nested call.
+    pub diagnostic: Box<Diagnostic>,
+    /// The active calls, innermost first.
+    pub stack_trace: Vec<StackFrame>,
+}
+
+impl RuntimeError {
+    /// Renders the diagnostic followed by the stack trace.
+    pub fn render(&self, filename: &str, source: &str, color: bool) -> String {
+        let mut out = self.diagnostic.render(filename, source, color);
+        out.push_str(&self.render_stack_trace());
+        out
+    }
+
+    /// Lists the frames, collapsing runs of the same call, such as those of a
+    /// runaway recursion, so the trace stays short.
+    fn render_stack_trace(&self) -> String {
+        if self.stack_trace.is_empty() {
+            return String::new();
+        }
+        let mut out = String::from("stack trace (most recent call first):\n");
+        let mut frames = self.stack_trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            match frame.call_site {
+                Some(call_site) => {
+                    out.push_str(&format!("    in '{}', called at {}\n", frame.function, call_site))
+                }
+                None => out.push_str(&format!("    in '{}'\n", frame.function)),
+            }
+            let mut repeated = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeated += 1;
+            }
+            if repeated > 0 {
+                let plural = if repeated == 1 { "" } else { "s" };
+                out.push_str(&format!("    ... previous frame repeated {} time{}\n", repeated, plural));
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.diagnostic)?;
+        write!(f, "{}", self.render_stack_trace().trim_end())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// The runtime failures both the interpreter and the VM can report, kept in one
+// place so the two give identical messages.
+
+pub(crate) fn undefined_variable(name: &str, span: Span) -> Diagnostic {
+    Diagnostic::error("E0300", format!("Undefined variable '{}'", name), span)
+}
+
+pub(crate) fn not_a_bool(what: &str, value: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error("E0301", format!("{} must be a boolean, got {:?}", what, value), span)
+}
+
+pub(crate) fn unsupported_arithmetic(op: ArithmeticOp, l: &Value, r: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0301",
+        format!("Unsupported arithmetic operation: {:?} {} {:?}", l, op, r),
+        span,
+    )
+}
+
+pub(crate) fn invalid_comparison(op: ComparisonOp, l: &Value, r: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0301",
+        format!("Invalid comparison between incompatible types: {:?} {} {:?}", l, op, r),
+        span,
+    )
+}
+
+pub(crate) fn unsupported_unary(operator: &str, value: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0301",
+        format!("Unsupported unary operation: {} {:?}", operator, value),
+        span,
+    )
+}
+
+pub(crate) fn invalid_for_range(span: Span) -> Diagnostic {
+    Diagnostic::error("E0301", "'for' loop range must be integers", span)
+}
+
+pub(crate) fn function_not_found(name: &str, span: Span) -> Diagnostic {
+    Diagnostic::error("E0302", format!("Function '{}' not found", name), span)
+}
+
+pub(crate) fn arity_mismatch(name: &str, expected: usize, got: usize, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0303",
+        format!("Function '{}' expects {} arguments, got {}", name, expected, got),
+        span,
+    )
+}
+
+pub(crate) fn too_few_arguments(name: &str, expected: usize, got: usize, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0303",
+        format!("Function '{}' expects at least {} arguments, got {}", name, expected, got),
+        span,
+    )
+}
+
+pub(crate) fn division_by_zero(span: Span, divisor: Span) -> Diagnostic {
+    Diagnostic::error("E0305", "Division by zero", span).with_label(divisor, "this is zero")
+}
+
+pub(crate) fn overflow(operator: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0306",
+        format!("Integer overflow: result of '{}' does not fit in an int", operator),
+        span,
+    )
+}
+
+pub(crate) fn stack_overflow(limit: usize, span: Span) -> Diagnostic {
+    Diagnostic::error("E0311", format!("Stack overflow: more than {} nested calls", limit), span)
+}
+
+pub(crate) fn native_argument_mismatch(
+    name: &str,
+    index: usize,
+    expected: &Type,
+    got: &Value,
+    span: Span,
+) -> Diagnostic {
+    Diagnostic::error(
+        "E0307",
+        format!("Argument {} of '{}' must be {}, got {:?}", index + 1, name, expected, got),
+        span,
+    )
+}
+
+pub(crate) fn native_failed(name: &str, message: &str, span: Span) -> Diagnostic {
+    Diagnostic::error("E0308", format!("Native function '{}' failed: {}", name, message), span)
+}
+
+pub(crate) fn not_an_object(value: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error("E0309", format!("Cannot access a field of {:?}", value), span)
+}
+
+pub(crate) fn no_member(member: &str, value: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error("E0309", format!("Cannot access '{}' on {:?}", member, value), span)
+}
+
+pub(crate) fn invalid_index(error: IndexError, array: &Value, index: &Value, span: Span) -> Diagnostic {
+    match error {
+        IndexError::NotAnArray => Diagnostic::error("E0301", format!("Cannot index {:?}", array), span),
+        IndexError::NotAnInt => {
+            Diagnostic::error("E0301", format!("Array index must be an int, got {:?}", index), span)
+        }
+        IndexError::OutOfBounds { index, len } => Diagnostic::error(
+            "E0310",
+            format!("Index {} is out of bounds for an array of length {}", index, len),
+            span,
+        ),
+    }
+}
+
+pub(crate) fn not_iterable(value: &Value, span: Span) -> Diagnostic {
+    Diagnostic::error("E0301", format!("'for' loop can only iterate over an array, got {:?}", value), span)
+}
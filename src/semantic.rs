@@ -1,173 +1,970 @@
-use crate::ast::ASTNode;
-use crate::symbol_table::{SymbolTable, Type};
+use crate::ast::{ASTNode, MethodSignature, Node, Parameter};
+use crate::builtins;
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+use crate::symbol_table::{FunctionSignature, ScopeId, Symbol, SymbolKind, SymbolTable, Type};
 
-pub fn analyze(ast: &ASTNode, symbol_table: &mut SymbolTable) {
-    match ast {
-        ASTNode::Program(nodes) => {
-            for node in nodes {
-                analyze(node, symbol_table);
+/// Type checks a whole program, collecting every error instead of stopping at the first one.
+pub fn analyze(ast: &Node, symbol_table: &mut SymbolTable) -> Result<(), Vec<Diagnostic>> {
+    let mut analyzer = Analyzer { symbol_table, diagnostics: Vec::new(), return_type: None, base_class: None };
+    analyzer.check_statement(ast);
+    if analyzer.diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(analyzer.diagnostics)
+    }
+}
+
+struct Analyzer<'a> {
+    symbol_table: &'a mut SymbolTable,
+    diagnostics: Vec<Diagnostic>,
+    /// The declared return type of the function being checked, if any.
+    return_type: Option<Type>,
+    /// The base class of the class whose methods are being checked, for `super` calls.
+    base_class: Option<String>,
+}
+
+impl Analyzer<'_> {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn declare(&mut self, name: &str, kind: SymbolKind, span: Span) {
+        if let Err(err) = self.symbol_table.declare(name, kind, span) {
+            self.report(err);
+        }
+    }
+
+    /// Declares the functions and classes of a block up front so they can be
+    /// used before their definition.
+    fn declare_functions(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match &node.kind {
+                ASTNode::FunctionDeclaration { name, params, return_type, .. } => {
+                    self.declare(name, SymbolKind::Function(signature(params, return_type)), node.span);
+                }
+                ASTNode::ClassDeclaration { name, members, .. } => {
+                    let scope = self.declare_members(members);
+                    self.declare(name, SymbolKind::Class(scope), node.span);
+                }
+                ASTNode::InterfaceDeclaration { name, methods } => {
+                    let scope = self.symbol_table.enter_scope();
+                    for method in methods {
+                        let signature = signature(&method.params, &method.return_type);
+                        self.declare(&method.name, SymbolKind::Function(signature), method.span);
+                    }
+                    self.symbol_table.exit_scope();
+                    self.declare(name, SymbolKind::Interface(scope), node.span);
+                }
+                _ => {}
             }
         }
-        ASTNode::ClassDeclaration { name: _, members } => {
-            for member in members {
-                analyze(member, symbol_table);
+        // Base classes and interfaces are linked once they are all declared, so
+        // a class can refer to ones declared after it.
+        for node in nodes {
+            if let ASTNode::ClassDeclaration { name, base, interfaces, .. } = &node.kind {
+                if let Some(base) = base {
+                    self.inherit(name, base, node.span);
+                }
+                self.implement(name, interfaces, node.span);
+            }
+        }
+    }
+
+    /// Makes the class declared as `name` at `span` inherit the members of `base`.
+    fn inherit(&mut self, name: &str, base: &str, span: Span) {
+        let Some(scope) = self.class_scope(name, span) else { return };
+        let Some(base_scope) = self.symbol_table.get_class(base) else {
+            self.report(Diagnostic::error("E0217", format!("Unknown class '{}'", base), span));
+            return;
+        };
+        if !self.symbol_table.set_base(scope, base_scope) {
+            let message = if name == base {
+                format!("Class '{}' can't extend itself", name)
+            } else {
+                format!("Class '{}' can't extend '{}', which already inherits from it", name, base)
+            };
+            self.report(Diagnostic::error("E0223", message, span));
+        }
+    }
+
+    /// Records the interfaces the class declared as `name` at `span` implements.
+    /// Whether it has their methods is checked along with its members.
+    fn implement(&mut self, name: &str, interfaces: &[String], span: Span) {
+        let Some(scope) = self.class_scope(name, span) else { return };
+        for interface in interfaces {
+            match self.symbol_table.get_interface(interface) {
+                Some(methods) => self.symbol_table.add_interface(scope, methods),
+                None => self.report(Diagnostic::error("E0217", format!("Unknown interface '{}'", interface), span)),
             }
         }
-        ASTNode::FunctionDeclaration { name, params, body } => {
-            symbol_table.declare_function(name, params.clone());
-            symbol_table.enter_scope();
+    }
+
+    /// The member scope of the class declared as `name` at `span`, unless
+    /// another class of the same name was declared first.
+    fn class_scope(&self, name: &str, span: Span) -> Option<ScopeId> {
+        match self.symbol_table.lookup(name) {
+            Some(Symbol { kind: SymbolKind::Class(scope), span: declared, .. }) if *declared == span => Some(*scope),
+            _ => None,
+        }
+    }
 
-            for param in params {
-                symbol_table.declare_variable(param, Type::Int);
+    /// Whether a value of type `value` can be stored where `target` is
+    /// expected: either they are the same type, `value` is an instance of
+    /// a class extending or implementing `target`, or `value` is an empty
+    /// array literal.
+    fn is_assignable(&self, value: &Type, target: &Type) -> bool {
+        match (value, target) {
+            (Type::Class(value), Type::Class(target)) => {
+                match (self.symbol_table.get_members(value), self.symbol_table.get_members(target)) {
+                    (Some(value), Some(target)) => self.symbol_table.inherits(value, target),
+                    _ => value == target,
+                }
             }
-            for statement in body {
-                analyze(statement, symbol_table);
+            (Type::Array(value), Type::Array(target)) => fits_element(value, target),
+            _ => value == target,
+        }
+    }
+
+    /// Declares the fields and methods of a class in a scope of their own,
+    /// which is returned. Methods see the fields through it.
+    fn declare_members(&mut self, members: &[Node]) -> ScopeId {
+        let scope = self.symbol_table.enter_scope();
+        self.declare_functions(members);
+        for member in members {
+            if let ASTNode::VariableDeclaration { name, var_type } = &member.kind {
+                self.declare(name, SymbolKind::Field(var_type.clone()), member.span);
             }
+        }
+        self.symbol_table.exit_scope();
+        scope
+    }
 
-            symbol_table.exit_scope();
+    /// Reports a class type that doesn't name a visible class or interface.
+    fn check_type(&mut self, declared: &Type, span: Span) {
+        match declared {
+            Type::Class(name) if self.symbol_table.get_members(name).is_none() => {
+                self.report(Diagnostic::error("E0217", format!("Unknown type '{}'", name), span));
+            }
+            Type::Array(element) => self.check_type(element, span),
+            _ => {}
         }
-        ASTNode::VariableDeclaration { name, var_type } => {
-            symbol_table.declare_variable(name, var_type.clone());
+    }
+
+    /// Checks statements in the current scope.
+    fn check_block(&mut self, nodes: &[Node]) {
+        self.declare_functions(nodes);
+        for node in nodes {
+            self.check_statement(node);
         }
-        ASTNode::Assignment { variable, expression } => {
-            if let Some(var_type) = symbol_table.get_variable_type(variable) {
-                let expr_type = get_expression_type(expression, symbol_table);
-                if &expr_type != var_type {
-                    panic!(
-                        "Type mismatch in assignment to '{}': expected {:?}, got {:?}",
-                        variable, var_type, expr_type
-                    );
+    }
+
+    /// Checks statements in a new scope nested in the current one.
+    fn check_scoped_block(&mut self, nodes: &[Node]) {
+        self.symbol_table.enter_scope();
+        self.check_block(nodes);
+        self.symbol_table.exit_scope();
+    }
+
+    fn check_statement(&mut self, node: &Node) {
+        let span = node.span;
+        match &node.kind {
+            ASTNode::Program(nodes) => self.check_block(nodes),
+            ASTNode::ClassDeclaration { name, base, interfaces, members } => {
+                // A class that failed to declare because of a duplicate name still gets its members checked.
+                let scope = match self.class_scope(name, span) {
+                    Some(scope) => scope,
+                    None => self.declare_members(members),
+                };
+                self.symbol_table.reenter_scope(scope);
+                let enclosing = std::mem::replace(&mut self.base_class, base.clone());
+                let receiver = Type::Class(name.clone());
+                for member in members {
+                    if let Some(inherited) = self.symbol_table.base_of(scope) {
+                        self.check_override(name, member, inherited);
+                    }
+                    match &member.kind {
+                        ASTNode::VariableDeclaration { var_type, .. } => self.check_type(var_type, member.span),
+                        ASTNode::FunctionDeclaration { name: method, params, return_type, body } => {
+                            if method == "new" {
+                                self.report(Diagnostic::error(
+                                    "E0222",
+                                    "A method can't be called 'new'",
+                                    member.span,
+                                ).with_note(format!("'{}.new(...)' always creates an instance", name)));
+                            }
+                            self.check_function(params, return_type, body, Some(&receiver), member.span);
+                        }
+                        ASTNode::Error => {}
+                        _ => self.report(Diagnostic::error(
+                            "E0219",
+                            "Only fields and methods can be declared in a class",
+                            member.span,
+                        )),
+                    }
+                }
+                self.base_class = enclosing;
+                self.symbol_table.exit_scope();
+                for interface in interfaces {
+                    self.check_implements(name, scope, interface, span);
                 }
-            } else {
-                panic!("Variable '{}' not declared", variable);
             }
-        
-            analyze(expression, symbol_table);
-        }        
-        ASTNode::Arithmetic { left, right, operator: _ } => {
-            analyze(left, symbol_table);
-            analyze(right, symbol_table);
-
-            let left_type = get_expression_type(left, symbol_table);
-            let right_type = get_expression_type(right, symbol_table);
+            ASTNode::InterfaceDeclaration { methods, .. } => {
+                for MethodSignature { params, return_type, span, .. } in methods {
+                    self.check_type(return_type, *span);
+                    for param in params {
+                        self.check_type(&param.param_type, param.span);
+                    }
+                }
+            }
+            ASTNode::FunctionDeclaration { name: _, params, return_type, body } => {
+                self.check_function(params, return_type, body, None, span);
+            }
+            ASTNode::VariableDeclaration { name, var_type } => {
+                self.check_type(var_type, span);
+                self.declare(name, SymbolKind::Variable(var_type.clone()), span);
+            }
+            ASTNode::Assignment { variable, expression } => {
+                let var_type = self.symbol_table.get_variable_type(variable).cloned();
+                let expr_type = self.check_expression(expression);
+                match (var_type, expr_type) {
+                    (None, _) => self.report(Diagnostic::error(
+                        "E0200",
+                        format!("Variable '{}' not declared", variable),
+                        span,
+                    )),
+                    (Some(var_type), Some(expr_type)) if !self.is_assignable(&expr_type, &var_type) => {
+                        let mut error = Diagnostic::error(
+                            "E0201",
+                            format!(
+                                "Type mismatch in assignment to '{}': expected {}, got {}",
+                                variable, var_type, expr_type
+                            ),
+                            expression.span,
+                        );
+                        if let Some(declared) = self.symbol_table.get_variable_span(variable) {
+                            error = error.with_label(
+                                declared,
+                                format!("variable declared here as {}", var_type),
+                            );
+                        }
+                        self.report(error)
+                    }
+                    _ => {}
+                }
+            }
+            ASTNode::FieldAssignment { object, field, expression } => {
+                let expr_type = self.check_expression(expression);
+                let Some((class, members)) = self.check_receiver(object, field) else { return };
+                let Some(Symbol { kind: SymbolKind::Field(field_type), span: declared, .. }) =
+                    self.symbol_table.lookup_in(members, field).cloned()
+                else {
+                    self.no_member(&class, "field", field, span);
+                    return;
+                };
+                match expr_type {
+                    Some(expr_type) if !self.is_assignable(&expr_type, &field_type) => self.report(
+                        Diagnostic::error(
+                            "E0201",
+                            format!(
+                                "Type mismatch in assignment to '{}.{}': expected {}, got {}",
+                                class, field, field_type, expr_type
+                            ),
+                            expression.span,
+                        )
+                        .with_label(declared, format!("field declared here as {}", field_type)),
+                    ),
+                    _ => {}
+                }
+            }
+            ASTNode::IndexAssignment { object, index, expression } => {
+                let expr_type = self.check_expression(expression);
+                let element_type = self.check_index(object, index);
+                if let (Some(element_type), Some(expr_type)) = (element_type, expr_type) {
+                    if !self.is_assignable(&expr_type, &element_type) {
+                        self.report(Diagnostic::error(
+                            "E0201",
+                            format!(
+                                "Type mismatch in assignment to an array element: expected {}, got {}",
+                                element_type, expr_type
+                            ),
+                            expression.span,
+                        ));
+                    }
+                }
+            }
+            ASTNode::WhileLoop { condition, body } => {
+                self.check_condition(condition, "Condition in 'while' loop");
+                self.check_scoped_block(body);
+            }
+            ASTNode::ForLoop { variable, start, end, body } => {
+                for bound in [start, end] {
+                    match self.check_expression(bound) {
+                        Some(bound_type) if bound_type != Type::Int => self.report(Diagnostic::error(
+                            "E0205",
+                            format!(
+                                "Start and end expressions in 'for' loop must be integers, got {}",
+                                bound_type
+                            ),
+                            bound.span,
+                        )),
+                        _ => {}
+                    }
+                }
 
-            if left_type != right_type {
-                panic!("Type mismatch in arithmetic: {:?} vs {:?}", left_type, right_type);
+                // The loop variable is scoped to the loop body.
+                self.symbol_table.enter_scope();
+                self.declare(variable, SymbolKind::Variable(Type::Int), span);
+                self.check_block(body);
+                self.symbol_table.exit_scope();
+            }
+            ASTNode::ForEach { variable, array, body } => {
+                let element_type = match self.check_expression(array) {
+                    Some(Type::Array(element_type)) => *element_type,
+                    Some(array_type) => {
+                        self.report(Diagnostic::error(
+                            "E0205",
+                            format!("'for' loop can only iterate over an array, got {}", array_type),
+                            array.span,
+                        ));
+                        Type::Any
+                    }
+                    None => Type::Any,
+                };
+                self.symbol_table.enter_scope();
+                self.declare(variable, SymbolKind::Variable(element_type), span);
+                self.check_block(body);
+                self.symbol_table.exit_scope();
+            }
+            ASTNode::IfElse { condition, then_block, else_block } => {
+                self.check_condition(condition, "Condition in 'if'");
+                self.check_scoped_block(then_block);
+                if let Some(else_block) = else_block {
+                    self.check_scoped_block(else_block);
+                }
+            }
+            ASTNode::Ret { expression } => self.check_ret(expression.as_deref(), span),
+            ASTNode::Error => {}
+            _ => {
+                self.check_expression(node);
             }
         }
-        ASTNode::WhileLoop { condition, body } => {
-            let cond_type = get_expression_type(condition, symbol_table);
-            if cond_type != Type::Bool {
-                panic!("Condition in 'while' loop must be boolean, got {:?}", cond_type);
-            }
-        
-            analyze(condition, symbol_table);
-        
-            for statement in body {
-                analyze(statement, symbol_table);
-            }
-        }        
-        ASTNode::ForLoop { variable, start, end, body } => {
-            let start_type = match start.as_ref() {
-                ASTNode::Number(_) => Type::Int,
-                ASTNode::Identifier(name) => symbol_table
-                    .get_variable_type(name)
-                    .cloned()
-                    .unwrap_or_else(|| panic!("Undefined variable: {}", name)),
-                _ => panic!("Invalid start expression in 'for' loop"),
-            };
+    }
+
+    /// Checks that a member of `class` agrees with what it inherits from the
+    /// members in `inherited`: only methods can be overridden, and except for
+    /// `init` they must keep their signature.
+    fn check_override(&mut self, class: &str, member: &Node, inherited: ScopeId) {
+        let (name, kind, signature) = match &member.kind {
+            ASTNode::VariableDeclaration { name, .. } => (name, "Field", None),
+            ASTNode::FunctionDeclaration { name, params, return_type, .. } => {
+                (name, "Method", Some(signature(params, return_type)))
+            }
+            _ => return,
+        };
+        let Some(previous) = self.symbol_table.lookup_in(inherited, name).cloned() else { return };
+        let error = match (&previous.kind, signature) {
+            (SymbolKind::Function(_), Some(_)) if name == "init" => return,
+            (SymbolKind::Function(overridden), Some(signature)) if *overridden == signature => return,
+            (SymbolKind::Function(_), Some(_)) => Diagnostic::error(
+                "E0224",
+                format!("Method '{}.{}' doesn't match the signature of the method it overrides", class, name),
+                member.span,
+            )
+            .with_label(previous.span, "overridden method declared here"),
+            _ => Diagnostic::error(
+                "E0225",
+                format!("{} '{}' is already declared in a base class of '{}'", kind, name, class),
+                member.span,
+            )
+            .with_label(previous.span, "previously declared here"),
+        };
+        self.report(error);
+    }
 
-            let end_type = match end.as_ref() {
-                ASTNode::Number(_) => Type::Int,
-                ASTNode::Identifier(name) => symbol_table
-                    .get_variable_type(name)
-                    .cloned()
-                    .unwrap_or_else(|| panic!("Undefined variable: {}", name)),
-                _ => panic!("Invalid end expression in 'for' loop"),
+    /// Checks that the class `class`, whose members are in `scope`, has every
+    /// method of `interface` with the same signature, declared or inherited.
+    fn check_implements(&mut self, class: &str, scope: ScopeId, interface: &str, span: Span) {
+        // An unknown interface was reported when the class was declared.
+        let Some(required) = self.symbol_table.get_interface(interface) else { return };
+        let required: Vec<Symbol> = self.symbol_table.symbols_in(required).into_iter().cloned().collect();
+        for method in required {
+            let SymbolKind::Function(expected) = &method.kind else { continue };
+            let error = match self.symbol_table.lookup_in(scope, &method.name) {
+                Some(Symbol { kind: SymbolKind::Function(found), .. }) if found == expected => continue,
+                Some(found @ Symbol { kind: SymbolKind::Function(_), .. }) => Diagnostic::error(
+                    "E0228",
+                    format!(
+                        "Method '{}.{}' doesn't match its signature in interface '{}'",
+                        class, method.name, interface
+                    ),
+                    found.span,
+                ),
+                _ => Diagnostic::error(
+                    "E0227",
+                    format!("Class '{}' doesn't implement method '{}' of interface '{}'", class, method.name, interface),
+                    span,
+                ),
             };
+            self.report(error.with_label(method.span, format!("required by '{}' here", interface)));
+        }
+    }
 
-            if start_type != Type::Int || end_type != Type::Int {
-                panic!("Start and end expressions in 'for' loop must be integers");
+    /// Checks a function, or a method of the class `receiver` is an instance of.
+    fn check_function(
+        &mut self,
+        params: &[Parameter],
+        return_type: &Type,
+        body: &[Node],
+        receiver: Option<&Type>,
+        span: Span,
+    ) {
+        self.check_type(return_type, span);
+        self.symbol_table.enter_scope();
+        if let Some(receiver) = receiver {
+            self.declare("self", SymbolKind::Parameter(receiver.clone()), span);
+        }
+        for param in params {
+            self.check_type(&param.param_type, param.span);
+            self.declare(&param.name, SymbolKind::Parameter(param.param_type.clone()), param.span);
+        }
+        let enclosing = self.return_type.replace(return_type.clone());
+        self.check_block(body);
+        self.return_type = enclosing;
+        if *return_type != Type::Void && !always_returns(body) {
+            self.report(
+                Diagnostic::error("E0232", format!("Function may end without returning {}", return_type), span)
+                    .with_note("every path through the function needs a 'ret' with a value"),
+            );
+        }
+        self.symbol_table.exit_scope();
+    }
+
+    fn check_ret(&mut self, expression: Option<&Node>, span: Span) {
+        let value_type = match expression {
+            Some(expression) => match self.check_expression(expression) {
+                Some(value_type) => value_type,
+                None => return,
+            },
+            None => Type::Void,
+        };
+        let Some(return_type) = self.return_type.clone() else {
+            return;
+        };
+        if self.is_assignable(&value_type, &return_type) {
+            return;
+        }
+        let error = match expression {
+            None => Diagnostic::error(
+                "E0214",
+                format!("Missing return value, function returns {}", return_type),
+                span,
+            ),
+            Some(expression) if return_type == Type::Void => Diagnostic::error(
+                "E0214",
+                "Cannot return a value from a function without a return type",
+                expression.span,
+            ),
+            Some(expression) => Diagnostic::error(
+                "E0214",
+                format!("Return type mismatch: expected {}, got {}", return_type, value_type),
+                expression.span,
+            ),
+        };
+        self.report(error);
+    }
+
+    fn check_condition(&mut self, condition: &Node, what: &str) {
+        if let Some(cond_type) = self.check_expression(condition) {
+            if cond_type != Type::Bool {
+                self.report(Diagnostic::error(
+                    "E0204",
+                    format!("{} must be boolean, got {}", what, cond_type),
+                    condition.span,
+                ));
             }
+        }
+    }
 
-            symbol_table.declare_variable(variable, Type::Int);
-            for statement in body {
-                analyze(statement, symbol_table);
+    fn check_bool_operand(&mut self, operator: &str, operand: &Node) {
+        if let Some(operand_type) = self.check_expression(operand) {
+            if operand_type != Type::Bool {
+                self.report(Diagnostic::error(
+                    "E0210",
+                    format!("Operator '{}' requires bool operands, got {}", operator, operand_type),
+                    operand.span,
+                ));
             }
         }
-        ASTNode::IfElse { condition, then_block, else_block } => {
-            let cond_type = get_expression_type(condition, symbol_table);
-            if cond_type != Type::Bool {
-                panic!("Condition in 'if' must be boolean, got {:?}", cond_type);
+    }
+
+    /// Returns the type of `expr`, or `None` if an error was already reported for it.
+    fn check_expression(&mut self, expr: &Node) -> Option<Type> {
+        let span = expr.span;
+        match &expr.kind {
+            ASTNode::Number(_) => Some(Type::Int),
+            ASTNode::Float(_) => Some(Type::Float),
+            ASTNode::Boolean(_) => Some(Type::Bool),
+            ASTNode::StringLiteral(_) => Some(Type::String),
+            ASTNode::Interpolation(parts) => {
+                for part in parts {
+                    if self.check_expression(part) == Some(Type::Void) {
+                        self.report(Diagnostic::error(
+                            "E0216",
+                            "Cannot interpolate a void value into a string",
+                            part.span,
+                        ));
+                    }
+                }
+                Some(Type::String)
             }
-        
-            analyze(condition, symbol_table);
-            for stmt in then_block {
-                analyze(stmt, symbol_table);
+            ASTNode::ArrayLiteral(elements) => {
+                // The elements must agree on a type, which an empty literal leaves open.
+                let mut element_type = Some(Type::Any);
+                for element in elements {
+                    let Some(found) = self.check_expression(element) else {
+                        element_type = None;
+                        continue;
+                    };
+                    let Some(expected) = &element_type else { continue };
+                    if found == Type::Void {
+                        self.report(Diagnostic::error("E0231", "An array can't hold a void value", element.span));
+                        element_type = None;
+                    } else if *expected == Type::Any || self.is_assignable(expected, &found) {
+                        element_type = Some(found);
+                    } else if !self.is_assignable(&found, expected) {
+                        self.report(Diagnostic::error(
+                            "E0231",
+                            format!("Array elements must all have the same type: expected {}, got {}", expected, found),
+                            element.span,
+                        ));
+                        element_type = None;
+                    }
+                }
+                Some(Type::Array(Box::new(element_type?)))
             }
-            if let Some(else_block) = else_block {
-                for stmt in else_block {
-                    analyze(stmt, symbol_table);
+            ASTNode::Index { object, index } => self.check_index(object, index),
+            ASTNode::Identifier(name) => {
+                let var_type = self.symbol_table.get_variable_type(name).cloned();
+                if var_type.is_none() {
+                    self.report(Diagnostic::error(
+                        "E0200",
+                        format!("Undefined variable: {}", name),
+                        span,
+                    ));
                 }
+                var_type
+            }
+            ASTNode::Arithmetic { left, operator, right } => {
+                let left_type = self.check_expression(left);
+                let right_type = self.check_expression(right);
+                let (left_type, right_type) = (left_type?, right_type?);
+                let is_concatenation = operator == "+" && left_type == Type::String;
+                if !is_numeric(&left_type) && !is_concatenation {
+                    self.report(unsupported_operand(operator, &left_type, left.span));
+                    return None;
+                }
+                if left_type != right_type {
+                    self.report(
+                        Diagnostic::error(
+                            "E0202",
+                            format!(
+                                "Type mismatch in arithmetic expression: {} vs {}",
+                                left_type, right_type
+                            ),
+                            span,
+                        )
+                        .with_label(left.span, format!("this is {}", left_type))
+                        .with_label(right.span, format!("this is {}", right_type)),
+                    );
+                    return None;
+                }
+                Some(left_type)
+            }
+            ASTNode::Comparison { left, operator, right } => {
+                let left_type = self.check_expression(left);
+                let right_type = self.check_expression(right);
+                if let (Some(left_type), Some(right_type)) = (left_type, right_type) {
+                    let is_equality = operator == "==" || operator == "!=";
+                    if !is_equality && !is_numeric(&left_type) && left_type != Type::String {
+                        self.report(unsupported_operand(operator, &left_type, left.span));
+                    } else if !self.is_assignable(&left_type, &right_type) && !self.is_assignable(&right_type, &left_type) {
+                        self.report(
+                            Diagnostic::error(
+                                "E0203",
+                                format!(
+                                    "Type mismatch in comparison expression: {} vs {}",
+                                    left_type, right_type
+                                ),
+                                span,
+                            )
+                            .with_label(left.span, format!("this is {}", left_type))
+                            .with_label(right.span, format!("this is {}", right_type)),
+                        );
+                    }
+                }
+                Some(Type::Bool)
+            }
+            ASTNode::Logical { left, operator, right } => {
+                for operand in [left, right] {
+                    self.check_bool_operand(operator, operand);
+                }
+                Some(Type::Bool)
+            }
+            ASTNode::Unary { operator, operand } if operator == "not" => {
+                self.check_bool_operand(operator, operand);
+                Some(Type::Bool)
+            }
+            ASTNode::Unary { operator, operand } => {
+                let operand_type = self.check_expression(operand)?;
+                if !is_numeric(&operand_type) {
+                    self.report(unsupported_operand(operator, &operand_type, operand.span));
+                    return None;
+                }
+                Some(operand_type)
+            }
+            ASTNode::SelfRef => {
+                let self_type = self.symbol_table.get_variable_type("self").cloned();
+                if self_type.is_none() {
+                    self.report(Diagnostic::error("E0218", "'self' can only be used inside a method", span));
+                }
+                self_type
+            }
+            ASTNode::FunctionCall { name, args } => {
+                let arg_types: Vec<Option<Type>> =
+                    args.iter().map(|arg| self.check_expression(arg)).collect();
+                let Some(signature) = self.symbol_table.get_function(name).cloned() else {
+                    self.report(Diagnostic::error(
+                        "E0206",
+                        format!("Unknown function '{}'", name),
+                        span,
+                    ));
+                    return None;
+                };
+                // The array builtins take any array, and their other parameters
+                // and result depend on what it holds.
+                let signature = match arg_types.first() {
+                    Some(Some(Type::Array(element))) => self.array_builtin(name, element).unwrap_or(signature),
+                    _ if self.is_builtin(name, "len") => {
+                        FunctionSignature { params: vec![Type::String], return_type: Type::Int, variadic: false }
+                    }
+                    _ => signature,
+                };
+
+                if let Some(mut error) = self.check_arguments(name, &signature, args, arg_types, span) {
+                    match self.symbol_table.lookup(name) {
+                        Some(declared) if matches!(declared.kind, SymbolKind::Function(_)) => {
+                            error = error.with_label(declared.span, "function declared here");
+                        }
+                        _ => error = error.with_note(format!("'{}' is a native function", name)),
+                    }
+                    self.report(error);
+                } else if self.is_builtin(name, "format") {
+                    self.check_format_template(args);
+                }
+                Some(signature.return_type)
+            }
+            ASTNode::MemberAccess { object, member } => {
+                let (class, members) = self.check_receiver(object, member)?;
+                match self.symbol_table.lookup_in(members, member) {
+                    Some(Symbol { kind: SymbolKind::Field(field_type), .. }) => Some(field_type.clone()),
+                    _ => {
+                        self.no_member(&class, "field", member, span);
+                        None
+                    }
+                }
+            }
+            ASTNode::MethodCall { object, method, args } => {
+                let object_type = self.check_expression(object);
+                let arg_types: Vec<Option<Type>> =
+                    args.iter().map(|arg| self.check_expression(arg)).collect();
+                let object_type = object_type?;
+                if let Some(signature) = self.string_method(&object_type, method) {
+                    let name = format!("string.{}", method);
+                    if let Some(error) = self.check_arguments(&name, &signature, args, arg_types, span) {
+                        self.report(error.with_note(format!("'{}' is a native function", method)));
+                    }
+                    return Some(signature.return_type);
+                }
+                let (class, members) = self.members_of(object, object_type, method)?;
+                let Some(Symbol { kind: SymbolKind::Function(signature), span: declared, .. }) =
+                    self.symbol_table.lookup_in(members, method).cloned()
+                else {
+                    self.no_member(&class, "method", method, span);
+                    return None;
+                };
+                let name = format!("{}.{}", class, method);
+                if let Some(error) = self.check_arguments(&name, &signature, args, arg_types, span) {
+                    self.report(error.with_label(declared, "method declared here"));
+                }
+                Some(signature.return_type)
+            }
+            ASTNode::SuperCall { method, args } => {
+                let arg_types: Vec<Option<Type>> =
+                    args.iter().map(|arg| self.check_expression(arg)).collect();
+                if self.symbol_table.get_variable_type("self").is_none() {
+                    self.report(Diagnostic::error("E0218", "'super' can only be used inside a method", span));
+                    return None;
+                }
+                let Some(base) = self.base_class.clone() else {
+                    self.report(Diagnostic::error(
+                        "E0226",
+                        "'super' can only be used in a class that extends another",
+                        span,
+                    ));
+                    return None;
+                };
+                // An unknown base class was already reported with the class.
+                let members = self.symbol_table.get_class(&base)?;
+                let Some(Symbol { kind: SymbolKind::Function(signature), span: declared, .. }) =
+                    self.symbol_table.lookup_in(members, method).cloned()
+                else {
+                    self.no_member(&base, "method", method, span);
+                    return None;
+                };
+                let name = format!("{}.{}", base, method);
+                if let Some(error) = self.check_arguments(&name, &signature, args, arg_types, span) {
+                    self.report(error.with_label(declared, "method declared here"));
+                }
+                Some(signature.return_type)
+            }
+            ASTNode::New { class, args } => {
+                let arg_types: Vec<Option<Type>> =
+                    args.iter().map(|arg| self.check_expression(arg)).collect();
+                let Some(members) = self.symbol_table.get_class(class) else {
+                    let mut error = Diagnostic::error("E0217", format!("Unknown class '{}'", class), span);
+                    if self.symbol_table.get_interface(class).is_some() {
+                        error = error.with_note(format!("'{}' is an interface, only classes have instances", class));
+                    }
+                    self.report(error);
+                    return None;
+                };
+
+                // Without an `init` method the constructor takes no arguments.
+                let init = match self.symbol_table.lookup_in(members, "init") {
+                    Some(Symbol { kind: SymbolKind::Function(signature), span, .. }) => {
+                        Some((signature.clone(), *span))
+                    }
+                    _ => None,
+                };
+                let signature = match &init {
+                    Some((signature, _)) => signature.clone(),
+                    None => FunctionSignature { params: Vec::new(), return_type: Type::Void, variadic: false },
+                };
+                let name = format!("{}.new", class);
+                if let Some(error) = self.check_arguments(&name, &signature, args, arg_types, span) {
+                    self.report(match init {
+                        Some((_, declared)) => error.with_label(declared, "'init' declared here"),
+                        None => error.with_note(format!("'{}' has no 'init' method", class)),
+                    });
+                }
+                Some(Type::Class(class.clone()))
+            }
+            _ => {
+                self.report(Diagnostic::error(
+                    "E0209",
+                    format!("Unsupported expression type: {:?}", expr.kind),
+                    span,
+                ));
+                None
             }
-        }        
-        ASTNode::Comparison { left, right, operator: _ } => {
-            analyze(left, symbol_table);
-            analyze(right, symbol_table);
-        
-            let left_type = get_expression_type(left, symbol_table);
-            let right_type = get_expression_type(right, symbol_table);
-        
-            if left_type != right_type {
-                panic!(
-                    "Type mismatch in comparison: {:?} vs {:?}",
-                    left_type, right_type
-                );
+        }
+    }
+
+    /// Checks the object whose `member` is being accessed, returning its class
+    /// and the scope holding the class's members.
+    fn check_receiver(&mut self, object: &Node, member: &str) -> Option<(String, ScopeId)> {
+        let object_type = self.check_expression(object)?;
+        self.members_of(object, object_type, member)
+    }
+
+    /// Returns the class of `object`, which is of type `object_type`, and the
+    /// scope holding the class's members.
+    fn members_of(&mut self, object: &Node, object_type: Type, member: &str) -> Option<(String, ScopeId)> {
+        if let Type::Class(class) = &object_type {
+            if let Some(members) = self.symbol_table.get_members(class) {
+                return Some((class.clone(), members));
+            }
+        }
+        self.report(Diagnostic::error(
+            "E0221",
+            format!("Cannot access '{}' on a value of type {}", member, object_type),
+            object.span,
+        ));
+        None
+    }
+
+    /// Checks `object[index]`, returning the type of the element.
+    fn check_index(&mut self, object: &Node, index: &Node) -> Option<Type> {
+        let object_type = self.check_expression(object);
+        let index_type = self.check_expression(index);
+        if let Some(index_type) = index_type.filter(|index_type| *index_type != Type::Int) {
+            self.report(Diagnostic::error(
+                "E0230",
+                format!("Array index must be an int, got {}", index_type),
+                index.span,
+            ));
+        }
+        match object_type? {
+            Type::Array(element_type) => Some(*element_type),
+            object_type => {
+                self.report(Diagnostic::error(
+                    "E0229",
+                    format!("Cannot index a value of type {}", object_type),
+                    object.span,
+                ));
+                None
             }
         }
-        
-        _ => {}
     }
+
+    /// Checks the arguments of a call against `signature`. A wrong number of
+    /// arguments is returned rather than reported, for the caller to add where
+    /// the function was declared.
+    fn check_arguments(
+        &mut self,
+        name: &str,
+        signature: &FunctionSignature,
+        args: &[Node],
+        arg_types: Vec<Option<Type>>,
+        span: Span,
+    ) -> Option<Diagnostic> {
+        if !signature.accepts(args.len()) {
+            let expected = signature.params.len() - signature.variadic as usize;
+            return Some(Diagnostic::error(
+                "E0212",
+                format!(
+                    "Function '{}' expects {}{} argument{}, got {}",
+                    name,
+                    if signature.variadic { "at least " } else { "" },
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span,
+            ));
+        }
+        for (index, (arg, arg_type)) in args.iter().zip(arg_types).enumerate() {
+            let expected = signature.param(index).expect("the argument count was checked");
+            let Some(arg_type) = arg_type else { continue };
+            let matches = match expected {
+                Type::Any => arg_type != Type::Void,
+                expected => self.is_assignable(&arg_type, expected),
+            };
+            if !matches {
+                let expected = match expected {
+                    Type::Any => "a value".to_string(),
+                    Type::Array(element) if **element == Type::Any => "an array".to_string(),
+                    expected => expected.to_string(),
+                };
+                self.report(Diagnostic::error(
+                    "E0213",
+                    format!("Argument {} of '{}' must be {}, got {}", index + 1, name, expected, arg_type),
+                    arg.span,
+                ));
+            }
+        }
+        None
+    }
+
+    /// Reports that the class or interface `owner` has no `kind` called `member`.
+    fn no_member(&mut self, owner: &str, kind: &str, member: &str, span: Span) {
+        let what = if self.symbol_table.get_interface(owner).is_some() { "Interface" } else { "Class" };
+        self.report(Diagnostic::error("E0220", format!("{} '{}' has no {} '{}'", what, owner, kind, member), span));
+    }
+
+    /// Whether `name` resolves to the builtin `builtin` rather than a program function.
+    fn is_builtin(&self, name: &str, builtin: &str) -> bool {
+        name == builtin
+            && self
+                .symbol_table
+                .lookup(name)
+                .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::NativeFunction(_)))
+    }
+
+    /// The signature of `s.method(...)` for a string `s`, which calls the string
+    /// builtin `method` with `s` as its first argument.
+    fn string_method(&self, object_type: &Type, method: &str) -> Option<FunctionSignature> {
+        if *object_type != Type::String || !builtins::STRING_METHODS.contains(&method) || !self.is_builtin(method, method) {
+            return None;
+        }
+        let mut signature = self.symbol_table.get_function(method)?.clone();
+        signature.params.remove(0);
+        Some(signature)
+    }
+
+    /// The signature of the array builtin `name` when called on an array of `element`s.
+    fn array_builtin(&self, name: &str, element: &Type) -> Option<FunctionSignature> {
+        let array = Type::Array(Box::new(element.clone()));
+        let element = element.clone();
+        let (params, return_type) = match name {
+            "len" => (vec![array], Type::Int),
+            "push" => (vec![array, element], Type::Void),
+            "pop" => (vec![array], element),
+            "insert" => (vec![array, Type::Int, element], Type::Void),
+            "remove" => (vec![array, Type::Int], element),
+            _ => return None,
+        };
+        self.is_builtin(name, name).then_some(FunctionSignature { params, return_type, variadic: false })
+    }
+
+    /// Checks a literal `format` template against the number of arguments given for it.
+    fn check_format_template(&mut self, args: &[Node]) {
+        let Some(ASTNode::StringLiteral(template)) = args.first().map(|arg| &arg.kind) else {
+            return;
+        };
+        let given = args.len() - 1;
+        let problem = match builtins::count_placeholders(template) {
+            Ok(count) if count == given => return,
+            Ok(count) => builtins::placeholder_mismatch(count, given),
+            Err(problem) => problem,
+        };
+        self.report(Diagnostic::error("E0215", format!("Invalid format string: {}", problem), args[0].span));
+    }
+}
+
+/// Whether an array of `value`s can be used as an array of `target`s. Arrays
+/// can be changed through any reference to them, so the element types must be
+/// the same, except that an empty array literal fits any array.
+fn fits_element(value: &Type, target: &Type) -> bool {
+    match (value, target) {
+        (Type::Any, _) => true,
+        (Type::Array(value), Type::Array(target)) => fits_element(value, target),
+        _ => value == target,
+    }
+}
+
+/// Whether running `block` always ends in a `ret`: either one of its
+/// statements is a `ret`, an `if` whose branches both return, or a
+/// `while true` loop, which can only be left by returning.
+fn always_returns(block: &[Node]) -> bool {
+    block.iter().any(|node| match &node.kind {
+        ASTNode::Ret { .. } => true,
+        ASTNode::IfElse { then_block, else_block: Some(else_block), .. } => {
+            always_returns(then_block) && always_returns(else_block)
+        }
+        ASTNode::WhileLoop { condition, .. } => matches!(condition.kind, ASTNode::Boolean(true)),
+        _ => false,
+    })
+}
+
+fn is_numeric(value_type: &Type) -> bool {
+    matches!(value_type, Type::Int | Type::Float)
+}
+
+fn unsupported_operand(operator: &str, operand_type: &Type, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        "E0211",
+        format!("Operator '{}' is not supported for {} values", operator, operand_type),
+        span,
+    )
 }
 
-fn get_expression_type(expr: &ASTNode, symbol_table: &SymbolTable) -> Type {
-    match expr {
-        ASTNode::Number(_) => Type::Int,
-        ASTNode::Float(_) => Type::Float,
-        ASTNode::Boolean(_) => Type::Bool,
-        ASTNode::StringLiteral(_) => Type::String,
-        ASTNode::Identifier(name) => symbol_table
-            .get_variable_type(name)
-            .cloned()
-            .unwrap_or_else(|| panic!("Undefined variable: {}", name)),
-        ASTNode::Arithmetic { left, right, .. } => {
-            let left_type = get_expression_type(left, symbol_table);
-            let right_type = get_expression_type(right, symbol_table);
-            if left_type != right_type {
-                panic!(
-                    "Type mismatch in arithmetic expression: {:?} vs {:?}",
-                    left_type, right_type
-                );
-            }
-            left_type
-        }
-        ASTNode::Comparison { left, right, .. } => {
-            let left_type = get_expression_type(left, symbol_table);
-            let right_type = get_expression_type(right, symbol_table);
-            if left_type != right_type {
-                panic!(
-                    "Type mismatch in comparison expression: {:?} vs {:?}",
-                    left_type, right_type
-                );
-            }
-            Type::Bool
-        }
-        ASTNode::FunctionCall { name, .. } => {
-            if !symbol_table.functions.contains_key(name) {
-                panic!("Semantic error: Unknown function '{}'", name);
-            }
-            Type::Int
-        }
-        _ => panic!("Unsupported expression type in get_expression_type: {:?}", expr),
+/// The signature of a function or method declared with `params` and `return_type`.
+fn signature(params: &[Parameter], return_type: &Type) -> FunctionSignature {
+    FunctionSignature {
+        params: params.iter().map(|param| param.param_type.clone()).collect(),
+        return_type: return_type.clone(),
+        variadic: false,
     }
 }
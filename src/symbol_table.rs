@@ -1,4 +1,9 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+use crate::native::Natives;
+use crate::span::Span;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Type {
@@ -6,56 +11,327 @@ pub enum Type {
     Float,
     String,
     Bool,
+    /// The "type" of functions that do not return a value.
+    Void,
+    /// Accepts a value of any type. Only native function parameters have it.
+    Any,
+    /// An instance of the named script class, or for an interface's name,
+    /// of any class implementing it.
+    Class(String),
+    /// `array<T>`, a list of `T`s that can grow and shrink. The empty literal
+    /// `[]` is an `array<any>`, which fits any array type.
+    Array(Box<Type>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Void => write!(f, "void"),
+            Type::Any => write!(f, "any"),
+            Type::Class(name) => write!(f, "{}", name),
+            Type::Array(element) => write!(f, "array<{}>", element),
+        }
+    }
+}
+
+/// The parameter and return types a function was declared with.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FunctionSignature {
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    /// Whether the last parameter may be repeated any number of times,
+    /// including none. Only native functions are variadic.
+    pub variadic: bool,
+}
+
+impl FunctionSignature {
+    /// Whether a call with `count` arguments has the right number of them.
+    pub fn accepts(&self, count: usize) -> bool {
+        if self.variadic {
+            count + 1 >= self.params.len()
+        } else {
+            count == self.params.len()
+        }
+    }
+
+    /// The type expected for the argument at `index`, if there can be one.
+    pub fn param(&self, index: usize) -> Option<&Type> {
+        match self.params.get(index) {
+            Some(param) => Some(param),
+            None if self.variadic => self.params.last(),
+            None => None,
+        }
+    }
+}
+
+/// What a name refers to, along with the type information for that kind of symbol.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SymbolKind {
+    Variable(Type),
+    Parameter(Type),
+    Function(FunctionSignature),
+    /// A function registered by the host, see [`Natives`].
+    NativeFunction(FunctionSignature),
+    /// A class, whose fields and methods are declared in the given scope.
+    Class(ScopeId),
+    /// An interface, whose required methods are declared in the given scope.
+    Interface(ScopeId),
+    Field(Type),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the symbol was declared.
+    pub span: Span,
+}
+
+impl Symbol {
+    /// The type of the symbol when used as a value, or `None` for functions, classes and interfaces.
+    pub fn value_type(&self) -> Option<&Type> {
+        match &self.kind {
+            SymbolKind::Variable(ty) | SymbolKind::Parameter(ty) | SymbolKind::Field(ty) => Some(ty),
+            SymbolKind::Function(_)
+            | SymbolKind::NativeFunction(_)
+            | SymbolKind::Class(_)
+            | SymbolKind::Interface(_) => None,
+        }
+    }
+}
+
+/// Index of a scope in the symbol table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeId(usize);
+
+/// Holds the host's native functions, outside the program so it can shadow them.
+const PRELUDE: ScopeId = ScopeId(0);
+const GLOBAL: ScopeId = ScopeId(1);
+
+#[derive(Debug)]
+struct Scope {
+    parent: Option<ScopeId>,
+    /// For the members of a subclass, the members of its base class, which are
+    /// visible from it before its parent's symbols are.
+    base: Option<ScopeId>,
+    /// For the members of a class, the scopes of the interfaces it implements.
+    interfaces: Vec<ScopeId>,
+    symbols: HashMap<String, Symbol>,
 }
 
+/// A tree of lexical scopes. Each scope owns its bindings and names are
+/// resolved by walking from the current scope up through its parents, so an
+/// inner declaration shadows an outer one until its scope is exited.
+///
+/// Exited scopes are kept so the table still describes the whole program
+/// after analysis. The global scope's parent is a prelude holding the host's
+/// native functions.
 #[derive(Debug)]
 pub struct SymbolTable {
-    pub variables: HashMap<String, Type>,
-    pub functions: HashMap<String, Vec<String>>,
-    pub local_variables_stack: Vec<HashSet<String>>,
+    scopes: Vec<Scope>,
+    current: ScopeId,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
-            variables: HashMap::new(),
-            functions: HashMap::new(),
-            local_variables_stack: vec![HashSet::new()],
+            scopes: vec![
+                Scope { parent: None, base: None, interfaces: Vec::new(), symbols: HashMap::new() },
+                Scope { parent: Some(PRELUDE), base: None, interfaces: Vec::new(), symbols: HashMap::new() },
+            ],
+            current: GLOBAL,
         }
     }
 
-    pub fn declare_variable(&mut self, name: &str, var_type: Type) {
-        if self.local_variables_stack.last().unwrap().contains(name) {
-            panic!("Variable '{}' is already declared in the current scope.", name);
+    /// Creates a table in which the host's native functions are visible.
+    pub fn with_natives(natives: &Natives) -> Self {
+        let mut table = Self::new();
+        for native in natives.iter() {
+            let symbol = Symbol {
+                name: native.name.clone(),
+                kind: SymbolKind::NativeFunction(native.signature.clone()),
+                span: Span::default(),
+            };
+            table.scopes[PRELUDE.0].symbols.insert(native.name.clone(), symbol);
         }
-        if self.variables.contains_key(name) {
-            return;
+        table
+    }
+
+    pub fn current_scope(&self) -> ScopeId {
+        self.current
+    }
+
+    /// Opens a new scope nested in the current one.
+    pub fn enter_scope(&mut self) -> ScopeId {
+        let id = ScopeId(self.scopes.len());
+        self.scopes.push(Scope { parent: Some(self.current), base: None, interfaces: Vec::new(), symbols: HashMap::new() });
+        self.current = id;
+        id
+    }
+
+    /// Makes an exited scope current again, e.g. to check the methods of a
+    /// class whose members were declared ahead of time.
+    pub fn reenter_scope(&mut self, scope: ScopeId) {
+        self.current = scope;
+    }
+
+    /// Makes the members in `base` visible through `scope`, for a class that
+    /// extends another. Fails if `scope` would end up inheriting from itself.
+    pub fn set_base(&mut self, scope: ScopeId, base: ScopeId) -> bool {
+        if self.inherits(base, scope) {
+            return false;
         }
-        self.local_variables_stack.last_mut().unwrap().insert(name.to_string());
-        self.variables.insert(name.to_string(), var_type);
+        self.scopes[scope.0].base = Some(base);
+        true
     }
 
-    pub fn get_variable_type(&self, name: &str) -> Option<&Type> {
-        for scope in self.local_variables_stack.iter().rev() {
-            if scope.contains(name) {
-                return self.variables.get(name);
+    /// Records that the class whose members are in `scope` implements the
+    /// interface whose methods are in `interface`.
+    pub fn add_interface(&mut self, scope: ScopeId, interface: ScopeId) {
+        self.scopes[scope.0].interfaces.push(interface);
+    }
+
+    /// The members a class's member scope inherits, if its class has a base class.
+    pub fn base_of(&self, scope: ScopeId) -> Option<ScopeId> {
+        self.scopes.get(scope.0)?.base
+    }
+
+    /// Whether the class whose members are in `scope` is `base` or extends it,
+    /// directly or not. An interface counts as a base of the classes
+    /// implementing it and of their subclasses.
+    pub fn inherits(&self, scope: ScopeId, base: ScopeId) -> bool {
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            if id == base || self.scopes[id.0].interfaces.contains(&base) {
+                return true;
             }
+            scope = self.scopes[id.0].base;
         }
-        None
+        false
     }
 
+    /// Returns to the parent of the current scope. The global scope is never exited.
     pub fn exit_scope(&mut self) {
-        self.local_variables_stack.pop();
+        if self.current != GLOBAL {
+            if let Some(parent) = self.scopes[self.current.0].parent {
+                self.current = parent;
+            }
+        }
+    }
+
+    /// Declares `name` in the current scope. Redeclaring a name in the same
+    /// scope is an error; declaring it in a nested scope shadows the outer one.
+    pub fn declare(&mut self, name: &str, kind: SymbolKind, span: Span) -> Result<(), Diagnostic> {
+        let symbols = &mut self.scopes[self.current.0].symbols;
+        if let Some(previous) = symbols.get(name) {
+            let (code, message) = match kind {
+                SymbolKind::Function(_) => ("E0208", format!("Function '{}' is already declared.", name)),
+                SymbolKind::Class(_) => ("E0208", format!("Class '{}' is already declared.", name)),
+                SymbolKind::Interface(_) => ("E0208", format!("Interface '{}' is already declared.", name)),
+                _ => (
+                    "E0207",
+                    format!("Variable '{}' is already declared in the current scope.", name),
+                ),
+            };
+            return Err(Diagnostic::error(code, message, span)
+                .with_label(previous.span, "previously declared here"));
+        }
+        symbols.insert(name.to_string(), Symbol { name: name.to_string(), kind, span });
+        Ok(())
+    }
+
+    pub fn declare_variable(&mut self, name: &str, var_type: Type, span: Span) -> Result<(), Diagnostic> {
+        self.declare(name, SymbolKind::Variable(var_type), span)
+    }
+
+    pub fn declare_function(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        self.declare(name, SymbolKind::Function(signature), span)
     }
 
-    pub fn enter_scope(&mut self) {
-        self.local_variables_stack.push(HashSet::new());
+    /// Finds the innermost visible symbol called `name`.
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        let mut scope = Some(self.current);
+        while let Some(id) = scope {
+            if let Some(symbol) = self.lookup_in(id, name) {
+                return Some(symbol);
+            }
+            scope = self.scopes[id.0].parent;
+        }
+        None
     }
 
-    pub fn declare_function(&mut self, name: &str, params: Vec<String>) {
-        if self.functions.contains_key(name) {
-            panic!("Function '{}' is already declared.", name);
+    /// Finds a symbol declared in `scope` or, for a class's members, inherited
+    /// from its base classes. The scope's parents are ignored.
+    pub fn lookup_in(&self, scope: ScopeId, name: &str) -> Option<&Symbol> {
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            let current = self.scopes.get(id.0)?;
+            if let Some(symbol) = current.symbols.get(name) {
+                return Some(symbol);
+            }
+            scope = current.base;
         }
-        self.functions.insert(name.to_string(), params);
+        None
+    }
+
+    /// The symbols declared directly in `scope`, in the order they appear in the source.
+    pub fn symbols_in(&self, scope: ScopeId) -> Vec<&Symbol> {
+        let mut symbols: Vec<&Symbol> = self.scopes[scope.0].symbols.values().collect();
+        symbols.sort_by_key(|symbol| symbol.span.start);
+        symbols
+    }
+
+    /// Returns the type of the visible variable, parameter or field called `name`.
+    pub fn get_variable_type(&self, name: &str) -> Option<&Type> {
+        self.lookup(name)?.value_type()
+    }
+
+    /// Returns where a visible variable was declared.
+    pub fn get_variable_span(&self, name: &str) -> Option<Span> {
+        let symbol = self.lookup(name)?;
+        symbol.value_type().map(|_| symbol.span)
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&FunctionSignature> {
+        match &self.lookup(name)?.kind {
+            SymbolKind::Function(signature) | SymbolKind::NativeFunction(signature) => Some(signature),
+            _ => None,
+        }
+    }
+
+    /// Returns the scope holding the members of the visible class called `name`.
+    pub fn get_class(&self, name: &str) -> Option<ScopeId> {
+        match self.lookup(name)?.kind {
+            SymbolKind::Class(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    /// Returns the scope holding the methods of the visible interface called `name`.
+    pub fn get_interface(&self, name: &str) -> Option<ScopeId> {
+        match self.lookup(name)?.kind {
+            SymbolKind::Interface(methods) => Some(methods),
+            _ => None,
+        }
+    }
+
+    /// Returns the members of the class or interface `name` names, for a value of type `Type::Class(name)`.
+    pub fn get_members(&self, name: &str) -> Option<ScopeId> {
+        self.get_class(name).or_else(|| self.get_interface(name))
     }
 }
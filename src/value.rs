@@ -0,0 +1,350 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// A runtime value, shared by the interpreter and the virtual machine.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Object(Object),
+    Array(Array),
+    Void,
+    /// A value being returned by `ret`, unwound through the enclosing blocks by the interpreter.
+    Return(Box<Value>),
+}
+
+/// How a value is shown to the user, e.g. by `print` and `to_string`.
+/// Floats always show a decimal point so they can be told apart from ints.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Object(object) => write!(f, "<{} object>", object.class()),
+            Value::Array(array) => {
+                write!(f, "[")?;
+                for (index, element) in array.elements().iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match element {
+                        Value::String(s) => write!(f, "{:?}", s)?,
+                        _ => write!(f, "{}", element)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Void => write!(f, "void"),
+            Value::Return(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// An instance of a script class. Cloning an object copies the reference,
+/// not the fields, and two objects are only equal if they are the same instance.
+#[derive(Clone)]
+pub struct Object {
+    class: Rc<str>,
+    /// The field values, in the order the class declares its fields.
+    fields: Rc<RefCell<Vec<Value>>>,
+}
+
+impl Object {
+    /// Creates an instance of `class` whose fields start out as `fields`.
+    pub fn new(class: &str, fields: Vec<Value>) -> Self {
+        Object { class: class.into(), fields: Rc::new(RefCell::new(fields)) }
+    }
+
+    pub fn class(&self) -> &str {
+        &self.class
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.fields.borrow().get(index).cloned()
+    }
+
+    /// Sets a field, returning `false` if the object has no field at `index`.
+    pub fn set(&self, index: usize, value: Value) -> bool {
+        match self.fields.borrow_mut().get_mut(index) {
+            Some(field) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.fields, &other.fields)
+    }
+}
+
+/// Only shows the class, since an object may (indirectly) contain itself.
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.class)
+    }
+}
+
+/// A script array. Like objects, cloning an array copies the reference, so
+/// `push` through one variable is seen through every other; unlike objects,
+/// two arrays are equal if they hold equal elements.
+#[derive(Clone, Default)]
+pub struct Array(Rc<RefCell<Vec<Value>>>);
+
+impl Array {
+    pub fn new(elements: Vec<Value>) -> Self {
+        Array(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn elements(&self) -> std::cell::Ref<'_, Vec<Value>> {
+        self.0.borrow()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.0.borrow().get(index).cloned()
+    }
+
+    /// Replaces an element, returning `false` if `index` is out of bounds.
+    pub fn set(&self, index: usize, value: Value) -> bool {
+        match self.0.borrow_mut().get_mut(index) {
+            Some(element) => {
+                *element = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn push(&self, value: Value) {
+        self.0.borrow_mut().push(value);
+    }
+
+    pub fn pop(&self) -> Option<Value> {
+        self.0.borrow_mut().pop()
+    }
+
+    /// Inserts before `index`, which may be the length to append. Returns
+    /// `false` if `index` is out of bounds.
+    pub fn insert(&self, index: usize, value: Value) -> bool {
+        let mut elements = self.0.borrow_mut();
+        if index > elements.len() {
+            return false;
+        }
+        elements.insert(index, value);
+        true
+    }
+
+    pub fn remove(&self, index: usize) -> Option<Value> {
+        let mut elements = self.0.borrow_mut();
+        (index < elements.len()).then(|| elements.remove(index))
+    }
+}
+
+impl PartialEq for Array {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0) || *self.0.borrow() == *other.0.borrow()
+    }
+}
+
+impl fmt::Debug for Array {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.0.borrow().iter()).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl ArithmeticOp {
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Some(match symbol {
+            "+" => ArithmeticOp::Add,
+            "-" => ArithmeticOp::Subtract,
+            "*" => ArithmeticOp::Multiply,
+            "/" => ArithmeticOp::Divide,
+            "%" => ArithmeticOp::Remainder,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+            ArithmeticOp::Remainder => "%",
+        }
+    }
+}
+
+impl fmt::Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonOp {
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl ComparisonOp {
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Some(match symbol {
+            "<" => ComparisonOp::Less,
+            ">" => ComparisonOp::Greater,
+            "<=" => ComparisonOp::LessEqual,
+            ">=" => ComparisonOp::GreaterEqual,
+            "==" => ComparisonOp::Equal,
+            "!=" => ComparisonOp::NotEqual,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ComparisonOp::Less => "<",
+            ComparisonOp::Greater => ">",
+            ComparisonOp::LessEqual => "<=",
+            ComparisonOp::GreaterEqual => ">=",
+            ComparisonOp::Equal => "==",
+            ComparisonOp::NotEqual => "!=",
+        }
+    }
+}
+
+impl fmt::Display for ComparisonOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// Why [`arithmetic`] or [`negate`] could not produce a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    Overflow,
+    Unsupported,
+}
+
+/// Applies a binary arithmetic operator.
+///
+/// Integer arithmetic is checked: dividing by zero or leaving the `i64`
+/// range is an error rather than a panic or a silent wrap. Float arithmetic
+/// follows IEEE 754, so `1.0 / 0.0` is infinity and `0.0 / 0.0` is NaN.
+/// `+` also concatenates strings.
+pub fn arithmetic(op: ArithmeticOp, left: &Value, right: &Value) -> Result<Value, ArithmeticError> {
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            if b == 0 && matches!(op, ArithmeticOp::Divide | ArithmeticOp::Remainder) {
+                return Err(ArithmeticError::DivisionByZero);
+            }
+            let result = match op {
+                ArithmeticOp::Add => a.checked_add(b),
+                ArithmeticOp::Subtract => a.checked_sub(b),
+                ArithmeticOp::Multiply => a.checked_mul(b),
+                ArithmeticOp::Divide => a.checked_div(b),
+                ArithmeticOp::Remainder => a.checked_rem(b),
+            };
+            result.map(Value::Int).ok_or(ArithmeticError::Overflow)
+        }
+        (Value::Float(a), Value::Float(b)) => Ok(Value::Float(match op {
+            ArithmeticOp::Add => a + b,
+            ArithmeticOp::Subtract => a - b,
+            ArithmeticOp::Multiply => a * b,
+            ArithmeticOp::Divide => a / b,
+            ArithmeticOp::Remainder => a % b,
+        })),
+        (Value::String(a), Value::String(b)) if op == ArithmeticOp::Add => {
+            Ok(Value::String(format!("{}{}", a, b)))
+        }
+        _ => Err(ArithmeticError::Unsupported),
+    }
+}
+
+/// Why [`element`] could not find an array element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexError {
+    NotAnArray,
+    NotAnInt,
+    OutOfBounds { index: i64, len: usize },
+}
+
+/// Resolves `array[index]` to the array and a position within its bounds.
+pub fn element(array: &Value, index: &Value) -> Result<(Array, usize), IndexError> {
+    let Value::Array(array) = array else { return Err(IndexError::NotAnArray) };
+    let Value::Int(index) = *index else { return Err(IndexError::NotAnInt) };
+    let len = array.len();
+    match usize::try_from(index) {
+        Ok(position) if position < len => Ok((array.clone(), position)),
+        _ => Err(IndexError::OutOfBounds { index, len }),
+    }
+}
+
+/// Applies unary `-`, with the same overflow rules as [`arithmetic`].
+pub fn negate(value: &Value) -> Result<Value, ArithmeticError> {
+    match value {
+        Value::Int(n) => n.checked_neg().map(Value::Int).ok_or(ArithmeticError::Overflow),
+        Value::Float(f) => Ok(Value::Float(-f)),
+        _ => Err(ArithmeticError::Unsupported),
+    }
+}
+
+/// Applies a comparison operator, or returns `None` if the operands can't be compared.
+/// Numbers and strings (by code point, so `"B" < "a"`) are ordered; every
+/// type supports `==` and `!=` against itself, objects comparing by identity
+/// and arrays element by element.
+pub fn compare(op: ComparisonOp, left: &Value, right: &Value) -> Option<Value> {
+    let ordering = match (left, right) {
+        (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+        (Value::Bool(_), Value::Bool(_))
+        | (Value::Object(_), Value::Object(_))
+        | (Value::Array(_), Value::Array(_)) => {
+            return match op {
+                ComparisonOp::Equal => Some(Value::Bool(left == right)),
+                ComparisonOp::NotEqual => Some(Value::Bool(left != right)),
+                _ => None,
+            };
+        }
+        _ => return None,
+    };
+    Some(Value::Bool(match op {
+        ComparisonOp::Less => ordering.is_some_and(|o| o.is_lt()),
+        ComparisonOp::Greater => ordering.is_some_and(|o| o.is_gt()),
+        ComparisonOp::LessEqual => ordering.is_some_and(|o| o.is_le()),
+        ComparisonOp::GreaterEqual => ordering.is_some_and(|o| o.is_ge()),
+        ComparisonOp::Equal => ordering.is_some_and(|o| o.is_eq()),
+        ComparisonOp::NotEqual => !ordering.is_some_and(|o| o.is_eq()),
+    }))
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::builtins::{self, Output};
+use crate::bytecode::{Class, Instruction, Program, SCRIPT};
+use crate::diagnostic::Diagnostic;
+use crate::native::{NativeFunction, Natives};
+use crate::runtime_error::{
+    arity_mismatch, division_by_zero, function_not_found, invalid_comparison, invalid_for_range, invalid_index,
+    not_a_bool, no_member, not_an_object, not_iterable, overflow, undefined_variable, unsupported_arithmetic,
+    unsupported_unary, stack_overflow, RuntimeError, StackFrame, MAX_CALL_DEPTH,
+};
+use crate::span::Span;
+use crate::value::{arithmetic, compare, element, negate, ArithmeticError, Array, Object, Value};
+
+/// A function call in progress. Its locals are `stack[base..base + locals]`.
+struct CallFrame {
+    function: u16,
+    ip: usize,
+    base: usize,
+    call_site: Option<Span>,
+}
+
+/// A stack-based virtual machine executing a compiled [`Program`].
+///
+/// Behaves like the tree-walking [`Interpreter`](crate::interpreter::Interpreter):
+/// the top-level code runs first, then `run` is called if the program has one,
+/// and runtime errors carry the same diagnostics and stack traces.
+pub struct Vm<'a> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    /// `None` until the global's declaration has run.
+    globals: Vec<Option<Value>>,
+    /// The host's implementation of each of [`Program::natives`], if it registered one.
+    natives: Vec<Option<Rc<NativeFunction>>>,
+    /// The classes by name, for looking up the members of objects.
+    classes: HashMap<&'a str, &'a Class>,
+}
+
+impl<'a> Vm<'a> {
+    /// Creates a VM whose builtins print to stdout.
+    pub fn new(program: &'a Program) -> Self {
+        Self::with_natives(program, &builtins::natives(Output::stdout()))
+    }
+
+    /// Creates a VM that calls into the host's `natives`. A native the program
+    /// needs but the host did not register fails when it is called.
+    pub fn with_natives(program: &'a Program, natives: &Natives) -> Self {
+        Vm {
+            program,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: vec![None; program.globals.len()],
+            natives: program.natives.iter().map(|name| natives.get(name).cloned()).collect(),
+            classes: program.classes.iter().map(|class| (class.name.as_str(), class)).collect(),
+        }
+    }
+
+    /// Runs the program and returns the value returned by `run`, or void if there is none.
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        self.stack.clear();
+        self.frames.clear();
+        self.call(SCRIPT, 0, None)?;
+        self.execute()?;
+        match self.program.entry {
+            Some(entry) => {
+                self.call(entry, 0, None)?;
+                self.execute()
+            }
+            None => Ok(Value::Void),
+        }
+    }
+
+    /// Attaches the current call stack to a runtime failure.
+    fn fail(&self, diagnostic: Diagnostic) -> RuntimeError {
+        RuntimeError {
+            diagnostic: Box::new(diagnostic),
+            stack_trace: self
+                .frames
+                .iter()
+                .rev()
+                .filter(|frame| frame.function != SCRIPT)
+                .map(|frame| StackFrame {
+                    function: self.program.functions[frame.function as usize].name.clone(),
+                    call_site: frame.call_site,
+                })
+                .collect(),
+        }
+    }
+
+    /// Pushes a frame for `function`, whose `argc` arguments are on top of the stack.
+    fn call(&mut self, function: u16, argc: u8, call_site: Option<Span>) -> Result<(), RuntimeError> {
+        let compiled = &self.program.functions[function as usize];
+        if argc != compiled.arity {
+            return Err(self.fail(arity_mismatch(
+                &compiled.name,
+                compiled.arity as usize,
+                argc as usize,
+                call_site.unwrap_or_default(),
+            )));
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(self.fail(stack_overflow(MAX_CALL_DEPTH, call_site.unwrap_or_default())));
+        }
+        let base = self.stack.len() - argc as usize;
+        self.stack.resize(base + compiled.locals as usize, Value::Void);
+        self.frames.push(CallFrame { function, ip: 0, base, call_site });
+        Ok(())
+    }
+
+    /// The host's implementation of the string builtin `name`, which the
+    /// compiler lists among the natives of any program calling it as a method.
+    fn string_method(&self, name: &str) -> Option<Rc<NativeFunction>> {
+        if !builtins::STRING_METHODS.contains(&name) {
+            return None;
+        }
+        let index = self.program.natives.iter().position(|native| native == name)?;
+        self.natives[index].clone()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("compiled code never underflows the stack")
+    }
+
+    /// Executes instructions until the frame that was current on entry returns.
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        let program = self.program;
+        let depth = self.frames.len();
+        loop {
+            let frame = self.frames.last_mut().expect("execute needs a frame");
+            let function = &program.functions[frame.function as usize];
+            let at = frame.ip;
+            let instruction = function.code[at];
+            let span = function.spans[at];
+            frame.ip += 1;
+            let base = frame.base;
+
+            match instruction {
+                Instruction::Constant(index) => self.stack.push(program.constants[index as usize].clone()),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Dup => {
+                    let value = self.stack.last().expect("compiled code never underflows the stack").clone();
+                    self.stack.push(value);
+                }
+                Instruction::GetLocal(slot) => {
+                    let value = self.stack[base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetLocal(slot) => {
+                    let value = self.pop();
+                    self.stack[base + slot as usize] = value;
+                }
+                Instruction::GetGlobal(index) => match &self.globals[index as usize] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(self.fail(undefined_variable(&program.globals[index as usize], span))),
+                },
+                Instruction::SetGlobal(index) => {
+                    let value = self.pop();
+                    match &mut self.globals[index as usize] {
+                        Some(global) => *global = value,
+                        None => {
+                            return Err(self.fail(undefined_variable(&program.globals[index as usize], span)))
+                        }
+                    }
+                }
+                Instruction::DefineGlobal(index) => {
+                    let value = self.pop();
+                    self.globals[index as usize] = Some(value);
+                }
+                Instruction::Arithmetic(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    match arithmetic(op, &left, &right) {
+                        Ok(value) => self.stack.push(value),
+                        Err(ArithmeticError::DivisionByZero) => {
+                            // The divisor is always computed by the instruction just before this one.
+                            let divisor = function.spans[at - 1];
+                            return Err(self.fail(division_by_zero(span, divisor)));
+                        }
+                        Err(ArithmeticError::Overflow) => return Err(self.fail(overflow(op.symbol(), span))),
+                        Err(ArithmeticError::Unsupported) => {
+                            return Err(self.fail(unsupported_arithmetic(op, &left, &right, span)))
+                        }
+                    }
+                }
+                Instruction::Compare(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    match compare(op, &left, &right) {
+                        Some(value) => self.stack.push(value),
+                        None => return Err(self.fail(invalid_comparison(op, &left, &right, span))),
+                    }
+                }
+                Instruction::Negate => {
+                    let value = self.pop();
+                    match negate(&value) {
+                        Ok(value) => self.stack.push(value),
+                        Err(ArithmeticError::Overflow) => return Err(self.fail(overflow("-", span))),
+                        Err(_) => return Err(self.fail(unsupported_unary("-", &value, span))),
+                    }
+                }
+                Instruction::Not => match self.pop() {
+                    Value::Bool(b) => self.stack.push(Value::Bool(!b)),
+                    value => return Err(self.fail(unsupported_unary("not", &value, span))),
+                },
+                Instruction::BuildString(count) => {
+                    let parts = self.stack.split_off(self.stack.len() - count as usize);
+                    let string = parts.iter().map(Value::to_string).collect();
+                    self.stack.push(Value::String(string));
+                }
+                Instruction::BuildArray(count) => {
+                    let elements = self.stack.split_off(self.stack.len() - count as usize);
+                    self.stack.push(Value::Array(Array::new(elements)));
+                }
+                Instruction::GetIndex => {
+                    let index = self.pop();
+                    let array = self.pop();
+                    match element(&array, &index) {
+                        Ok((array, position)) => self.stack.push(array.get(position).expect("the index was checked")),
+                        Err(err) => return Err(self.fail(invalid_index(err, &array, &index, span))),
+                    }
+                }
+                Instruction::SetIndex => {
+                    let index = self.pop();
+                    let array = self.pop();
+                    let value = self.pop();
+                    match element(&array, &index) {
+                        Ok((array, position)) => {
+                            array.set(position, value);
+                        }
+                        Err(err) => return Err(self.fail(invalid_index(err, &array, &index, span))),
+                    }
+                }
+                Instruction::Length => match self.pop() {
+                    Value::Array(array) => self.stack.push(Value::Int(array.len() as i64)),
+                    value => return Err(self.fail(not_iterable(&value, span))),
+                },
+                Instruction::CheckBool(condition) => {
+                    let value = self.stack.last().expect("compiled code never underflows the stack");
+                    if !matches!(value, Value::Bool(_)) {
+                        return Err(self.fail(not_a_bool(condition.describe(), value, span)));
+                    }
+                }
+                Instruction::CheckRange => {
+                    let bounds = &self.stack[self.stack.len() - 2..];
+                    if !matches!(bounds, [Value::Int(_), Value::Int(_)]) {
+                        return Err(self.fail(invalid_for_range(span)));
+                    }
+                }
+                Instruction::Jump(target) => self.jump(target),
+                Instruction::JumpIfFalse(target, condition) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.jump(target),
+                    value => return Err(self.fail(not_a_bool(condition.describe(), &value, span))),
+                },
+                Instruction::Call(function, argc) => self.call(function, argc, Some(span))?,
+                Instruction::CallNative(index, argc) => {
+                    let Some(native) = self.natives[index as usize].clone() else {
+                        return Err(self.fail(function_not_found(&program.natives[index as usize], span)));
+                    };
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    match native.call(&args, span) {
+                        Ok(value) => self.stack.push(value),
+                        Err(err) => return Err(self.fail(err)),
+                    }
+                }
+                Instruction::New(index) => {
+                    let class = &program.classes[index as usize];
+                    let mut fields = vec![Value::Void; class.fields.len()];
+                    for &field in &class.arrays {
+                        fields[field as usize] = Value::Array(Array::new(Vec::new()));
+                    }
+                    self.stack.push(Value::Object(Object::new(&class.name, fields)));
+                }
+                Instruction::GetField(field) => match self.pop() {
+                    Value::Object(object) => match object.get(field as usize) {
+                        Some(value) => self.stack.push(value),
+                        None => return Err(self.fail(not_an_object(&Value::Object(object), span))),
+                    },
+                    value => return Err(self.fail(not_an_object(&value, span))),
+                },
+                Instruction::SetField(field) => {
+                    let target = self.pop();
+                    let value = self.pop();
+                    match target {
+                        Value::Object(object) if object.set(field as usize, value) => {}
+                        target => return Err(self.fail(not_an_object(&target, span))),
+                    }
+                }
+                Instruction::GetMember(name) => {
+                    let object = self.pop();
+                    let (object, field) = self.field(&object, name, span)?;
+                    self.stack.push(object.get(field).expect("the field was found"));
+                }
+                Instruction::SetMember(name) => {
+                    let object = self.pop();
+                    let value = self.pop();
+                    let (object, field) = self.field(&object, name, span)?;
+                    object.set(field, value);
+                }
+                Instruction::Invoke(name, argc) => {
+                    let name = self.name(name);
+                    let receiver = &self.stack[self.stack.len() - 1 - argc as usize];
+                    let native = match receiver {
+                        Value::String(_) => self.string_method(name),
+                        _ => None,
+                    };
+                    if let Some(native) = native {
+                        let args = self.stack.split_off(self.stack.len() - 1 - argc as usize);
+                        match native.call(&args, span) {
+                            Ok(value) => self.stack.push(value),
+                            Err(err) => return Err(self.fail(err)),
+                        }
+                        continue;
+                    }
+                    let method = match receiver {
+                        Value::Object(object) => self.classes.get(object.class()).and_then(|class| {
+                            class.methods.iter().find(|(method, _)| method == name).map(|(_, function)| *function)
+                        }),
+                        _ => None,
+                    };
+                    let Some(method) = method else {
+                        return Err(self.fail(no_member(name, receiver, span)));
+                    };
+                    // Report the count without the receiver, like the interpreter does.
+                    let compiled = &program.functions[method as usize];
+                    if compiled.arity != argc + 1 {
+                        let expected = compiled.arity as usize - 1;
+                        return Err(self.fail(arity_mismatch(&compiled.name, expected, argc as usize, span)));
+                    }
+                    self.call(method, argc + 1, Some(span))?;
+                }
+                Instruction::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("returning from a frame");
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() < depth {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    /// The member name held by a string constant.
+    fn name(&self, index: u16) -> &'a str {
+        match &self.program.constants[index as usize] {
+            Value::String(name) => name,
+            other => unreachable!("member names are string constants, got {:?}", other),
+        }
+    }
+
+    /// Finds the field named by a string constant, failing unless `value` is an object with such a field.
+    fn field(&self, value: &Value, name: u16, span: Span) -> Result<(Object, usize), RuntimeError> {
+        let name = self.name(name);
+        if let Value::Object(object) = value {
+            let class = self.classes.get(object.class());
+            if let Some(field) = class.and_then(|class| class.fields.iter().position(|field| field == name)) {
+                return Ok((object.clone(), field));
+            }
+        }
+        Err(self.fail(no_member(name, value, span)))
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().expect("jumping within a frame").ip = target as usize;
+    }
+}
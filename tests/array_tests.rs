@@ -0,0 +1,272 @@
+use neutron::ast::Node;
+use neutron::builtins::{self, Output};
+use neutron::bytecode_file;
+use neutron::compiler::compile_with_natives;
+use neutron::diagnostic::Diagnostic;
+use neutron::interpreter::{Interpreter, RuntimeError, Value};
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::semantic::analyze;
+use neutron::symbol_table::SymbolTable;
+use neutron::vm::Vm;
+
+fn parse(input: &str) -> Node {
+    let mut parser = Parser::new(Lexer::new(input));
+    parser.parse_program().unwrap()
+}
+
+fn check(input: &str) -> Vec<Diagnostic> {
+    let natives = builtins::natives(Output::stdout());
+    match analyze(&parse(input), &mut SymbolTable::with_natives(&natives)) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+fn codes(input: &str) -> Vec<&'static str> {
+    check(input).iter().map(|e| e.code).collect()
+}
+
+/// Runs `input` on the interpreter, the VM and the VM after a round trip
+/// through a bytecode file, asserting they all agree. Returns the result and
+/// what was printed.
+fn run_all(input: &str) -> (Value, String) {
+    assert_eq!(check(input), Vec::new(), "checking {}", input);
+    let ast = parse(input);
+
+    let (output, interpreted) = Output::buffer();
+    let result = Interpreter::with_output(output).interpret(&ast).unwrap();
+
+    let (output, executed) = Output::buffer();
+    let natives = builtins::natives(output);
+    let program = compile_with_natives(&ast, &natives).unwrap();
+    assert_eq!(Vm::with_natives(&program, &natives).run(), Ok(result.clone()), "running {}", input);
+    let loaded = bytecode_file::read(&bytecode_file::write(&program)).unwrap();
+    assert_eq!(Vm::with_natives(&loaded, &natives).run(), Ok(result.clone()), "running {}", input);
+
+    let printed = interpreted.borrow().clone();
+    assert_eq!(executed.borrow().as_str(), printed.repeat(2), "running {}", input);
+    (result, printed)
+}
+
+/// Runs a program that fails on both the interpreter and the VM, asserting they fail the same way.
+fn fail_all(input: &str) -> RuntimeError {
+    let ast = parse(input);
+    let interpreted = Interpreter::with_output(Output::buffer().0).interpret(&ast).unwrap_err();
+    let natives = builtins::natives(Output::buffer().0);
+    let program = compile_with_natives(&ast, &natives).unwrap();
+    assert_eq!(Vm::with_natives(&program, &natives).run(), Err(interpreted.clone()), "running {}", input);
+    interpreted
+}
+
+const INVENTORY: &str = "
+class Item then
+    var name string
+    var weight int
+
+    func init(n string, w int) then
+        name = n
+        weight = w
+    end
+end
+
+func heaviest(items array<Item>) Item then
+    var best Item
+    best = items[0]
+    for item in items
+        if item.weight > best.weight then
+            best = item
+        end
+    end
+    ret best
+end
+
+func total(weights array<int>) int then
+    var sum int
+    sum = 0
+    for w in weights
+        sum = sum + w
+    end
+    ret sum
+end
+
+func run() int then
+    var items array<Item>
+    items = []
+    push(items, Item.new(\"rope\", 3))
+    push(items, Item.new(\"anvil\", 40))
+    insert(items, 0, Item.new(\"torch\", 1))
+    println(\"heaviest is {heaviest(items).name} of {len(items)} items\")
+
+    var weights array<int>
+    weights = [5, 1, 4]
+    weights[1] = weights[0] * 2
+    push(weights, 7)
+    println(weights)
+    println(\"removed {remove(weights, 0)}, popped {pop(weights)}, left {weights}\")
+
+    var grid array<array<int>>
+    grid = [[], [1, 2]]
+    push(grid[0], 9)
+    grid[1][0] = grid[0][0] + 1
+    println(grid)
+    println(split(\"a,b,,c\", \",\"))
+
+    var doubled array<int>
+    doubled = [1, 2, 3]
+    for n in doubled
+        if n < 3 then
+            push(doubled, n * 10)
+        end
+    end
+    println(doubled)
+    println([1, 2] == [1, 2])
+    ret total(weights) + total(doubled)
+end
+";
+
+#[test]
+fn test_arrays_run_the_same_everywhere() {
+    let (result, printed) = run_all(INVENTORY);
+    assert_eq!(result, Value::Int(10 + 4 + 36));
+    assert_eq!(
+        printed,
+        "heaviest is anvil of 3 items\n\
+         [5, 10, 4, 7]\n\
+         removed 5, popped 7, left [10, 4]\n\
+         [[9], [10, 2]]\n\
+         [\"a\", \"b\", \"\", \"c\"]\n\
+         [1, 2, 3, 10, 20]\n\
+         true\n"
+    );
+}
+
+#[test]
+fn test_arrays_are_shared_between_variables() {
+    let input = "func fill(xs array<int>) then
+        push(xs, 1)
+        xs[0] = 5
+    end
+    func run() int then
+        var a array<int>
+        var b array<int>
+        a = [0]
+        b = a
+        fill(b)
+        ret a[0] + len(a)
+    end";
+    assert_eq!(run_all(input).0, Value::Int(7));
+}
+
+#[test]
+fn test_arrays_are_type_checked() {
+    let body = |statements: &str| codes(&format!("func f() then var xs array<int> var s string {} end", statements));
+    assert_eq!(body("xs = [1, 2] xs = [] xs[0] = 3 push(xs, len(xs))"), Vec::<&str>::new());
+    assert_eq!(body("xs = [\"a\"]"), vec!["E0201"]);
+    assert_eq!(body("xs[0] = \"a\""), vec!["E0201"]);
+    assert_eq!(body("s = xs[0]"), vec!["E0201"]);
+    assert_eq!(body("xs = [1, \"a\"]"), vec!["E0231"]);
+    assert_eq!(body("s[0] = \"a\""), vec!["E0229"]);
+    assert_eq!(body("xs[\"a\"] = 1"), vec!["E0230"]);
+    assert_eq!(body("push(xs, 1.5)"), vec!["E0213"]);
+    assert_eq!(body("push(s, 1)"), vec!["E0213"]);
+    assert_eq!(body("s = pop(xs)"), vec!["E0201"]);
+    assert_eq!(body("insert(xs, \"0\", 1)"), vec!["E0213"]);
+    assert_eq!(body("len(5)"), vec!["E0213"]);
+    assert_eq!(body("for x in s end"), vec!["E0205"]);
+    assert_eq!(body("for x in xs s = x end"), vec!["E0201"]);
+    assert_eq!(codes("var xs array<Missing>"), vec!["E0217"]);
+
+    let errors = check("func f() then var xs array<int> xs = [1, 2.5] end");
+    assert_eq!(errors[0].message, "Array elements must all have the same type: expected int, got float");
+    let errors = check("func f() then var xs array<int> push(5, 1) end");
+    assert_eq!(errors[0].message, "Argument 1 of 'push' must be an array, got int");
+    // Arrays can be changed through any reference, so the element types must match exactly.
+    let errors = check(
+        "interface Named then func name() string end
+        class Item implements Named then func name() string then ret \"item\" end end
+        func f() then var named array<Named> var items array<Item> named = items end",
+    );
+    assert_eq!(errors[0].message, "Type mismatch in assignment to 'named': expected array<Named>, got array<Item>");
+}
+
+#[test]
+fn test_out_of_bounds_is_a_runtime_error() {
+    let err = fail_all("func run() int then var xs array<int> xs = [1, 2, 3] ret xs[3] end");
+    assert_eq!(err.diagnostic.code, "E0310");
+    assert_eq!(err.diagnostic.message, "Index 3 is out of bounds for an array of length 3");
+
+    let err = fail_all("func run() then var xs array<int> xs = [] xs[-1] = 1 end");
+    assert_eq!(err.diagnostic.message, "Index -1 is out of bounds for an array of length 0");
+
+    let err = fail_all("func run() then var xs array<int> xs[0] = 1 end");
+    assert_eq!(err.diagnostic.message, "Index 0 is out of bounds for an array of length 0");
+}
+
+#[test]
+fn test_declared_arrays_start_out_empty() {
+    let input = "class Bag then
+        var items array<string>
+    end
+    class Crate extends Bag then
+        var counts array<int>
+        func fill() then
+            push(items, \"nail\")
+            push(counts, len(items))
+        end
+    end
+    var log array<string>
+    func run() int then
+        var xs array<int>
+        var grid array<array<int>>
+        for x in xs
+            ret -1
+        end
+        push(xs, 1)
+        push(grid, xs)
+        var a Crate
+        var b Crate
+        a = Crate.new()
+        b = Crate.new()
+        a.fill()
+        a.fill()
+        push(log, \"done\")
+        println(log)
+        ret len(xs) + len(grid) + a.counts[1] + len(b.items)
+    end";
+    let (result, printed) = run_all(input);
+    assert_eq!(result, Value::Int(1 + 1 + 2));
+    assert_eq!(printed, "[\"done\"]\n");
+}
+
+#[test]
+fn test_array_builtins_leave_the_elements_alone() {
+    // Only an unchecked program can put a void in an array, which shows the
+    // builtins don't go through the elements to check their arguments.
+    let input = "func nothing() then end
+    func run() int then
+        var xs array<int>
+        xs = [nothing(), nothing()]
+        push(xs, 1)
+        ret len(xs)
+    end";
+    let result = Interpreter::with_output(Output::buffer().0).interpret(&parse(input));
+    assert_eq!(result, Ok(Value::Int(3)));
+}
+
+#[test]
+fn test_array_builtins_fail_at_runtime() {
+    let fails = |call: &str| fail_all(&format!("func run() then var xs array<int> xs = [1] {} end", call)).diagnostic;
+    let err = fails("pop(xs) pop(xs)");
+    assert_eq!(err.code, "E0308");
+    assert_eq!(err.message, "Native function 'pop' failed: cannot pop from an empty array");
+    assert_eq!(
+        fails("insert(xs, 2, 0)").message,
+        "Native function 'insert' failed: index 2 is out of bounds for an array of length 1"
+    );
+    assert_eq!(
+        fails("remove(xs, 1)").message,
+        "Native function 'remove' failed: index 1 is out of bounds for an array of length 1"
+    );
+    assert_eq!(fails("split(\"a\", \"\")").message, "Native function 'split' failed: the separator can't be empty");
+}
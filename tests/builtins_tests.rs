@@ -0,0 +1,192 @@
+use neutron::ast::Node;
+use neutron::builtins::{self, Output};
+use neutron::compiler::compile_with_natives;
+use neutron::diagnostic::Diagnostic;
+use neutron::interpreter::{Interpreter, Value};
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::semantic::analyze;
+use neutron::symbol_table::SymbolTable;
+use neutron::vm::Vm;
+
+fn parse(input: &str) -> Node {
+    let mut parser = Parser::new(Lexer::new(input));
+    parser.parse_program().unwrap()
+}
+
+fn check(input: &str) -> Vec<Diagnostic> {
+    let natives = builtins::natives(Output::stdout());
+    match analyze(&parse(input), &mut SymbolTable::with_natives(&natives)) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+/// Runs `input` on the interpreter and the VM, asserting both print the same
+/// thing and return the same result. Returns what was printed.
+fn output_of(input: &str) -> String {
+    assert_eq!(check(input), Vec::new(), "checking {}", input);
+    let ast = parse(input);
+
+    let (output, interpreted) = Output::buffer();
+    let result = Interpreter::with_output(output).interpret(&ast);
+
+    let (output, executed) = Output::buffer();
+    let natives = builtins::natives(output);
+    let program = compile_with_natives(&ast, &natives).unwrap();
+    assert_eq!(result, Vm::with_natives(&program, &natives).run(), "running {}", input);
+
+    assert_eq!(interpreted, executed, "running {}", input);
+    let printed = interpreted.borrow().clone();
+    printed
+}
+
+#[test]
+fn test_print_writes_to_the_output() {
+    let input = "func run() then
+        print(\"a\")
+        print(1)
+        println(\"\")
+        println(2.0)
+        println(1 < 2)
+        for i = 0 3
+            print(i)
+        end
+    end";
+    assert_eq!(output_of(input), "a1\n2.0\ntrue\n012");
+}
+
+#[test]
+fn test_to_string_and_len() {
+    let input = "func run() then
+        var s string
+        s = to_string(-1.5)
+        println(s)
+        println(len(s))
+        println(len(\"héllo\"))
+        println(to_string(false))
+    end";
+    assert_eq!(output_of(input), "-1.5\n4\n5\nfalse\n");
+}
+
+#[test]
+fn test_format() {
+    let input = "func run() then
+        var name string
+        name = \"world\"
+        println(format(\"hello {}!\", name))
+        println(format(\"{} + {} = {}\", 1, 2, 1 + 2))
+        println(format(r\"{{}} {}\", true))
+        println(\"{{{name}}}\")
+        println(format(\"no placeholders\"))
+    end";
+    assert_eq!(output_of(input), "hello world!\n1 + 2 = 3\n{} true\n{world}\nno placeholders\n");
+
+    assert_eq!(builtins::format("{}}", &[Value::Int(1)]), Err("unmatched '}' in format string".to_string()));
+    assert_eq!(builtins::count_placeholders("{{{}}}"), Ok(1));
+}
+
+#[test]
+fn test_builtins_are_type_checked() {
+    let errors = check("func run() then println(len(1)) end");
+    assert_eq!(errors[0].code, "E0213");
+    assert_eq!(errors[0].message, "Argument 1 of 'len' must be string, got int");
+
+    let errors = check("func f() then end func run() then print(f()) end");
+    assert_eq!(errors[0].code, "E0213");
+    assert_eq!(errors[0].message, "Argument 1 of 'print' must be a value, got void");
+
+    let errors = check("func run() then var x int x = to_string(1) end");
+    assert_eq!(errors[0].code, "E0201");
+
+    let errors = check("func run() then println(format()) end");
+    assert_eq!(errors[0].code, "E0212");
+    assert_eq!(errors[0].message, "Function 'format' expects at least 1 argument, got 0");
+
+    let errors = check("func run() then println(format(\"{} and {}\", 1)) end");
+    assert_eq!(errors[0].code, "E0215");
+    assert_eq!(
+        errors[0].message,
+        "Invalid format string: format string has 2 placeholders but 1 argument was given"
+    );
+
+    let errors = check("func run() then println(format(\"\\{\")) end");
+    assert_eq!(errors[0].message, "Invalid format string: unmatched '{' in format string");
+}
+
+#[test]
+fn test_format_errors_at_runtime() {
+    let input = "func run() then
+        var template string
+        template = \"{} {}\"
+        println(format(template, 1))
+    end";
+    let err = Interpreter::new().interpret(&parse(input)).unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0308");
+    assert_eq!(
+        err.diagnostic.message,
+        "Native function 'format' failed: format string has 2 placeholders but 1 argument was given"
+    );
+}
+
+#[test]
+fn test_string_operations() {
+    let input = "func run() then
+        var s string
+        s = \"  Hello, \" + \"World  \"
+        println(\"[\" + trim(s) + \"]\")
+        println(upper(s) + lower(s))
+        println(substring(trim(s), 7, 12))
+        println(substring(\"héllo\", 1, 2))
+        println(contains(s, \"lo, W\"))
+        println(contains(s, \"xyz\"))
+        println(\"apple\" < \"banana\")
+        println(\"B\" < \"a\")
+        println(\"b\" >= \"b\")
+    end";
+    assert_eq!(
+        output_of(input),
+        "[Hello, World]\n  HELLO, WORLD    hello, world  \nWorld\né\ntrue\nfalse\ntrue\ntrue\ntrue\n"
+    );
+
+    let err = Interpreter::new().interpret(&parse("func run() then println(substring(\"abc\", 2, 5)) end")).unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0308");
+    assert_eq!(
+        err.diagnostic.message,
+        "Native function 'substring' failed: range 2..5 is out of bounds for a string of length 3"
+    );
+}
+
+#[test]
+fn test_string_methods() {
+    let input = "func run() then
+        var s string
+        s = \"  a,b \"
+        println(s.trim().upper())
+        println(s.len())
+        println(s.substring(2, 3) + s.lower())
+        println(s.contains(\",\"))
+        println(\"x-y\".split(\"-\"))
+        println(len(s.trim().split(\",\")))
+    end";
+    assert_eq!(output_of(input), "A,B\n6\na  a,b \ntrue\n[\"x\", \"y\"]\n2\n");
+
+    let errors = check("func run() then var s string s = \"a\" println(s.substring(1)) end");
+    assert_eq!(errors[0].code, "E0212");
+    assert_eq!(errors[0].message, "Function 'string.substring' expects 2 arguments, got 1");
+    let errors = check("func run() then var b bool b = \"a\".contains(1) end");
+    assert_eq!(errors[0].message, "Argument 1 of 'string.contains' must be string, got int");
+    let errors = check("func run() then println(\"a\".format()) end");
+    assert_eq!(errors[0].code, "E0221");
+    assert_eq!(errors[0].message, "Cannot access 'format' on a value of type string");
+}
+
+#[test]
+fn test_string_operators_are_type_checked() {
+    let codes = |input: &str| check(input).iter().map(|e| e.code).collect::<Vec<_>>();
+    assert_eq!(codes("func run() then var s string s = \"a\" + \"b\" end"), Vec::<&str>::new());
+    assert_eq!(codes("func run() then var s string s = \"a\" + 1 end"), vec!["E0202"]);
+    assert_eq!(codes("func run() then var s string s = \"a\" - \"b\" end"), vec!["E0211"]);
+    assert_eq!(codes("func run() then var b bool b = \"a\" <= \"b\" end"), Vec::<&str>::new());
+    assert_eq!(codes("func run() then var b bool b = true < false end"), vec!["E0211"]);
+}
@@ -0,0 +1,116 @@
+use neutron::bytecode::{Condition, Instruction, Program};
+use neutron::bytecode_file::{self, LoadError, VERSION};
+use neutron::compiler::compile;
+use neutron::disassembler::disassemble;
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::vm::Vm;
+
+fn compile_source(input: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(input));
+    compile(&parser.parse_program().unwrap()).unwrap()
+}
+
+const FIB: &str = "var calls int
+func fib(n int) int then
+    calls = calls + 1
+    if n < 2 then
+        ret n
+    end
+    ret fib(n - 1) + fib(n - 2)
+end
+func run() float then
+    calls = 0
+    var half float
+    half = 0.5
+    ret -half * 2.0
+end";
+
+#[test]
+fn test_round_trip_preserves_program() {
+    let program = compile_source(FIB);
+    let bytes = bytecode_file::write(&program);
+    assert!(bytecode_file::is_bytecode(&bytes));
+
+    let loaded = bytecode_file::read(&bytes).unwrap();
+    assert_eq!(loaded.constants, program.constants);
+    assert_eq!(loaded.globals, program.globals);
+    assert_eq!(loaded.entry, program.entry);
+    assert_eq!(loaded.functions.len(), program.functions.len());
+    for (loaded, original) in loaded.functions.iter().zip(&program.functions) {
+        assert_eq!(loaded.name, original.name);
+        assert_eq!((loaded.arity, loaded.locals), (original.arity, original.locals));
+        assert_eq!(loaded.code, original.code);
+        assert_eq!(loaded.spans, original.spans);
+    }
+    assert_eq!(Vm::new(&loaded).run(), Vm::new(&program).run());
+}
+
+#[test]
+fn test_rejects_bad_files() {
+    let bytes = bytecode_file::write(&compile_source(FIB));
+
+    assert_eq!(bytecode_file::read(b"func run() then end"), Err(LoadError::NotBytecode));
+
+    let mut newer = bytes.clone();
+    newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(bytecode_file::read(&newer), Err(LoadError::UnsupportedVersion(VERSION + 1)));
+
+    for len in [5, bytes.len() / 2, bytes.len() - 1] {
+        assert_eq!(bytecode_file::read(&bytes[..len]), Err(LoadError::Truncated), "length {}", len);
+    }
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(matches!(bytecode_file::read(&trailing), Err(LoadError::Malformed(_))));
+}
+
+#[test]
+fn test_rejects_out_of_range_operands() {
+    let mut program = compile_source(FIB);
+    let fib = &mut program.functions[1];
+    fib.code.insert(0, Instruction::GetLocal(fib.locals));
+    fib.spans.insert(0, fib.spans[0]);
+
+    match bytecode_file::read(&bytecode_file::write(&program)) {
+        Err(LoadError::Malformed(reason)) => assert!(reason.contains("out of range"), "{}", reason),
+        other => panic!("expected a malformed file, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_rejects_code_that_underflows_the_stack() {
+    let malformed = |code: Vec<Instruction>| {
+        let mut program = compile_source("func run() int then ret 1 end");
+        let run = &mut program.functions[1];
+        run.spans = vec![run.spans[0]; code.len()];
+        run.code = code;
+        match bytecode_file::read(&bytecode_file::write(&program)) {
+            Err(LoadError::Malformed(reason)) => reason,
+            other => panic!("expected a malformed file, got {:?}", other.map(|_| ())),
+        }
+    };
+    let reason = malformed(vec![Instruction::Pop, Instruction::Constant(0), Instruction::Return]);
+    assert!(reason.contains("pops more values than the stack holds"), "{}", reason);
+    let reason = malformed(vec![Instruction::Constant(0), Instruction::BuildArray(2), Instruction::Return]);
+    assert!(reason.contains("pops more values than the stack holds"), "{}", reason);
+    // One way to the return leaves a value for it, the other doesn't.
+    let reason = malformed(vec![
+        Instruction::Constant(0),
+        Instruction::Constant(0),
+        Instruction::JumpIfFalse(4, Condition::If),
+        Instruction::Constant(0),
+        Instruction::Return,
+    ]);
+    assert!(reason.contains("stack depth at 4"), "{}", reason);
+}
+
+#[test]
+fn test_disassembly() {
+    let program = compile_source("var g int\nfunc f(a int) int then\n    g = a\n    ret g + 1\nend");
+    let text = disassemble(&program);
+    assert!(text.contains("function 1 'f' (arity 1, locals 1):"), "{}", text);
+    assert!(text.contains("  0000     3  GET_LOCAL 0\n  0001     |  SET_GLOBAL 0  ; g\n"), "{}", text);
+    assert!(text.contains("ARITHMETIC +"), "{}", text);
+    assert!(text.contains("CONSTANT 1  ; Int(1)"), "{}", text);
+}
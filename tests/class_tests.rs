@@ -0,0 +1,575 @@
+use neutron::ast::Node;
+use neutron::builtins::{self, Output};
+use neutron::bytecode_file;
+use neutron::compiler::compile_with_natives;
+use neutron::diagnostic::Diagnostic;
+use neutron::interpreter::{Interpreter, Value};
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::semantic::analyze;
+use neutron::symbol_table::SymbolTable;
+use neutron::vm::Vm;
+
+fn parse(input: &str) -> Node {
+    let mut parser = Parser::new(Lexer::new(input));
+    parser.parse_program().unwrap()
+}
+
+fn check(input: &str) -> Vec<Diagnostic> {
+    let natives = builtins::natives(Output::stdout());
+    match analyze(&parse(input), &mut SymbolTable::with_natives(&natives)) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+/// Runs `input` on the interpreter, the VM and the VM after a round trip
+/// through a bytecode file, asserting they all agree. Returns the result and
+/// what was printed.
+fn run_all(input: &str) -> (Value, String) {
+    assert_eq!(check(input), Vec::new(), "checking {}", input);
+    let ast = parse(input);
+
+    let (output, interpreted) = Output::buffer();
+    let result = Interpreter::with_output(output).interpret(&ast).unwrap();
+
+    let (output, executed) = Output::buffer();
+    let natives = builtins::natives(output);
+    let program = compile_with_natives(&ast, &natives).unwrap();
+    assert_eq!(Vm::with_natives(&program, &natives).run(), Ok(result.clone()), "running {}", input);
+    let loaded = bytecode_file::read(&bytecode_file::write(&program)).unwrap();
+    assert_eq!(loaded.classes, program.classes);
+    assert_eq!(Vm::with_natives(&loaded, &natives).run(), Ok(result.clone()), "running {}", input);
+
+    let printed = interpreted.borrow().clone();
+    assert_eq!(executed.borrow().as_str(), printed.repeat(2), "running {}", input);
+    (result, printed)
+}
+
+const COUNTERS: &str = "
+var last Counter
+
+class Counter then
+    var count int
+
+    func init(start int) then
+        count = start
+        bump(2)
+        last = self
+        println(\"counter at {count}\")
+    end
+
+    func bump(by int) then
+        var count int
+        count = by
+        add(count)
+    end
+
+    func add(amount int) then
+        count = count + amount
+    end
+end
+
+class Doubler then
+    var count int
+
+    func init() then
+        count = compute()
+        println(\"doubler at {count}\")
+    end
+
+    func compute() int then
+        ret 21 * 2
+    end
+end
+
+func compute() int then
+    ret 0
+end
+
+func run() bool then
+    var a Counter
+    var b Counter
+    a = Counter.new(1)
+    b = Counter.new(5)
+    Doubler.new()
+    println(a)
+    ret a == a and a != b and last == b and compute() == 0
+end
+";
+
+#[test]
+fn test_instances_have_their_own_fields() {
+    let (result, printed) = run_all(COUNTERS);
+    assert_eq!(result, Value::Bool(true));
+    assert_eq!(printed, "counter at 3\ncounter at 7\ndoubler at 42\n<Counter object>\n");
+}
+
+#[test]
+fn test_methods_are_compiled_per_class() {
+    let program = compile_with_natives(&parse(COUNTERS), &builtins::natives(Output::stdout())).unwrap();
+    let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["<script>", "Counter.init", "Counter.bump", "Counter.add", "Doubler.init", "Doubler.compute", "compute", "run"]
+    );
+    // Methods take the instance as an extra first argument.
+    assert_eq!(program.functions[1].arity, 2);
+    assert_eq!(program.classes[0].fields, vec!["count".to_string()]);
+}
+
+#[test]
+fn test_classes_are_type_checked() {
+    let codes = |input: &str| check(input).iter().map(|e| e.code).collect::<Vec<_>>();
+
+    let errors = check("class P then func init(x int) then end end func run() then var p P p = P.new() end");
+    assert_eq!(errors[0].code, "E0212");
+    assert_eq!(errors[0].message, "Function 'P.new' expects 1 argument, got 0");
+    assert_eq!(errors[0].labels[0].message, "'init' declared here");
+
+    let errors = check("class P then end func run() then var p P p = P.new(1) end");
+    assert_eq!(errors[0].notes, vec!["'P' has no 'init' method".to_string()]);
+
+    assert_eq!(codes("class P then func init(x int) then end end func run() then P.new(true) end"), vec!["E0213"]);
+    assert_eq!(codes("class P then end class Q then end func run() then var p P p = Q.new() end"), vec!["E0201"]);
+    assert_eq!(codes("func run() then var p P p = P.new() end"), vec!["E0217", "E0217"]);
+    assert_eq!(codes("func f(p Missing) Other then end"), vec!["E0217", "E0217", "E0232"]);
+    assert_eq!(codes("func run() then println(self) end"), vec!["E0218"]);
+    assert_eq!(codes("class P then var x int x = 1 end"), vec!["E0219"]);
+
+    // Fields and methods are only visible inside their class.
+    let errors = check("class P then var x int func get() int then ret x end end func run() int then ret x + get() end");
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages, vec!["Undefined variable: x", "Unknown function 'get'"]);
+}
+
+#[test]
+fn test_member_access_and_method_calls() {
+    let input = "
+    class Vec2 then
+        var x float
+        var y float
+
+        func init(x float, y float) then
+            self.x = x
+            self.y = y
+        end
+
+        func plus(other Vec2) Vec2 then
+            ret Vec2.new(x + other.x, y + other.y)
+        end
+
+        func length_squared() float then
+            ret self.x * x + y * self.y
+        end
+    end
+
+    class Body then
+        var position Vec2
+        var name string
+
+        func init(name string) then
+            self.name = name
+            position = Vec2.new(0.0, 0.0)
+        end
+    end
+
+    func run() float then
+        var body Body
+        body = Body.new(\"ship\")
+        body.position.x = 3.0
+        body.position = body.position.plus(Vec2.new(0.0, 4.0))
+        println(\"{body.name} at {body.position.x}, {body.position.y}\")
+        ret body.position.length_squared()
+    end
+    ";
+    let (result, printed) = run_all(input);
+    assert_eq!(result, Value::Float(25.0));
+    assert_eq!(printed, "ship at 3.0, 4.0\n");
+}
+
+#[test]
+fn test_members_are_type_checked() {
+    let class = "class P then
+        var hp int
+        func hit(damage int) int then ret hp - damage end
+    end
+    ";
+    let check_body = |body: &str| check(&format!("{}func run() then var p P var n int {} end", class, body));
+
+    let errors = check_body("n = p.mana");
+    assert_eq!(errors[0].code, "E0220");
+    assert_eq!(errors[0].message, "Class 'P' has no field 'mana'");
+
+    let errors = check_body("p.heal(1)");
+    assert_eq!(errors[0].message, "Class 'P' has no method 'heal'");
+
+    let errors = check_body("n.hp = 1");
+    assert_eq!(errors[0].code, "E0221");
+    assert_eq!(errors[0].message, "Cannot access 'hp' on a value of type int");
+
+    let errors = check_body("p.hp = true");
+    assert_eq!(errors[0].code, "E0201");
+    assert_eq!(errors[0].message, "Type mismatch in assignment to 'P.hp': expected int, got bool");
+    assert_eq!(errors[0].labels[0].message, "field declared here as int");
+
+    let errors = check_body("n = p.hit()");
+    assert_eq!(errors[0].code, "E0212");
+    assert_eq!(errors[0].message, "Function 'P.hit' expects 1 argument, got 0");
+    assert_eq!(errors[0].labels[0].message, "method declared here");
+
+    assert_eq!(check_body("var b bool b = p.hit(1)")[0].code, "E0201");
+    assert_eq!(check_body("n = p.hp + p.hit(p.hp)"), Vec::new());
+    assert_eq!(check("class Q then func new() then end end")[0].code, "E0222");
+}
+
+#[test]
+fn test_members_of_void_are_runtime_errors() {
+    let input = "class P then var hp int func heal() then end end
+    func run() int then
+        var p P
+        ret p.hp
+    end";
+    let ast = parse(input);
+    let interpreted = Interpreter::new().interpret(&ast).unwrap_err();
+    let executed = Vm::new(&compile_with_natives(&ast, &builtins::natives(Output::stdout())).unwrap()).run().unwrap_err();
+    assert_eq!(interpreted, executed);
+    assert_eq!(interpreted.diagnostic.code, "E0309");
+    assert_eq!(interpreted.diagnostic.message, "Cannot access 'hp' on Void");
+
+    let err = Interpreter::new().interpret(&parse(&input.replace("ret p.hp", "p.heal()"))).unwrap_err();
+    assert_eq!(err.diagnostic.message, "Cannot access 'heal' on Void");
+}
+
+#[test]
+fn test_unknown_classes_in_unchecked_programs() {
+    let ast = parse("func run() then var p Missing p = Missing.new() end");
+    let err = Interpreter::new().interpret(&ast).unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0312");
+    assert_eq!(err.diagnostic.message, "Unknown class 'Missing'");
+
+    let natives = builtins::natives(Output::stdout());
+    let err = compile_with_natives(&ast, &natives).unwrap_err();
+    assert_eq!(err.code, "E0401");
+    assert_eq!(err.message, "Unknown class 'Missing'");
+    let err = compile_with_natives(&parse("class B extends Missing then end"), &natives).unwrap_err();
+    assert_eq!(err.code, "E0401");
+}
+
+const ACTORS: &str = "
+class Actor then
+    var name string
+    var hp int
+
+    func init(name string, hp int) then
+        self.name = name
+        self.hp = hp
+    end
+
+    func describe() string then
+        ret \"{name} ({hp} hp)\"
+    end
+
+    func damage() int then
+        ret 1
+    end
+
+    func attack(target Actor) then
+        target.hp = target.hp - damage()
+        println(\"{name} hits {target.describe()}\")
+    end
+end
+
+class Goblin extends Enemy then
+    var rage int
+
+    func init(rage int) then
+        super.init(\"goblin\", 5)
+        self.rage = rage
+        loot = 1
+    end
+
+    func damage() int then
+        ret super.damage() * rage
+    end
+
+    func describe() string then
+        ret \"angry \" + super.describe()
+    end
+end
+
+class Enemy extends Actor then
+    var loot int
+
+    func damage() int then
+        ret 2
+    end
+end
+
+func strongest(a Actor, b Actor) Actor then
+    if a.hp > b.hp then
+        ret a
+    end
+    ret b
+end
+
+func run() int then
+    var hero Actor
+    var goblin Goblin
+    var enemy Enemy
+    var someone Actor
+    hero = Actor.new(\"hero\", 20)
+    goblin = Goblin.new(3)
+    enemy = Enemy.new(\"orc\", 8)
+    goblin.attack(hero)
+    hero.attack(goblin)
+    enemy.attack(hero)
+    someone = goblin
+    println(strongest(someone, enemy).describe())
+    println(someone == goblin)
+    ret hero.hp + goblin.loot
+end
+";
+
+#[test]
+fn test_subclasses_inherit_and_override() {
+    let (result, printed) = run_all(ACTORS);
+    assert_eq!(result, Value::Int(13));
+    assert_eq!(
+        printed,
+        "goblin hits hero (14 hp)\nhero hits angry goblin (4 hp)\norc hits hero (12 hp)\norc (8 hp)\ntrue\n"
+    );
+}
+
+#[test]
+fn test_subclasses_are_compiled_with_inherited_members() {
+    let program = compile_with_natives(&parse(ACTORS), &builtins::natives(Output::stdout())).unwrap();
+    let goblin = program.classes.iter().find(|class| class.name == "Goblin").unwrap();
+    assert_eq!(goblin.fields, vec!["name", "hp", "loot", "rage"]);
+    let methods: Vec<String> = goblin
+        .methods
+        .iter()
+        .map(|(method, function)| format!("{} -> {}", method, program.functions[*function as usize].name))
+        .collect();
+    assert_eq!(
+        methods,
+        vec![
+            "init -> Goblin.init",
+            "damage -> Goblin.damage",
+            "describe -> Goblin.describe",
+            "attack -> Actor.attack",
+        ]
+    );
+}
+
+#[test]
+fn test_base_methods_resolve_names_in_their_own_class() {
+    // `Sub` declaring its own `hp` and `helper` doesn't change what the
+    // inherited `total` refers to: the global variable and function.
+    let input = "
+    var hp int
+    func helper() int then ret 3 end
+
+    class Base then
+        func total() int then ret hp + helper() end
+    end
+
+    class Sub extends Base then
+        var hp int
+        func init() then hp = 100 end
+        func helper() int then ret 50 end
+    end
+
+    func run() int then
+        hp = 5
+        var sub Sub
+        sub = Sub.new()
+        ret sub.total()
+    end";
+    assert_eq!(run_all(input).0, Value::Int(8));
+}
+
+#[test]
+fn test_inheritance_is_type_checked() {
+    let codes = |input: &str| check(input).iter().map(|e| e.code).collect::<Vec<_>>();
+    let messages = |input: &str| check(input).into_iter().map(|e| e.message).collect::<Vec<_>>();
+    let base = "class A then var x int func init(x int) then end func get() int then ret x end end\n";
+
+    assert_eq!(codes("class B extends Missing then end"), vec!["E0217"]);
+    assert_eq!(messages("class A extends A then end"), vec!["Class 'A' can't extend itself"]);
+    assert_eq!(
+        messages("class A extends B then end class B extends A then end"),
+        vec!["Class 'B' can't extend 'A', which already inherits from it"]
+    );
+
+    let errors = check(&format!("{}class B extends A then func get() bool then ret true end end", base));
+    assert_eq!(errors[0].code, "E0224");
+    assert_eq!(errors[0].message, "Method 'B.get' doesn't match the signature of the method it overrides");
+    assert_eq!(errors[0].labels[0].message, "overridden method declared here");
+
+    let errors = check(&format!("{}class B extends A then var x int end class C extends B then var get int end", base));
+    let fields: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        fields,
+        vec!["Field 'x' is already declared in a base class of 'B'", "Field 'get' is already declared in a base class of 'C'"]
+    );
+
+    // `init` isn't inherited as a signature to keep, but is used when the subclass has none.
+    let with_base = |body: &str| codes(&format!("{}{}", base, body));
+    assert_eq!(with_base("class B extends A then func init() then super.init(1) end end"), Vec::<&str>::new());
+    assert_eq!(
+        messages(&format!("{}class B extends A then end func run() then B.new() end", base)),
+        vec!["Function 'B.new' expects 1 argument, got 0"]
+    );
+
+    assert_eq!(with_base("class B extends A then func get() int then ret super.get() + x end end"), Vec::<&str>::new());
+    assert_eq!(with_base("class B extends A then func f() then super.nope() end end"), vec!["E0220"]);
+    assert_eq!(with_base("class B extends A then func f() then super.get(1) end end"), vec!["E0212"]);
+    assert_eq!(codes("class B then func f() then super.f() end end"), vec!["E0226"]);
+    assert_eq!(codes("func f() then super.f() end"), vec!["E0218"]);
+
+    // A subclass instance can stand in for its base class, but not the other way around.
+    let run = |body: &str| with_base(&format!("class B extends A then end func run() then var a A var b B {} end", body));
+    assert_eq!(run("a = b a.x = b.get() b = B.new(1) a = B.new(2)"), Vec::<&str>::new());
+    assert_eq!(run("b = a"), vec!["E0201"]);
+    assert_eq!(run("var same bool same = a == b or b != a"), Vec::<&str>::new());
+    assert_eq!(
+        with_base("class B extends A then end func make() A then ret B.new(1) end func take(b B) then end func run() then take(make()) end"),
+        vec!["E0213"]
+    );
+}
+
+#[test]
+fn test_interfaces_accept_any_implementing_class() {
+    let input = "
+    interface Updatable then
+        func update(dt float)
+        func name() string
+    end
+
+    interface Scored then
+        func score() int
+    end
+
+    class Timer implements Updatable then
+        var elapsed float
+
+        func init() then
+            elapsed = 0.0
+        end
+
+        func update(dt float) then
+            elapsed = elapsed + dt
+        end
+
+        func name() string then
+            ret \"timer at {elapsed}\"
+        end
+    end
+
+    class Player extends Mover implements Scored then
+        var points int
+
+        func init() then
+            points = 0
+            x = 0.0
+        end
+
+        func update(dt float) then
+            super.update(dt)
+            points = points + 1
+        end
+
+        func score() int then
+            ret points
+        end
+    end
+
+    class Mover implements Updatable then
+        var x float
+
+        func update(dt float) then
+            x = x + dt * 2.0
+        end
+
+        func name() string then
+            ret \"mover at {x}\"
+        end
+    end
+
+    func tick(thing Updatable, dt float) then
+        thing.update(dt)
+        println(thing.name())
+    end
+
+    func run() int then
+        var player Player
+        var current Updatable
+        var scored Scored
+        player = Player.new()
+        tick(Timer.new(), 0.5)
+        tick(player, 0.5)
+        current = player
+        tick(current, 1.0)
+        scored = player
+        ret scored.score()
+    end
+    ";
+    let (result, printed) = run_all(input);
+    assert_eq!(result, Value::Int(2));
+    assert_eq!(printed, "timer at 0.5\nmover at 1.0\nmover at 3.0\n");
+}
+
+#[test]
+fn test_interfaces_are_type_checked() {
+    let codes = |input: &str| check(input).iter().map(|e| e.code).collect::<Vec<_>>();
+    let interface = "interface Shape then
+        func area() float
+        func scale(by float)
+    end
+    ";
+    let with_shape = |body: &str| check(&format!("{}{}", interface, body));
+
+    let errors = with_shape("class Square implements Shape then func area() float then ret 1.0 end end");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, "E0227");
+    assert_eq!(errors[0].message, "Class 'Square' doesn't implement method 'scale' of interface 'Shape'");
+    assert_eq!(errors[0].labels[0].message, "required by 'Shape' here");
+
+    let errors = with_shape("class Square implements Shape then func area() int then ret 1 end func scale(by float) then end end");
+    assert_eq!(errors[0].code, "E0228");
+    assert_eq!(errors[0].message, "Method 'Square.area' doesn't match its signature in interface 'Shape'");
+
+    // Methods can come from a base class.
+    let square = "class Square then func area() float then ret 1.0 end func scale(by float) then end end\n";
+    assert_eq!(with_shape(&format!("{}class Tile extends Square implements Shape then end", square)), Vec::new());
+
+    let errors = with_shape("class Square implements Shape, Missing, Shape then end");
+    let found: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(found[0], "Unknown interface 'Missing'");
+    assert_eq!(errors.len(), 5);
+
+    let errors = with_shape("func run() then var s Shape s = Shape.new() end");
+    assert_eq!(errors[0].message, "Unknown class 'Shape'");
+    assert_eq!(errors[0].notes, vec!["'Shape' is an interface, only classes have instances".to_string()]);
+
+    let errors = with_shape("func f(s Shape) float then s.scale(2.0) ret s.area() + s.size end");
+    assert_eq!(errors[0].message, "Interface 'Shape' has no field 'size'");
+
+    let run = |body: &str| {
+        let program = format!(
+            "{}{}class Circle implements Shape then func area() float then ret 3.0 end func scale(by float) then end end
+            func run() then var shape Shape var square Square var circle Circle {} end",
+            interface, square, body
+        );
+        codes(&program)
+    };
+    assert_eq!(run("shape = circle circle = Circle.new() shape = Circle.new()"), Vec::<&str>::new());
+    assert_eq!(run("circle = shape"), vec!["E0201"]);
+    // Having the methods isn't enough, a class has to declare that it implements the interface.
+    assert_eq!(run("shape = square"), vec!["E0201"]);
+    assert_eq!(run("var same bool same = shape == circle"), Vec::<&str>::new());
+
+    assert_eq!(codes("interface I then func f(x Missing) end"), vec!["E0217"]);
+    assert_eq!(codes("interface I then end class I then end"), vec!["E0208"]);
+}
@@ -0,0 +1,50 @@
+use neutron::diagnostic::Diagnostic;
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::semantic::analyze;
+use neutron::span::Span;
+use neutron::symbol_table::SymbolTable;
+
+#[test]
+fn test_render_assignment_mismatch_with_declaration_label() {
+    let source = "func run() then\n    var total int\n\n    total = true\nend";
+    let mut parser = Parser::new(Lexer::new(source));
+    let ast = parser.parse_program().unwrap();
+    let errors = analyze(&ast, &mut SymbolTable::new()).unwrap_err();
+
+    let expected = "\
+error[E0201]: Type mismatch in assignment to 'total': expected int, got bool
+ --> demo.neutron:4:13
+  |
+2 |     var total int
+  |     ------------- variable declared here as int
+...
+4 |     total = true
+  |             ^^^^
+";
+    assert_eq!(errors[0].render("demo.neutron", source, false), expected);
+}
+
+#[test]
+fn test_render_notes_and_multiline_span() {
+    let source = "x = 1\ny = 2";
+    let diagnostic = Diagnostic::error("E0100", "Something is off", Span::new(4, 9, 1, 5))
+        .with_note("spans are underlined up to the end of their first line");
+
+    let expected = "\
+error[E0100]: Something is off
+ --> test.neutron:1:5
+  |
+1 | x = 1
+  |     ^
+  = note: spans are underlined up to the end of their first line
+";
+    assert_eq!(diagnostic.render("test.neutron", source, false), expected);
+}
+
+#[test]
+fn test_render_with_color() {
+    let diagnostic = Diagnostic::error("E0001", "Bad", Span::new(0, 1, 1, 1));
+    let rendered = diagnostic.render("a.neutron", "@", true);
+    assert!(rendered.starts_with("\x1b[1;31merror[E0001]\x1b[0m"));
+}
@@ -0,0 +1,264 @@
+use neutron::interpreter::{Interpreter, RuntimeError, StackFrame, Value};
+use neutron::span::Span;
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::runtime_error::MAX_INTERPRETER_CALL_DEPTH;
+
+fn run(input: &str) -> Value {
+    let mut parser = Parser::new(Lexer::new(input));
+    let ast = parser.parse_program().unwrap();
+    Interpreter::new().interpret(&ast).unwrap()
+}
+
+fn run_err(input: &str) -> RuntimeError {
+    let mut parser = Parser::new(Lexer::new(input));
+    let ast = parser.parse_program().unwrap();
+    Interpreter::new().interpret(&ast).unwrap_err()
+}
+
+#[test]
+fn test_run_returns_value() {
+    let input = "func run() then var x int x = (1 + 2) * 3 ret x end";
+    match run(input) {
+        Value::Int(value) => assert_eq!(value, 9),
+        other => panic!("Expected an Int, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unknown_function_is_an_error() {
+    let input = "func run() then ret missing(1) end";
+    let err = run_err(input);
+    assert_eq!(err.diagnostic.code, "E0302");
+    assert_eq!(err.diagnostic.message, "Function 'missing' not found");
+}
+
+#[test]
+fn test_wrong_argument_count_is_an_error() {
+    let input = "func add(a int, b int) int then ret a + b end func run() then ret add(1) end";
+    let err = run_err(input);
+    assert_eq!(err.diagnostic.code, "E0303");
+}
+
+#[test]
+fn test_comparison_and_modulo_operators() {
+    let input = "
+        func run() then
+            var count int
+            count = 0
+            for i = 0 10
+                if i % 3 == 0 and i != 6 or i >= 9 then
+                    count = count + 1
+                end
+            end
+            ret count
+        end
+    ";
+    match run(input) {
+        Value::Int(value) => assert_eq!(value, 3),
+        other => panic!("Expected an Int, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_logical_operators_short_circuit() {
+    let input = "
+        func run() then
+            var ok bool
+            ok = false and missing() == 1
+            ok = true or missing() == 1
+            ret not ok
+        end
+    ";
+    match run(input) {
+        Value::Bool(value) => assert!(!value),
+        other => panic!("Expected a Bool, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unary_minus_and_subtraction() {
+    let input = "
+        func run() then
+            var x int
+            var y int
+            y = 5
+            x = y-1 - -3 * -(2)
+            ret x
+        end
+    ";
+    match run(input) {
+        Value::Int(value) => assert_eq!(value, -2),
+        other => panic!("Expected an Int, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_recursive_calls_keep_their_own_locals() {
+    let input = "
+        func fib(n int) int then
+            var a int
+            var b int
+            if n < 2 then
+                ret n
+            end
+            a = fib(n - 1)
+            b = fib(n - 2)
+            ret a + b
+        end
+
+        func run() int then
+            ret fib(15)
+        end
+    ";
+    match run(input) {
+        Value::Int(value) => assert_eq!(value, 610),
+        other => panic!("Expected an Int, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_callee_cannot_see_caller_locals() {
+    let input = "
+        func peek() int then
+            ret secret
+        end
+
+        func run() int then
+            var secret int
+            secret = 42
+            ret peek()
+        end
+    ";
+    let err = run_err(input);
+    assert_eq!(err.diagnostic.code, "E0300");
+    assert_eq!(err.diagnostic.message, "Undefined variable 'secret'");
+}
+
+#[test]
+fn test_globals_are_shared_between_calls() {
+    let input = "
+        var total int
+
+        func add(n int) then
+            total = total + n
+        end
+
+        func run() int then
+            total = 0
+            for i = 0 5
+                add(i)
+            end
+            ret total
+        end
+    ";
+    match run(input) {
+        Value::Int(value) => assert_eq!(value, 10),
+        other => panic!("Expected an Int, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_runtime_errors_carry_a_stack_trace() {
+    let input = "func inner() int then
+    ret missing
+end
+func outer() int then
+    ret inner()
+end
+func run() int then
+    ret outer()
+end";
+    let err = run_err(input);
+    assert_eq!(err.diagnostic.code, "E0300");
+    assert_eq!(
+        err.stack_trace,
+        vec![
+            StackFrame { function: "inner".to_string(), call_site: Some(Span::new(72, 79, 5, 9)) },
+            StackFrame { function: "outer".to_string(), call_site: Some(Span::new(112, 119, 8, 9)) },
+            StackFrame { function: "run".to_string(), call_site: None },
+        ]
+    );
+    assert!(err.render("main.neutron", input, false).ends_with(
+        "stack trace (most recent call first):\n    in 'inner', called at 5:9\n    in 'outer', called at 8:9\n    in 'run'\n"
+    ));
+}
+
+/// Wraps `body` in a `run` function with an int `x` and a float `f` declared.
+fn in_run(body: &str) -> String {
+    format!("func run() int then\n var x int\n var f float\n{}\nend", body)
+}
+
+#[test]
+fn test_integer_division_by_zero_is_a_runtime_error() {
+    for body in ["x = 0 ret 10 / x", "x = 0 ret 10 % x"] {
+        let err = run_err(&in_run(body));
+        assert_eq!(err.diagnostic.code, "E0305");
+        assert_eq!(err.diagnostic.message, "Division by zero");
+        assert_eq!(err.diagnostic.labels[0].message, "this is zero");
+        assert_eq!(err.stack_trace[0].function, "run");
+    }
+}
+
+#[test]
+fn test_integer_overflow_is_a_runtime_error() {
+    let bodies = [
+        "x = 9223372036854775807 ret x + 1",
+        "x = -9223372036854775807 ret x - 2",
+        "x = 4611686018427387904 ret x * 2",
+        "x = -9223372036854775807 - 1 ret x / -1",
+        "x = -9223372036854775807 - 1 ret x % -1",
+        "x = -9223372036854775807 - 1 ret -x",
+    ];
+    for body in bodies {
+        let err = run_err(&in_run(body));
+        assert_eq!(err.diagnostic.code, "E0306", "evaluating {}", body);
+    }
+}
+
+#[test]
+fn test_float_arithmetic_follows_ieee_754() {
+    let float = |body: &str| match run(&in_run(body)) {
+        Value::Float(value) => value,
+        other => panic!("Expected a Float, got {:?}", other),
+    };
+    assert_eq!(float("f = 0.0 f = 1.0 / f ret f"), f64::INFINITY);
+    assert_eq!(float("f = 0.0 f = -1.0 / f ret f"), f64::NEG_INFINITY);
+    assert!(float("f = 0.0 f = f / f ret f").is_nan());
+    assert!(float("f = 0.0 f = 5.5 % f ret f").is_nan());
+}
+
+#[test]
+fn test_string_interpolation() {
+    let input = "func run() string then
+        var hp int
+        var speed float
+        hp = 7
+        speed = 2.0
+        ret \"HP: {hp}/{hp * 2}, speed {speed}, alive {hp > 0}, {\"nested {hp}\"}\"
+    end";
+    assert_eq!(run(input), Value::String("HP: 7/14, speed 2.0, alive true, nested 7".to_string()));
+}
+
+#[test]
+fn test_unbounded_recursion_is_a_stack_overflow() {
+    let input = "func f(n int) int then
+    if n < 0 then
+        ret 0
+    end
+    ret f(n + 1) + 1
+end
+func run() int then ret f(0) end";
+    // Runs on the test thread's own stack, which the limit has to fit in.
+    let err = run_err(input);
+    assert_eq!(err.diagnostic.code, "E0311");
+    assert_eq!(err.diagnostic.message, "Stack overflow: more than 200 nested calls");
+    assert_eq!(err.stack_trace.len(), MAX_INTERPRETER_CALL_DEPTH);
+    assert_eq!(err.stack_trace[0].function, "f");
+    assert_eq!(err.stack_trace.last().unwrap().function, "run");
+    // The recursive calls are collapsed into one line.
+    assert!(err.render("main.neutron", input, false).ends_with(
+        "stack trace (most recent call first):\n    in 'f', called at 5:9\n    \
+         ... previous frame repeated 197 times\n    in 'f', called at 7:25\n    in 'run'\n"
+    ));
+}
@@ -1,12 +1,13 @@
 #[cfg(test)]
 mod tests {
     use neutron::lexer::{Lexer, Token};
+    use neutron::span::Span;
 
     fn lex(input: &str) -> Vec<Token> {
         let mut lexer = Lexer::new(input);
         let mut tokens = Vec::new();
         loop {
-            let token = lexer.get_next_token();
+            let token = lexer.get_next_token().unwrap().token;
             if token == Token::EOF {
                 break;
             }
@@ -38,9 +39,9 @@ mod tests {
 
     #[test]
     fn test_float_token() {
-        let input = "3.14";
+        let input = "3.25";
         let tokens = lex(input);
-        assert_eq!(tokens, vec![Token::Float(3.14)]);
+        assert_eq!(tokens, vec![Token::Float(3.25)]);
     }
 
     #[test]
@@ -78,14 +79,14 @@ mod tests {
     fn test_negative_integer() {
         let input = "-42";
         let tokens = lex(input);
-        assert_eq!(tokens, vec![Token::Number(-42)]);
+        assert_eq!(tokens, vec![Token::Minus, Token::Number(42)]);
     }
 
     #[test]
     fn test_negative_float() {
-        let input = "-3.14";
+        let input = "-3.25";
         let tokens = lex(input);
-        assert_eq!(tokens, vec![Token::Float(-3.14)]);
+        assert_eq!(tokens, vec![Token::Minus, Token::Float(3.25)]);
     }
 
     #[test]
@@ -98,7 +99,8 @@ mod tests {
                 Token::Number(10),
                 Token::Minus,
                 Token::Number(5),
-                Token::Number(-3)
+                Token::Minus,
+                Token::Number(3)
             ]
         );
     }
@@ -109,7 +111,13 @@ mod tests {
         let tokens = lex(input);
         assert_eq!(
             tokens,
-            vec![Token::Number(-5), Token::Minus, Token::Number(-3)]
+            vec![
+                Token::Minus,
+                Token::Number(5),
+                Token::Minus,
+                Token::Minus,
+                Token::Number(3)
+            ]
         );
     }
 
@@ -119,4 +127,236 @@ mod tests {
         let tokens = lex(input);
         assert_eq!(tokens, vec![Token::If, Token::Else]);
     }
+
+    #[test]
+    fn test_token_spans() {
+        let mut lexer = Lexer::new("var x int\n  x = 10");
+        let spans: Vec<Span> = (0..6).map(|_| lexer.get_next_token().unwrap().span).collect();
+        assert_eq!(spans[0], Span::new(0, 3, 1, 1));
+        assert_eq!(spans[1], Span::new(4, 5, 1, 5));
+        assert_eq!(spans[2], Span::new(6, 9, 1, 7));
+        assert_eq!(spans[3], Span::new(12, 13, 2, 3));
+        assert_eq!(spans[4], Span::new(14, 15, 2, 5));
+        assert_eq!(spans[5], Span::new(16, 18, 2, 7));
+    }
+
+    #[test]
+    fn test_span_skips_comments() {
+        let mut lexer = Lexer::new("# comment\nret");
+        let token = lexer.get_next_token().unwrap();
+        assert_eq!(token.token, Token::Ret);
+        assert_eq!(token.span, Span::new(10, 13, 2, 1));
+    }
+
+    #[test]
+    fn test_unrecognized_character() {
+        let mut lexer = Lexer::new("x @ y");
+        lexer.get_next_token().unwrap();
+        let err = lexer.get_next_token().unwrap_err();
+        assert_eq!(err.code, "E0001");
+        assert_eq!(err.span, Span::new(2, 3, 1, 3));
+        assert_eq!(
+            lexer.get_next_token().unwrap().token,
+            Token::Identifier("y".to_string())
+        );
+    }
+
+    #[test]
+    fn test_integer_literal_out_of_range() {
+        let mut lexer = Lexer::new("99999999999999999999");
+        let err = lexer.get_next_token().unwrap_err();
+        assert_eq!(err.code, "E0002");
+    }
+
+    #[test]
+    fn test_comparison_and_logical_operators() {
+        let input = "a <= b >= c != d % e and f or not g < h";
+        let tokens = lex(input);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("a".to_string()),
+                Token::LessEqual,
+                Token::Identifier("b".to_string()),
+                Token::GreaterEqual,
+                Token::Identifier("c".to_string()),
+                Token::NotEqual,
+                Token::Identifier("d".to_string()),
+                Token::Modulo,
+                Token::Identifier("e".to_string()),
+                Token::And,
+                Token::Identifier("f".to_string()),
+                Token::Or,
+                Token::Not,
+                Token::Identifier("g".to_string()),
+                Token::LessThan,
+                Token::Identifier("h".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_minus_without_spaces_is_subtraction() {
+        let input = "x = y-1";
+        let tokens = lex(input);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("x".to_string()),
+                Token::Assign,
+                Token::Identifier("y".to_string()),
+                Token::Minus,
+                Token::Number(1)
+            ]
+        );
+    }
+
+    fn string(input: &str) -> String {
+        match Lexer::new(input).get_next_token().unwrap().token {
+            Token::StringLiteral(s) => s,
+            other => panic!("Expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(string(r#""a\nb\tc\\d\"e\'f\0""#), "a\nb\tc\\d\"e'f\0");
+        assert_eq!(string(r#""\u{48}\u{e9}\u{1F600}""#), "Hé😀");
+    }
+
+    #[test]
+    fn test_raw_and_multi_line_strings() {
+        assert_eq!(string(r#"r"C:\new\table""#), r"C:\new\table");
+        assert_eq!(string("\"\"\"\nline \"one\"\n\tline\\ttwo\n\"\"\""), "line \"one\"\n\tline\ttwo\n");
+        assert_eq!(string("r\"\"\"a\\n\"b\"\"\"\""), "a\\n\"b");
+        assert_eq!(string("\"\""), "");
+
+        let tokens = lex("x = \"\"\"a\nb\"\"\" y");
+        assert_eq!(tokens[2], Token::StringLiteral("a\nb".to_string()));
+        assert_eq!(tokens[3], Token::Identifier("y".to_string()));
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let mut lexer = Lexer::new("x = \"abc\ny = 1");
+        lexer.get_next_token().unwrap();
+        lexer.get_next_token().unwrap();
+        let err = lexer.get_next_token().unwrap_err();
+        assert_eq!(err.code, "E0003");
+        assert_eq!(err.message, "Unterminated string literal");
+        assert_eq!(err.span, Span::new(4, 5, 1, 5));
+        assert_eq!(lexer.get_next_token().unwrap().token, Token::Identifier("y".to_string()));
+
+        let err = Lexer::new("\n  r\"\"\"never closed\n").get_next_token().unwrap_err();
+        assert_eq!(err.code, "E0003");
+        assert_eq!(err.span, Span::new(3, 7, 2, 3));
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        // The string is still a token, with the invalid escape replaced.
+        let mut lexer = Lexer::new(r#""a\qb" x"#);
+        assert_eq!(lexer.get_next_token().unwrap().token, Token::StringLiteral("a\u{FFFD}b".to_string()));
+        let errors = lexer.take_diagnostics();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "E0004");
+        assert_eq!(errors[0].message, "Unknown escape sequence '\\q'");
+        assert_eq!(errors[0].span, Span::new(2, 4, 1, 3));
+        assert_eq!(lexer.get_next_token().unwrap().token, Token::Identifier("x".to_string()));
+        assert!(lexer.take_diagnostics().is_empty());
+
+        for input in [r#""\u{110000}""#, r#""\u{}""#, r#""\u41""#, r#""\u{1234567}""#] {
+            let mut lexer = Lexer::new(input);
+            lexer.get_next_token().unwrap();
+            assert_eq!(lexer.take_diagnostics()[0].message, "Invalid unicode escape", "lexing {}", input);
+        }
+    }
+
+    #[test]
+    fn test_interpolated_string_parts() {
+        use neutron::lexer::StringPart;
+
+        let tokens = lex("x = \"HP: {hp}/{max(a, \"}\")} {}\\{\"");
+        assert_eq!(
+            tokens[2],
+            Token::InterpolatedString(vec![
+                StringPart::Text("HP: ".to_string()),
+                StringPart::Code("hp".to_string(), Span::new(10, 10, 1, 11)),
+                StringPart::Text("/".to_string()),
+                StringPart::Code("max(a, \"}\")".to_string(), Span::new(15, 15, 1, 16)),
+                StringPart::Text(" {}{".to_string()),
+            ])
+        );
+        assert_eq!(string(r#"r"{raw}""#), "{raw}");
+        // Doubled braces are literal braces, except in raw strings.
+        assert_eq!(string(r#""{{}} {{x}}""#), "{} {x}");
+        assert_eq!(string(r#"r"{{x}}""#), "{{x}}");
+    }
+
+    #[test]
+    fn test_unclosed_interpolation() {
+        let mut lexer = Lexer::new("\"a {b\nc");
+        let err = lexer.get_next_token().unwrap_err();
+        assert_eq!(err.code, "E0005");
+        assert_eq!(err.span, Span::new(3, 4, 1, 4));
+    }
+
+    #[test]
+    fn test_dot_and_self() {
+        assert_eq!(
+            lex("Point.new(self, 1.5)"),
+            vec![
+                Token::Identifier("Point".to_string()),
+                Token::Dot,
+                Token::Identifier("new".to_string()),
+                Token::LParen,
+                Token::SelfRef,
+                Token::Comma,
+                Token::Float(1.5),
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inheritance_keywords() {
+        assert_eq!(
+            lex("class Goblin extends Enemy super.hit()"),
+            vec![
+                Token::Class,
+                Token::Identifier("Goblin".to_string()),
+                Token::Extends,
+                Token::Identifier("Enemy".to_string()),
+                Token::Super,
+                Token::Dot,
+                Token::Identifier("hit".to_string()),
+                Token::LParen,
+                Token::RParen,
+            ]
+        );
+        assert_eq!(
+            lex("interface Updatable implements"),
+            vec![Token::Interface, Token::Identifier("Updatable".to_string()), Token::Implements]
+        );
+    }
+
+    #[test]
+    fn test_array_tokens() {
+        assert_eq!(
+            lex("for x in xs[0] array<int>"),
+            vec![
+                Token::For,
+                Token::Identifier("x".to_string()),
+                Token::In,
+                Token::Identifier("xs".to_string()),
+                Token::LBracket,
+                Token::Number(0),
+                Token::RBracket,
+                Token::Type("array".to_string()),
+                Token::LessThan,
+                Token::Type("int".to_string()),
+                Token::GreaterThan,
+            ]
+        );
+    }
 }
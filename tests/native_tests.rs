@@ -0,0 +1,124 @@
+use neutron::ast::Node;
+use neutron::bytecode_file;
+use neutron::compiler::compile_with_natives;
+use neutron::interpreter::{Interpreter, RuntimeError, Value};
+use neutron::lexer::Lexer;
+use neutron::native::Natives;
+use neutron::parser::Parser;
+use neutron::semantic::analyze;
+use neutron::symbol_table::{FunctionSignature, SymbolTable, Type};
+use neutron::vm::Vm;
+
+fn parse(input: &str) -> Node {
+    let mut parser = Parser::new(Lexer::new(input));
+    parser.parse_program().unwrap()
+}
+
+/// A host with a few natives, registered through the interpreter's API.
+fn host() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("spawn", |x: f64, y: f64| -> i64 { (x * 10.0 + y) as i64 });
+    interpreter.register_fn("shout", |s: String| s.to_uppercase());
+    interpreter.register_fn("ready", || true);
+    interpreter.register_fn("sqrt", |x: f64| -> Result<f64, String> {
+        if x < 0.0 {
+            Err(format!("cannot take the square root of {}", x))
+        } else {
+            Ok(x.sqrt())
+        }
+    });
+    interpreter
+}
+
+/// Runs `input` on both the interpreter and the VM with the host's natives, asserting they agree.
+fn run_both(input: &str) -> Result<Value, Box<RuntimeError>> {
+    let ast = parse(input);
+    let mut interpreter = host();
+    let interpreted = interpreter.interpret(&ast);
+    let program = compile_with_natives(&ast, &interpreter.natives).unwrap();
+    let executed = Vm::with_natives(&program, &interpreter.natives).run();
+    assert_eq!(format!("{:?}", interpreted), format!("{:?}", executed), "running {}", input);
+    executed.map_err(Box::new)
+}
+
+#[test]
+fn test_natives_are_type_checked() {
+    let natives = host().natives;
+    let spawn = natives.get("spawn").unwrap();
+    assert_eq!(
+        spawn.signature,
+        FunctionSignature { params: vec![Type::Float, Type::Float], return_type: Type::Int, variadic: false }
+    );
+
+    let check = |input: &str| analyze(&parse(input), &mut SymbolTable::with_natives(&natives));
+    assert!(check("func run() int then ret spawn(1.5, 2.0) end").is_ok());
+    assert!(check("func run() then var s string s = shout(\"hi\") end").is_ok());
+
+    let errors = check("func run() int then ret spawn(1, 2.0) end").unwrap_err();
+    assert_eq!(errors[0].code, "E0213");
+    assert_eq!(errors[0].message, "Argument 1 of 'spawn' must be float, got int");
+
+    let errors = check("func run() then var b bool b = ready(1) end").unwrap_err();
+    assert_eq!(errors[0].code, "E0212");
+    assert!(errors[0].labels.is_empty());
+    assert_eq!(errors[0].notes, vec!["'ready' is a native function".to_string()]);
+
+    let errors = check("func run() then var s string s = spawn(1.0, 2.0) end").unwrap_err();
+    assert_eq!(errors[0].code, "E0201");
+
+    // Without the registry the natives are unknown.
+    let errors = analyze(&parse("func run() then ready() end"), &mut SymbolTable::new()).unwrap_err();
+    assert_eq!(errors[0].code, "E0206");
+}
+
+#[test]
+fn test_natives_are_called_at_runtime() {
+    assert_eq!(run_both("func run() int then ret spawn(1.5, 2.0) end"), Ok(Value::Int(17)));
+    assert_eq!(
+        run_both("func run() string then ret shout(\"hi\") end"),
+        Ok(Value::String("HI".to_string()))
+    );
+    assert_eq!(run_both("func run() bool then ret ready() and true end"), Ok(Value::Bool(true)));
+    assert_eq!(run_both("func run() float then ret sqrt(16.0) end"), Ok(Value::Float(4.0)));
+}
+
+#[test]
+fn test_program_functions_shadow_natives() {
+    let input = "func ready() bool then ret false end func run() bool then ret ready() end";
+    assert!(analyze(&parse(input), &mut SymbolTable::with_natives(&host().natives)).is_ok());
+    assert_eq!(run_both(input), Ok(Value::Bool(false)));
+}
+
+#[test]
+fn test_native_failures_are_runtime_errors() {
+    let err = run_both("func run() float then ret sqrt(-1.0) end").unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0308");
+    assert_eq!(
+        err.diagnostic.message,
+        "Native function 'sqrt' failed: cannot take the square root of -1"
+    );
+    assert_eq!(err.stack_trace.len(), 1);
+
+    let err = run_both("func run() int then ret spawn(1, 2.0) end").unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0307");
+    assert_eq!(err.diagnostic.message, "Argument 1 of 'spawn' must be float, got Int(1)");
+
+    let err = run_both("func run() bool then ret ready(1) end").unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0303");
+}
+
+#[test]
+fn test_bytecode_binds_natives_by_name() {
+    let natives = host().natives;
+    let ast = parse("func run() int then ret spawn(0.0, 4.0) end");
+    let program = compile_with_natives(&ast, &natives).unwrap();
+    assert_eq!(program.natives, vec!["spawn".to_string()]);
+
+    let loaded = bytecode_file::read(&bytecode_file::write(&program)).unwrap();
+    assert_eq!(Vm::with_natives(&loaded, &natives).run(), Ok(Value::Int(4)));
+
+    // A host that did not register the native fails when it is called.
+    let err = Vm::with_natives(&loaded, &Natives::new()).run().unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0302");
+    assert_eq!(err.diagnostic.message, "Function 'spawn' not found");
+}
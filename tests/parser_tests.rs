@@ -1,6 +1,7 @@
 use neutron::lexer::Lexer;
 use neutron::parser::Parser;
-use neutron::ast::ASTNode;
+use neutron::ast::{ASTNode, Node};
+use neutron::span::Span;
 use neutron::symbol_table::Type;
 
 #[test]
@@ -8,13 +9,13 @@ fn test_parse_program() {
     let input = "class MyClass then var x int end";
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse_program();
+    let ast = parser.parse_program().unwrap();
 
-    match ast {
+    match ast.kind {
         ASTNode::Program(nodes) => {
             assert_eq!(nodes.len(), 1);
-            match &nodes[0] {
-                ASTNode::ClassDeclaration { name, members } => {
+            match &nodes[0].kind {
+                ASTNode::ClassDeclaration { name, members, .. } => {
                     assert_eq!(name, "MyClass");
                     assert_eq!(members.len(), 1);
                 }
@@ -30,16 +31,17 @@ fn test_parse_class_declaration() {
     let input = "class MyClass then var x int end";
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse_program();
+    let ast = parser.parse_program().unwrap();
 
-    match ast {
+    match ast.kind {
         ASTNode::Program(nodes) => {
             assert_eq!(nodes.len(), 1);
-            match &nodes[0] {
-                ASTNode::ClassDeclaration { name, members } => {
+            match &nodes[0].kind {
+                ASTNode::ClassDeclaration { name, base, members, .. } => {
                     assert_eq!(name, "MyClass");
+                    assert_eq!(base, &None);
                     assert_eq!(members.len(), 1);
-                    match &members[0] {
+                    match &members[0].kind {
                         ASTNode::VariableDeclaration { name, ..  } => {
                             assert_eq!(name, "x");
                         }
@@ -55,22 +57,25 @@ fn test_parse_class_declaration() {
 
 #[test]
 fn test_parse_function_declaration() {
-    let input = "func myFunc(a, b) then var x int end";
+    let input = "func myFunc(a int, b float) bool then var x int end";
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse_program();
+    let ast = parser.parse_program().unwrap();
 
-    match ast {
+    match ast.kind {
         ASTNode::Program(nodes) => {
             assert_eq!(nodes.len(), 1);
-            match &nodes[0] {
-                ASTNode::FunctionDeclaration { name, params, body } => {
+            match &nodes[0].kind {
+                ASTNode::FunctionDeclaration { name, params, return_type, body } => {
                     assert_eq!(name, "myFunc");
                     assert_eq!(params.len(), 2);
-                    assert_eq!(params[0], "a");
-                    assert_eq!(params[1], "b");
+                    assert_eq!(params[0].name, "a");
+                    assert_eq!(params[0].param_type, Type::Int);
+                    assert_eq!(params[1].name, "b");
+                    assert_eq!(params[1].param_type, Type::Float);
+                    assert_eq!(return_type, &Type::Bool);
                     assert_eq!(body.len(), 1);
-                    match &body[0] {
+                    match &body[0].kind {
                         ASTNode::VariableDeclaration { name, .. } => {
                             assert_eq!(name, "x");
                         }
@@ -89,12 +94,12 @@ fn test_parse_variable_declaration() {
     let input = "var x int";
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse_program();
+    let ast = parser.parse_program().unwrap();
 
-    match ast {
+    match ast.kind {
         ASTNode::Program(nodes) => {
             assert_eq!(nodes.len(), 1);
-            match &nodes[0] {
+            match &nodes[0].kind {
                 ASTNode::VariableDeclaration { name, var_type } => {
                     assert_eq!(name, "x");
                     assert_eq!(var_type, &Type::Int);
@@ -111,15 +116,15 @@ fn test_parse_assignment() {
     let input = "x = 5";
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse_program();
+    let ast = parser.parse_program().unwrap();
 
-    match ast {
+    match ast.kind {
         ASTNode::Program(nodes) => {
             assert_eq!(nodes.len(), 1);
-            match &nodes[0] {
+            match &nodes[0].kind {
                 ASTNode::Assignment { variable, expression } => {
                     assert_eq!(variable, "x");
-                    match **expression {
+                    match expression.kind {
                         ASTNode::Number(value) => {
                             assert_eq!(value, 5);
                         }
@@ -138,29 +143,29 @@ fn test_parse_binary_operations() {
     let input = "x = 3 + 4 * 2";
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse_program();
+    let ast = parser.parse_program().unwrap();
 
-    match ast {
+    match ast.kind {
         ASTNode::Program(nodes) => {
             assert_eq!(nodes.len(), 1);
-            match &nodes[0] {
+            match &nodes[0].kind {
                 ASTNode::Assignment { variable, expression } => {
                     assert_eq!(variable, "x");
-                    match **expression {
+                    match expression.kind {
                         ASTNode::Arithmetic { ref operator, ref left, ref right } => {
                             assert_eq!(operator, "+");
-                            match **left {
+                            match left.kind {
                                 ASTNode::Number(value) => assert_eq!(value, 3),
                                 _ => panic!("Expected a Number on the left side"),
                             }
-                            match **right {
+                            match right.kind {
                                 ASTNode::Arithmetic { ref operator, ref left, ref right } => {
                                     assert_eq!(operator, "*");
-                                    match **left {
+                                    match left.kind {
                                         ASTNode::Number(value) => assert_eq!(value, 4),
                                         _ => panic!("Expected a Number on the left side"),
                                     }
-                                    match **right {
+                                    match right.kind {
                                         ASTNode::Number(value) => assert_eq!(value, 2),
                                         _ => panic!("Expected a Number on the right side"),
                                     }
@@ -176,4 +181,381 @@ fn test_parse_binary_operations() {
         }
         _ => panic!("Expected a Program ASTNode"),
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_node_spans() {
+    let input = "var x int\nx = 3 + 4";
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let ast = parser.parse_program().unwrap();
+
+    assert_eq!(ast.span, Span::new(0, 19, 1, 1));
+    match ast.kind {
+        ASTNode::Program(nodes) => {
+            assert_eq!(nodes[0].span, Span::new(0, 9, 1, 1));
+            assert_eq!(nodes[1].span, Span::new(10, 19, 2, 1));
+            match &nodes[1].kind {
+                ASTNode::Assignment { expression, .. } => {
+                    assert_eq!(expression.span, Span::new(14, 19, 2, 5));
+                }
+                _ => panic!("Expected an Assignment"),
+            }
+        }
+        _ => panic!("Expected a Program ASTNode"),
+    }
+}
+
+#[test]
+fn test_unexpected_token_is_reported() {
+    let input = "var x int\nx = = 5";
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let errors = parser.parse_program().unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, "E0100");
+    assert_eq!(errors[0].span, Span::new(14, 15, 2, 5));
+}
+
+#[test]
+fn test_recovers_after_syntax_errors() {
+    let input = "func run() then\n    x = = 5\n    var y int\n    y = (1 +\n    ret y\nend\nfunc other() then ret 1 end";
+    let mut parser = Parser::new(Lexer::new(input));
+    let (ast, errors) = parser.parse_with_recovery();
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].span.line, 2);
+    assert_eq!(errors[1].span.line, 5);
+    match ast.kind {
+        ASTNode::Program(nodes) => {
+            assert_eq!(nodes.len(), 2);
+            match &nodes[0].kind {
+                ASTNode::FunctionDeclaration { body, .. } => {
+                    assert_eq!(body.len(), 4);
+                    assert!(matches!(body[0].kind, ASTNode::Error));
+                    assert!(matches!(body[1].kind, ASTNode::VariableDeclaration { .. }));
+                    assert!(matches!(body[2].kind, ASTNode::Error));
+                    assert!(matches!(body[3].kind, ASTNode::Ret { .. }));
+                }
+                _ => panic!("Expected a FunctionDeclaration"),
+            }
+            assert!(matches!(nodes[1].kind, ASTNode::FunctionDeclaration { .. }));
+        }
+        _ => panic!("Expected a Program ASTNode"),
+    }
+}
+
+#[test]
+fn test_reports_lexer_errors_and_missing_end() {
+    let input = "func run() then\n    var x int\n    x = 1 $ 2\n";
+    let mut parser = Parser::new(Lexer::new(input));
+    let (ast, errors) = parser.parse_with_recovery();
+
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0001", "E0100", "E0100"]);
+    match ast.kind {
+        ASTNode::Program(nodes) => {
+            assert!(matches!(nodes[0].kind, ASTNode::FunctionDeclaration { .. }));
+        }
+        _ => panic!("Expected a Program ASTNode"),
+    }
+}
+
+#[test]
+fn test_invalid_escape_is_the_only_error() {
+    let mut parser = Parser::new(Lexer::new("func run() then\n    var s string\n    s = \"a\\q\" + \"b\"\nend"));
+    let errors = parser.parse_program().unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, "E0004");
+    assert_eq!(errors[0].span, Span::new(43, 45, 3, 11));
+}
+
+#[test]
+fn test_parse_logical_precedence() {
+    let input = "x = a or not b and c != d";
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let ast = parser.parse_program().unwrap();
+
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::Assignment { expression, .. } = &nodes[0].kind else { panic!("Expected an Assignment") };
+    match &expression.kind {
+        ASTNode::Logical { operator, left, right } => {
+            assert_eq!(operator, "or");
+            assert!(matches!(left.kind, ASTNode::Identifier(_)));
+            match &right.kind {
+                ASTNode::Logical { operator, left, right } => {
+                    assert_eq!(operator, "and");
+                    assert!(matches!(&left.kind, ASTNode::Unary { operator, .. } if operator == "not"));
+                    assert!(matches!(&right.kind, ASTNode::Comparison { operator, .. } if operator == "!="));
+                }
+                _ => panic!("Expected 'and' on the right side"),
+            }
+        }
+        _ => panic!("Expected a Logical"),
+    }
+}
+
+#[test]
+fn test_parse_unary_minus() {
+    let input = "x = y-1 * -z";
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let ast = parser.parse_program().unwrap();
+
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::Assignment { expression, .. } = &nodes[0].kind else { panic!("Expected an Assignment") };
+    match &expression.kind {
+        ASTNode::Arithmetic { operator, right, .. } => {
+            assert_eq!(operator, "-");
+            match &right.kind {
+                ASTNode::Arithmetic { operator, right, .. } => {
+                    assert_eq!(operator, "*");
+                    assert!(matches!(&right.kind, ASTNode::Unary { operator, .. } if operator == "-"));
+                }
+                _ => panic!("Expected '*' on the right side"),
+            }
+        }
+        _ => panic!("Expected an Arithmetic"),
+    }
+}
+
+fn parse_expression(input: &str) -> Node {
+    let source = format!("x = {}", input);
+    let mut parser = Parser::new(Lexer::new(&source));
+    let ast = parser.parse_program().unwrap();
+    let ASTNode::Program(mut nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::Assignment { expression, .. } = nodes.remove(0).kind else { panic!("Expected an Assignment") };
+    *expression
+}
+
+/// Renders an expression fully parenthesized so the tree shape is easy to assert on.
+fn shape(node: &Node) -> String {
+    match &node.kind {
+        ASTNode::Number(n) => n.to_string(),
+        ASTNode::Identifier(name) => name.clone(),
+        ASTNode::Arithmetic { left, operator, right }
+        | ASTNode::Comparison { left, operator, right }
+        | ASTNode::Logical { left, operator, right } => {
+            format!("({} {} {})", shape(left), operator, shape(right))
+        }
+        ASTNode::Unary { operator, operand } => format!("({} {})", operator, shape(operand)),
+        ASTNode::FunctionCall { name, args } => {
+            let args: Vec<String> = args.iter().map(shape).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        ASTNode::New { class, args } => {
+            let args: Vec<String> = args.iter().map(shape).collect();
+            format!("{}.new({})", class, args.join(", "))
+        }
+        ASTNode::SelfRef => "self".to_string(),
+        ASTNode::SuperCall { method, args } => {
+            let args: Vec<String> = args.iter().map(shape).collect();
+            format!("super.{}({})", method, args.join(", "))
+        }
+        ASTNode::MemberAccess { object, member } => format!("{}.{}", shape(object), member),
+        ASTNode::MethodCall { object, method, args } => {
+            let args: Vec<String> = args.iter().map(shape).collect();
+            format!("{}.{}({})", shape(object), method, args.join(", "))
+        }
+        ASTNode::ArrayLiteral(elements) => {
+            let elements: Vec<String> = elements.iter().map(shape).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        ASTNode::Index { object, index } => format!("{}[{}]", shape(object), shape(index)),
+        other => panic!("Unexpected node {:?}", other),
+    }
+}
+
+#[test]
+fn test_precedence_table() {
+    let cases = [
+        ("a + b < c + d", "((a + b) < (c + d))"),
+        ("a < b == c > d", "((a < b) == (c > d))"),
+        ("a - b - c", "((a - b) - c)"),
+        ("a / b * c % d", "(((a / b) * c) % d)"),
+        ("-a * b", "((- a) * b)"),
+        ("- -a", "(- (- a))"),
+        ("not a == b and c or d", "(((not (a == b)) and c) or d)"),
+        ("f(a, b + 1) * -g()", "(f(a, (b + 1)) * (- g()))"),
+        ("(a or b) and c", "((a or b) and c)"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(shape(&parse_expression(input)), expected, "parsing {}", input);
+    }
+}
+
+#[test]
+fn test_calling_a_non_function_is_an_error() {
+    let mut parser = Parser::new(Lexer::new("x = f(1)(2)"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].code, "E0103");
+}
+
+#[test]
+fn test_interpolation_embeds_expressions() {
+    let expr = parse_expression("\"{a + b * 2} of {f(c)}\"");
+    let ASTNode::Interpolation(parts) = &expr.kind else { panic!("Expected an Interpolation, got {:?}", expr.kind) };
+    assert_eq!(parts.len(), 3);
+    assert_eq!(shape(&parts[0]), "(a + (b * 2))");
+    assert!(matches!(&parts[1].kind, ASTNode::StringLiteral(text) if text == " of "));
+    assert_eq!(shape(&parts[2]), "f(c)");
+    // Spans of embedded expressions point into the surrounding source.
+    assert_eq!(parts[0].span, Span::new(6, 15, 1, 7));
+}
+
+#[test]
+fn test_invalid_interpolation_is_a_syntax_error() {
+    let mut parser = Parser::new(Lexer::new("x = \"{a b}\""));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Unexpected identifier 'b', expected '}' to end the interpolation");
+    assert_eq!(errors[0].span, Span::new(8, 9, 1, 9));
+}
+
+#[test]
+fn test_parse_constructors_and_class_types() {
+    assert_eq!(shape(&parse_expression("Point.new(a, b + 1) == self")), "(Point.new(a, (b + 1)) == self)");
+    assert_eq!(shape(&parse_expression("Point.new()")), "Point.new()");
+
+    let mut parser = Parser::new(Lexer::new("func f(p Point) Point then var q Point end"));
+    let ast = parser.parse_program().unwrap();
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::FunctionDeclaration { params, return_type, body, .. } = &nodes[0].kind else {
+        panic!("Expected a FunctionDeclaration")
+    };
+    assert_eq!(params[0].param_type, Type::Class("Point".to_string()));
+    assert_eq!(*return_type, Type::Class("Point".to_string()));
+    assert!(matches!(&body[0].kind, ASTNode::VariableDeclaration { var_type: Type::Class(class), .. } if class == "Point"));
+
+}
+
+#[test]
+fn test_parse_member_access() {
+    let cases = [
+        ("a.b.c", "a.b.c"),
+        ("a.b(1).c", "a.b(1).c"),
+        ("-a.b * c.d()", "((- a.b) * c.d())"),
+        ("self.hp + f(x).hp", "(self.hp + f(x).hp)"),
+        ("Point.new(1).x", "Point.new(1).x"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(shape(&parse_expression(input)), expected, "parsing {}", input);
+    }
+
+    let mut parser = Parser::new(Lexer::new("self.target.hp = 1 enemy.hit(2)"));
+    let ast = parser.parse_program().unwrap();
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::FieldAssignment { object, field, .. } = &nodes[0].kind else {
+        panic!("Expected a FieldAssignment, got {:?}", nodes[0].kind)
+    };
+    assert_eq!((shape(object).as_str(), field.as_str()), ("self.target", "hp"));
+    assert!(matches!(&nodes[1].kind, ASTNode::MethodCall { method, .. } if method == "hit"));
+
+    let mut parser = Parser::new(Lexer::new("x = p.1"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Expected field or method name after '.', found number 1");
+    let mut parser = Parser::new(Lexer::new("p.hp + 1 = 2"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Expected a variable, field or array element for assignment");
+}
+
+#[test]
+fn test_parse_inheritance() {
+    let mut parser = Parser::new(Lexer::new("class Goblin extends Enemy then func hit() then super.hit(1) end end"));
+    let ast = parser.parse_program().unwrap();
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::ClassDeclaration { name, base, members, .. } = &nodes[0].kind else {
+        panic!("Expected a ClassDeclaration, got {:?}", nodes[0].kind)
+    };
+    assert_eq!((name.as_str(), base.as_deref()), ("Goblin", Some("Enemy")));
+    let ASTNode::FunctionDeclaration { body, .. } = &members[0].kind else { panic!("Expected a method") };
+    assert_eq!(shape(&body[0]), "super.hit(1)");
+
+    assert_eq!(shape(&parse_expression("super.f(a) + super.g().x")), "(super.f(a) + super.g().x)");
+
+    let mut parser = Parser::new(Lexer::new("x = super.hp"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Unexpected end of file, expected '('");
+    let mut parser = Parser::new(Lexer::new("class A extends then end"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Expected base class name, found 'then'");
+}
+
+#[test]
+fn test_parse_interfaces() {
+    let input = "interface Updatable then
+        func update(dt float)
+        func name() string
+    end
+    class Player extends Actor implements Updatable, Scored then end";
+    let mut parser = Parser::new(Lexer::new(input));
+    let ast = parser.parse_program().unwrap();
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::InterfaceDeclaration { name, methods } = &nodes[0].kind else {
+        panic!("Expected an InterfaceDeclaration, got {:?}", nodes[0].kind)
+    };
+    assert_eq!(name, "Updatable");
+    let signatures: Vec<(&str, usize, &Type)> =
+        methods.iter().map(|m| (m.name.as_str(), m.params.len(), &m.return_type)).collect();
+    assert_eq!(signatures, vec![("update", 1, &Type::Void), ("name", 0, &Type::String)]);
+    assert_eq!(methods[1].span, Span::new(63, 81, 3, 9));
+
+    let ASTNode::ClassDeclaration { base, interfaces, .. } = &nodes[1].kind else {
+        panic!("Expected a ClassDeclaration, got {:?}", nodes[1].kind)
+    };
+    assert_eq!(base.as_deref(), Some("Actor"));
+    assert_eq!(interfaces, &vec!["Updatable".to_string(), "Scored".to_string()]);
+
+    let mut parser = Parser::new(Lexer::new("interface I then func f() then end end"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Unexpected 'then', expected 'func' or 'end'");
+    let mut parser = Parser::new(Lexer::new("class C implements then end"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Expected interface name, found 'then'");
+}
+
+#[test]
+fn test_parse_arrays() {
+    let cases = [
+        ("[1, a + 2, []]", "[1, (a + 2), []]"),
+        ("xs[i + 1] * 2", "(xs[(i + 1)] * 2)"),
+        ("grid[0][j]", "grid[0][j]"),
+        ("-xs[0]", "(- xs[0])"),
+        ("p.items[0].name", "p.items[0].name"),
+        ("[f(x)][0]", "[f(x)][0]"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(shape(&parse_expression(input)), expected, "parsing {}", input);
+    }
+
+    let input = "var grid array<array<int>>
+    grid[0][1] = 5
+    for row in grid
+        println(row)
+    end";
+    let mut parser = Parser::new(Lexer::new(input));
+    let ast = parser.parse_program().unwrap();
+    let ASTNode::Program(nodes) = ast.kind else { panic!("Expected a Program ASTNode") };
+    let ASTNode::VariableDeclaration { var_type, .. } = &nodes[0].kind else {
+        panic!("Expected a VariableDeclaration, got {:?}", nodes[0].kind)
+    };
+    assert_eq!(var_type, &Type::Array(Box::new(Type::Array(Box::new(Type::Int)))));
+    assert_eq!(var_type.to_string(), "array<array<int>>");
+    let ASTNode::IndexAssignment { object, index, expression } = &nodes[1].kind else {
+        panic!("Expected an IndexAssignment, got {:?}", nodes[1].kind)
+    };
+    assert_eq!((shape(object), shape(index), shape(expression)), ("grid[0]".to_string(), "1".to_string(), "5".to_string()));
+    let ASTNode::ForEach { variable, array, body } = &nodes[2].kind else {
+        panic!("Expected a ForEach, got {:?}", nodes[2].kind)
+    };
+    assert_eq!((variable.as_str(), shape(array).as_str(), body.len()), ("row", "grid", 1));
+
+    let mut parser = Parser::new(Lexer::new("x = [1 2]"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].message, "Unexpected number 2, expected ',' or ']'");
+    let mut parser = Parser::new(Lexer::new("var xs array"));
+    let errors = parser.parse_program().unwrap_err();
+    assert_eq!(errors[0].code, "E0100");
+}
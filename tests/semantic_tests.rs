@@ -1,35 +1,268 @@
+use neutron::diagnostic::Diagnostic;
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
 use neutron::semantic::analyze;
 use neutron::symbol_table::SymbolTable;
-use neutron::ast::ASTNode;
+use neutron::ast::{ASTNode, Node};
+use neutron::span::Span;
 use neutron::symbol_table::Type;
 
+fn check(input: &str) -> Result<(), Vec<Diagnostic>> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let ast = parser.parse_program().unwrap();
+    analyze(&ast, &mut SymbolTable::new())
+}
+
 #[test]
 fn test_variable_declaration() {
-    let program = ASTNode::Program(vec![
-        ASTNode::VariableDeclaration { 
+    let program = Node::new(ASTNode::Program(vec![
+        Node::new(ASTNode::VariableDeclaration {
             name: "x".to_string(),
             var_type: Type::Int
-        }
-    ]);
+        }, Span::default())
+    ]), Span::default());
     
     let mut symbol_table = SymbolTable::new();
 
-    analyze(&program, &mut symbol_table);
+    analyze(&program, &mut symbol_table).unwrap();
 
-    assert!(symbol_table.variables.contains_key("x"));
+    assert_eq!(symbol_table.get_variable_type("x"), Some(&Type::Int));
 }
 
 #[test]
-#[should_panic(expected = "Variable 'y' not declared")]
 fn test_undeclared_variable() {
-    let program = ASTNode::Program(vec![
-        ASTNode::Assignment {
+    let program = Node::new(ASTNode::Program(vec![
+        Node::new(ASTNode::Assignment {
             variable: "y".to_string(),
-            expression: Box::new(ASTNode::Number(42)),
-        }
-    ]);
+            expression: Box::new(Node::new(ASTNode::Number(42), Span::new(14, 16, 2, 9))),
+        }, Span::new(10, 16, 2, 5))
+    ]), Span::default());
 
     let mut symbol_table = SymbolTable::new();
 
-    analyze(&program, &mut symbol_table);
+    let errors = analyze(&program, &mut symbol_table).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "Variable 'y' not declared");
+    assert_eq!(errors[0].span, Span::new(10, 16, 2, 5));
+}
+
+#[test]
+fn test_collects_all_errors() {
+    let input = "
+        func run() then
+            var x int
+            var flag bool
+            x = 1.5
+            y = 2
+            if x then
+                x = x + flag
+            end
+            while missing(x) then
+                x = 1
+            end
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0201", "E0200", "E0204", "E0202", "E0206"]);
+}
+
+#[test]
+fn test_functions_can_be_called_before_declaration() {
+    let input = "func run() int then ret helper() end func helper() int then ret 1 end";
+    assert!(check(input).is_ok());
+}
+
+#[test]
+fn test_logical_operators_require_bool() {
+    let input = "
+        func run() then
+            var x int
+            var ok bool
+            ok = x and true
+            ok = not x
+            ok = true < false
+            ok = x % 2 == 0 or x >= 3
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0210", "E0210", "E0211"]);
+}
+
+#[test]
+fn test_unary_operator_types() {
+    let input = "
+        func run() then
+            var x int
+            var f float
+            var ok bool
+            x = -x
+            f = -f
+            ok = not ok
+            ok = -ok
+            x = not x
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0211", "E0210", "E0201"]);
+}
+
+#[test]
+fn test_function_signatures_are_checked() {
+    let input = "
+        func compute(a int, b float) float then
+            ret b * 2.0
+        end
+
+        func log(message string) then
+            ret
+        end
+
+        func run() then
+            var x int
+            var f float
+            f = compute(1, 2.5)
+            log(\"ok\")
+            x = compute(1, 2.5)
+            f = compute(1)
+            f = compute(1.5, 2)
+            x = log(\"ok\")
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0201", "E0212", "E0213", "E0213", "E0201"]);
+    assert_eq!(errors[2].message, "Argument 1 of 'compute' must be int, got float");
+}
+
+#[test]
+fn test_ret_must_match_return_type() {
+    let input = "
+        func half(n int) float then
+            if n > 0 then
+                ret 0.5
+            end
+            ret n
+        end
+
+        func nothing() then
+            ret 1
+        end
+
+        func missing() bool then
+            ret
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0214", "E0214", "E0214"]);
+    assert_eq!(errors[0].message, "Return type mismatch: expected float, got int");
+}
+
+#[test]
+fn test_functions_must_return_on_every_path() {
+    let input = "
+        func empty() int then
+        end
+
+        func sign(n int) int then
+            if n > 0 then
+                ret 1
+            else
+                if n < 0 then
+                    ret -1
+                end
+            end
+        end
+
+        func looping() int then
+            while n() > 0 then
+                ret 1
+            end
+        end
+
+        func n() int then
+            if true then
+                ret 1
+            else
+                ret 0
+            end
+        end
+
+        func forever() int then
+            while true then
+            end
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0232", "E0232", "E0232"]);
+    assert_eq!(errors[0].message, "Function may end without returning int");
+    assert_eq!(errors[0].span.line, 2);
+    assert_eq!(errors[1].span.line, 5);
+    assert_eq!(errors[2].span.line, 15);
+}
+
+#[test]
+fn test_block_scopes() {
+    let input = "
+        class Counter then
+            var count int
+
+            func bump(by int) then
+                count = count + by
+            end
+        end
+
+        func run() then
+            var x int
+            if true then
+                var x float
+                x = 1.5
+                var inner int
+            end
+            x = 2
+            inner = 3
+            for i = 0 3
+                x = x + i
+            end
+            x = i
+            count = 1
+        end
+    ";
+    let errors = check(input).unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Variable 'inner' not declared",
+            "Undefined variable: i",
+            "Variable 'count' not declared",
+        ]
+    );
+}
+
+#[test]
+fn test_redeclaring_a_parameter_is_an_error() {
+    let input = "func f(a int) then var a float end";
+    let errors = check(input).unwrap_err();
+    assert_eq!(errors[0].code, "E0207");
+    assert_eq!(errors[0].labels[0].message, "previously declared here");
+}
+
+#[test]
+fn test_interpolated_expressions_are_checked() {
+    assert!(check("func run() then var hp int var s string s = \"HP: {hp * 2} {hp > 0}\" end").is_ok());
+
+    let input = "func f() then end
+    func run() then
+        var s string
+        s = \"{missing} {f()}\"
+    end";
+    let errors = check(input).unwrap_err();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, vec!["E0200", "E0216"]);
+    assert_eq!(errors[1].span, Span::new(83, 86, 4, 25));
 }
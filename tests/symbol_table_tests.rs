@@ -1,23 +1,91 @@
-use neutron::symbol_table::SymbolTable;
+use neutron::symbol_table::{FunctionSignature, SymbolKind, SymbolTable};
 use neutron::symbol_table::Type;
+use neutron::span::Span;
 
 #[test]
 fn test_declare_variable() {
     let mut symbol_table = SymbolTable::new();
-    symbol_table.declare_variable("x", Type::Int);
+    symbol_table.declare_variable("x", Type::Int, Span::default()).unwrap();
 
-    assert!(symbol_table.variables.contains_key("x"));
-    assert_eq!(symbol_table.variables.get("x").unwrap(), &Type::Int);
+    assert_eq!(symbol_table.get_variable_type("x"), Some(&Type::Int));
 }
 
 #[test]
 fn test_declare_function() {
     let mut symbol_table = SymbolTable::new();
-    symbol_table.declare_function("myFunc", vec!["param1".to_string()]);
+    let signature = FunctionSignature { params: vec![Type::Int], return_type: Type::Bool, variadic: false };
+    symbol_table
+        .declare_function("myFunc", signature.clone(), Span::default())
+        .unwrap();
 
-    assert!(symbol_table.functions.contains_key("myFunc"));
-    assert_eq!(
-        symbol_table.functions.get("myFunc").unwrap(),
-        &vec!["param1".to_string()]
-    );
+    assert_eq!(symbol_table.get_function("myFunc"), Some(&signature));
+}
+
+#[test]
+fn test_redeclare_variable_in_same_scope() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.declare_variable("x", Type::Int, Span::default()).unwrap();
+    let err = symbol_table
+        .declare_variable("x", Type::Float, Span::new(10, 11, 2, 5))
+        .unwrap_err();
+
+    assert_eq!(err.code, "E0207");
+    assert_eq!(err.span, Span::new(10, 11, 2, 5));
+}
+
+#[test]
+fn test_inner_scope_shadows_outer_binding() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.declare_variable("x", Type::Int, Span::default()).unwrap();
+
+    let inner = symbol_table.enter_scope();
+    symbol_table.declare_variable("x", Type::Float, Span::new(10, 11, 2, 5)).unwrap();
+    symbol_table.declare("y", SymbolKind::Parameter(Type::Bool), Span::default()).unwrap();
+    assert_eq!(symbol_table.get_variable_type("x"), Some(&Type::Float));
+    assert_eq!(symbol_table.get_variable_span("x"), Some(Span::new(10, 11, 2, 5)));
+
+    symbol_table.exit_scope();
+    assert_eq!(symbol_table.get_variable_type("x"), Some(&Type::Int));
+    assert_eq!(symbol_table.lookup("y"), None);
+
+    let y = symbol_table.lookup_in(inner, "y").unwrap();
+    assert_eq!(y.kind, SymbolKind::Parameter(Type::Bool));
+}
+
+#[test]
+fn test_functions_are_not_values() {
+    let mut symbol_table = SymbolTable::new();
+    let signature = FunctionSignature { params: vec![], return_type: Type::Void, variadic: false };
+    symbol_table.declare_function("f", signature, Span::default()).unwrap();
+
+    assert_eq!(symbol_table.get_variable_type("f"), None);
+    let err = symbol_table.declare_variable("f", Type::Int, Span::default()).unwrap_err();
+    assert_eq!(err.code, "E0207");
+    assert_eq!(err.labels[0].message, "previously declared here");
+}
+
+#[test]
+fn test_class_members_are_inherited() {
+    let mut symbol_table = SymbolTable::new();
+    let base = symbol_table.enter_scope();
+    symbol_table.declare("hp", SymbolKind::Field(Type::Int), Span::default()).unwrap();
+    symbol_table.exit_scope();
+    let class = symbol_table.enter_scope();
+    symbol_table.declare("hp", SymbolKind::Field(Type::Float), Span::default()).unwrap();
+    symbol_table.declare("rage", SymbolKind::Field(Type::Int), Span::default()).unwrap();
+    symbol_table.exit_scope();
+
+    assert!(symbol_table.set_base(class, base));
+    assert!(!symbol_table.set_base(base, class));
+    assert!(symbol_table.inherits(class, base) && !symbol_table.inherits(base, class));
+    assert_eq!(symbol_table.base_of(class), Some(base));
+
+    symbol_table.declare_variable("x", Type::Int, Span::default()).unwrap();
+    symbol_table.reenter_scope(base);
+    assert_eq!(symbol_table.lookup("rage"), None);
+    symbol_table.reenter_scope(class);
+    // The class's own members come first, then inherited ones, then its parent's.
+    assert_eq!(symbol_table.get_variable_type("hp"), Some(&Type::Float));
+    assert_eq!(symbol_table.get_variable_type("x"), Some(&Type::Int));
+    assert_eq!(symbol_table.lookup_in(class, "x"), None);
 }
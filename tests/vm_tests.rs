@@ -0,0 +1,153 @@
+use neutron::bytecode::Instruction;
+use neutron::compiler::compile;
+use neutron::interpreter::Interpreter;
+use neutron::lexer::Lexer;
+use neutron::parser::Parser;
+use neutron::runtime_error::MAX_CALL_DEPTH;
+use neutron::vm::Vm;
+
+/// Runs `input` through both the interpreter and the VM, asserting they agree.
+/// Returns whether the program ran without a runtime error.
+fn run_both(input: &str) -> bool {
+    let mut parser = Parser::new(Lexer::new(input));
+    let ast = parser.parse_program().unwrap();
+    let interpreted = Interpreter::new().interpret(&ast);
+    let program = compile(&ast).unwrap();
+    let executed = Vm::new(&program).run();
+    assert_eq!(format!("{:?}", interpreted), format!("{:?}", executed), "running {}", input);
+    executed.is_ok()
+}
+
+#[test]
+fn test_vm_matches_interpreter_on_values() {
+    let programs = [
+        "func run() int then var x int x = (1 + 2) * 3 ret x end",
+        "func run() int then
+            var count int
+            count = 0
+            for i = 0 10
+                if i % 3 == 0 and i != 6 or i >= 9 then
+                    count = count + 1
+                end
+            end
+            ret count
+        end",
+        "func fib(n int) int then
+            if n < 2 then
+                ret n
+            end
+            ret fib(n - 1) + fib(n - 2)
+        end
+        func run() int then ret fib(15) end",
+        "var total int
+        func add(n int) then total = total + n end
+        func run() int then
+            total = 0
+            for i = 0 5
+                add(i)
+            end
+            ret total
+        end",
+        "func run() float then
+            var x float
+            var i int
+            x = 1.5
+            i = 0
+            while i < 3 then
+                var step float
+                step = 0.5
+                x = x * 2.0 - step
+                i = i + 1
+            end
+            ret -x
+        end",
+        "func run() bool then
+            var x int
+            if true then
+                var x float
+                x = 2.5
+            end
+            x = 7
+            for i = 0 3
+                i = 100
+                x = x + 1
+            end
+            ret x == 10 and not (x < 0 or false)
+        end",
+        "func early(n int) int then
+            for i = 0 100
+                while true then
+                    if i == n then
+                        ret i * 2
+                    end
+                    i = i + 1
+                end
+            end
+            ret -1
+        end
+        func run() int then ret early(4) end",
+        "var x int x = 1",
+        "func run() string then var hp int hp = 3 ret \"{hp}/{hp * 2} {hp == 3} {\"in {hp}\"}!\" end",
+    ];
+    for program in programs {
+        assert!(run_both(program), "running {}", program);
+    }
+}
+
+#[test]
+fn test_vm_matches_interpreter_on_errors() {
+    let programs = [
+        "func run() int then var x int x = 0 ret 10 / x end",
+        "func run() int then var x int x = 9223372036854775807 ret x + 1 end",
+        "func run() int then var x int x = -9223372036854775807 - 1 ret -x end",
+        "func peek() int then ret secret end
+        func run() int then var secret int secret = 1 ret peek() end",
+        "func inner(a int) int then ret a % 0 end
+        func outer() int then ret inner(1) end
+        func run() int then ret outer() end",
+        "func add(a int, b int) int then ret a + b end func run() int then ret add(1) end",
+        "func run(x int) then end",
+        "func run() then var x int if x then end end",
+        "func run() then var b bool b = false or 1 end",
+        "func run() then for i = 0 1.5 end end",
+    ];
+    for program in programs {
+        assert!(!run_both(program), "running {}", program);
+    }
+}
+
+#[test]
+fn test_locals_are_resolved_to_slots() {
+    let input = "var g int func f(a int) int then var b int b = a ret b + g end";
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = compile(&parser.parse_program().unwrap()).unwrap();
+
+    assert_eq!(program.globals, vec!["g".to_string()]);
+    let f = &program.functions[1];
+    assert_eq!((f.name.as_str(), f.arity, f.locals), ("f", 1, 2));
+    assert!(f.code.contains(&Instruction::GetLocal(0)));
+    assert!(f.code.contains(&Instruction::SetLocal(1)));
+    assert!(f.code.contains(&Instruction::GetGlobal(0)));
+    assert_eq!(f.code.len(), f.spans.len());
+}
+
+#[test]
+fn test_unbounded_recursion_is_a_stack_overflow() {
+    let mut parser = Parser::new(Lexer::new("func f(n int) int then ret f(n + 1) end func run() int then ret f(0) end"));
+    let program = compile(&parser.parse_program().unwrap()).unwrap();
+    let err = Vm::new(&program).run().unwrap_err();
+    assert_eq!(err.diagnostic.code, "E0311");
+    assert_eq!(err.diagnostic.message, "Stack overflow: more than 1000 nested calls");
+    assert_eq!(err.stack_trace.len(), MAX_CALL_DEPTH);
+    assert_eq!(err.stack_trace[0].function, "f");
+    assert_eq!(err.stack_trace.last().unwrap().function, "run");
+}
+
+#[test]
+fn test_statements_that_failed_to_parse_are_not_compiled() {
+    let (ast, errors) = Parser::new(Lexer::new("func run() then x = = 1 end")).parse_with_recovery();
+    assert_eq!(errors.len(), 1);
+    let err = compile(&ast).unwrap_err();
+    assert_eq!(err.code, "E0402");
+    assert_eq!(err.message, "Compilation not yet implemented for: Error");
+}